@@ -0,0 +1,72 @@
+//! Compares the file size and load time of JSON and bincode checkpoints of a
+//! study with 100k trials. Run with `cargo run --release --example checkpoint_formats`.
+
+use anyhow::Result;
+use rustuna::{
+    Distributions, FrozenTrialState, InMemoryStorage, IntUniformDistribution, Storage,
+    StudyDirection, UniformDistribution,
+};
+use std::time::Instant;
+
+const N_TRIALS: usize = 100_000;
+
+fn main() -> Result<()> {
+    let mut storage = InMemoryStorage::new();
+    let study_id = storage.create_study("benchmark", &[StudyDirection::Minimize])?;
+    for i in 0..N_TRIALS {
+        let trial_id = storage.create_new_trial(study_id)?;
+        let x = (i as f64 * 0.618_033_988_749_895).fract();
+        storage.set_trial_param(
+            trial_id,
+            "x",
+            Distributions::Uni(UniformDistribution::new(0.0, 1.0)),
+            x,
+        )?;
+        storage.set_trial_param(
+            trial_id,
+            "n",
+            Distributions::IntUni(IntUniformDistribution::new(0, 100)),
+            (i % 101) as f64,
+        )?;
+        for step in 0..3 {
+            storage.set_trial_intermediate_value(trial_id, step, x / (step + 1) as f64)?;
+        }
+        storage.set_trial_value(trial_id, x * x)?;
+        storage.set_trial_state(trial_id, FrozenTrialState::Completed)?;
+    }
+
+    let dir = std::env::temp_dir();
+    let json_path = dir.join(format!("rustuna-bench-{}.json", std::process::id()));
+    let bincode_path = dir.join(format!("rustuna-bench-{}.bin", std::process::id()));
+    let json_path = json_path.to_str().unwrap();
+    let bincode_path = bincode_path.to_str().unwrap();
+
+    storage.save_json(json_path)?;
+    storage.save_bincode(bincode_path)?;
+    let json_size = std::fs::metadata(json_path)?.len();
+    let bincode_size = std::fs::metadata(bincode_path)?.len();
+
+    let start = Instant::now();
+    let from_json = InMemoryStorage::load_json(json_path)?;
+    let json_load = start.elapsed();
+    let start = Instant::now();
+    let from_bincode = InMemoryStorage::load_bincode(bincode_path)?;
+    let bincode_load = start.elapsed();
+    std::fs::remove_file(json_path)?;
+    std::fs::remove_file(bincode_path)?;
+
+    assert_eq!(from_json.get_all_trials()?.len(), N_TRIALS);
+    assert_eq!(from_bincode.get_all_trials()?, from_json.get_all_trials()?);
+    println!("{} trials", N_TRIALS);
+    println!(
+        "json:    {:>6.1} MB, loaded in {:?}",
+        json_size as f64 / 1e6,
+        json_load
+    );
+    println!(
+        "bincode: {:>6.1} MB, loaded in {:?}",
+        bincode_size as f64 / 1e6,
+        bincode_load
+    );
+    Ok(())
+}
@@ -0,0 +1,616 @@
+use crate::error::RustunaError;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+pub trait Distribution<T> {
+    fn to_internal_repr(&self, external_repr: T) -> f64;
+    fn to_external_repr(&self, internal_repr: f64) -> T;
+    /// Whether the distribution can only take a single value.
+    fn is_single(&self) -> bool;
+    /// Nearest internal value within the bounds, for a sampler that proposed one outside.
+    fn clamp(&self, internal_repr: f64) -> f64;
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IntUniformDistribution {
+    pub low: i64,
+    pub high: i64,
+    #[serde(default = "default_int_step")]
+    pub step: i64,
+    /// Samples uniformly in log space instead of linearly.
+    #[serde(default)]
+    pub log: bool,
+}
+fn default_int_step() -> i64 {
+    1
+}
+impl IntUniformDistribution {
+    pub fn new(low: i64, high: i64) -> Self {
+        IntUniformDistribution {
+            low,
+            high,
+            step: 1,
+            log: false,
+        }
+    }
+
+    pub fn with_step(mut self, step: i64) -> Self {
+        self.step = step;
+        self
+    }
+
+    pub fn with_log(mut self, log: bool) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Largest value of the grid `low, low + step, ...` that does not exceed `high`.
+    pub fn last(&self) -> i64 {
+        self.low + (self.high - self.low) / self.step * self.step
+    }
+
+    /// Rounds `value` to the nearest valid stepped integer within `[low, high]`.
+    pub fn snap(&self, value: f64) -> i64 {
+        let idx = ((value - self.low as f64) / self.step as f64).round() as i64;
+        (self.low + idx * self.step).max(self.low).min(self.last())
+    }
+}
+impl Distribution<i64> for IntUniformDistribution {
+    fn to_internal_repr(&self, external_repr: i64) -> f64 {
+        external_repr as f64
+    }
+
+    fn to_external_repr(&self, internal_repr: f64) -> i64 {
+        self.snap(internal_repr)
+    }
+
+    fn is_single(&self) -> bool {
+        self.low == self.last()
+    }
+
+    fn clamp(&self, internal_repr: f64) -> f64 {
+        internal_repr.max(self.low as f64).min(self.last() as f64)
+    }
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UniformDistribution {
+    pub low: f64,
+    pub high: f64,
+}
+impl UniformDistribution {
+    pub fn new(low: f64, high: f64) -> Self {
+        UniformDistribution { low, high }
+    }
+}
+impl Distribution<f64> for UniformDistribution {
+    fn to_internal_repr(&self, external_repr: f64) -> f64 {
+        external_repr
+    }
+
+    fn to_external_repr(&self, internal_repr: f64) -> f64 {
+        self.clamp(internal_repr)
+    }
+
+    fn is_single(&self) -> bool {
+        self.low == self.high
+    }
+
+    fn clamp(&self, internal_repr: f64) -> f64 {
+        internal_repr.max(self.low).min(self.high)
+    }
+}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LogUniformDistribution {
+    pub low: f64,
+    pub high: f64,
+}
+impl LogUniformDistribution {
+    pub fn new(low: f64, high: f64) -> Self {
+        LogUniformDistribution { low, high }
+    }
+}
+impl Distribution<f64> for LogUniformDistribution {
+    fn to_internal_repr(&self, external_repr: f64) -> f64 {
+        external_repr.ln()
+    }
+
+    fn to_external_repr(&self, internal_repr: f64) -> f64 {
+        // exp(ln(high)) may round to just above high.
+        self.clamp(internal_repr).exp().max(self.low).min(self.high)
+    }
+
+    fn is_single(&self) -> bool {
+        self.low == self.high
+    }
+
+    fn clamp(&self, internal_repr: f64) -> f64 {
+        internal_repr.max(self.low.ln()).min(self.high.ln())
+    }
+}
+
+/// Uniform distribution over the grid `low, low + step, ...` that does not exceed `high`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiscreteUniformDistribution {
+    pub low: f64,
+    pub high: f64,
+    pub step: f64,
+}
+impl DiscreteUniformDistribution {
+    pub fn new(low: f64, high: f64, step: f64) -> Self {
+        DiscreteUniformDistribution { low, high, step }
+    }
+
+    /// Number of grid points within `[low, high]`.
+    pub fn n_steps(&self) -> usize {
+        // The epsilon keeps e.g. (1.0 - 0.0) / 0.1 from flooring to 9.
+        ((self.high - self.low) / self.step + 1e-8).floor() as usize + 1
+    }
+
+    pub fn snap(&self, value: f64) -> f64 {
+        let idx = ((value - self.low) / self.step).round();
+        let idx = idx.max(0.0).min((self.n_steps() - 1) as f64);
+        self.low + idx * self.step
+    }
+}
+impl Distribution<f64> for DiscreteUniformDistribution {
+    fn to_internal_repr(&self, external_repr: f64) -> f64 {
+        self.snap(external_repr)
+    }
+
+    fn to_external_repr(&self, internal_repr: f64) -> f64 {
+        self.snap(internal_repr)
+    }
+
+    fn is_single(&self) -> bool {
+        self.n_steps() == 1
+    }
+
+    fn clamp(&self, internal_repr: f64) -> f64 {
+        self.snap(internal_repr)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CategoricalDistribution {
+    pub choices: Vec<ExternalRepr>,
+    /// Relative probability of each choice; `None` draws them uniformly.
+    #[serde(default)]
+    pub weights: Option<Vec<f64>>,
+}
+impl CategoricalDistribution {
+    pub fn new(choices: Vec<ExternalRepr>) -> Self {
+        CategoricalDistribution {
+            choices,
+            weights: None,
+        }
+    }
+
+    /// Draws each choice with probability proportional to its weight. Fails
+    /// unless there is one finite, non-negative weight per choice and one of
+    /// them is positive.
+    pub fn with_weights(mut self, weights: Vec<f64>) -> Result<Self> {
+        if weights.len() != self.choices.len() {
+            return Err(anyhow!(
+                "{} weights given for {} choices",
+                weights.len(),
+                self.choices.len()
+            ));
+        }
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) || weights.iter().all(|w| *w == 0.0) {
+            return Err(anyhow!(
+                "Weights must be non-negative with a positive one, got {:?}",
+                weights
+            ));
+        }
+        self.weights = Some(weights);
+        Ok(self)
+    }
+
+    /// Index of `value` in `choices`, or an error naming the param `name` and
+    /// listing the allowed choices.
+    pub fn index_of(&self, name: &str, value: &ExternalRepr) -> Result<usize> {
+        self.choices
+            .iter()
+            .position(|choice| choice == value)
+            .ok_or_else(|| {
+                RustunaError::UnknownCategory {
+                    name: name.to_string(),
+                    value: value.clone(),
+                    choices: self.choices.clone(),
+                }
+                .into()
+            })
+    }
+}
+
+impl Distribution<ExternalRepr> for CategoricalDistribution {
+    /// Panics if `external_repr` is not one of the choices; use `index_of` to
+    /// get an error instead.
+    fn to_internal_repr(&self, external_repr: ExternalRepr) -> f64 {
+        match self
+            .choices
+            .iter()
+            .position(|choice| *choice == external_repr)
+        {
+            Some(idx) => idx as f64,
+            None => panic!(
+                "Unknown choice {:?}, expected one of {:?}",
+                external_repr, self.choices
+            ),
+        }
+    }
+
+    fn to_external_repr(&self, internal_repr: f64) -> ExternalRepr {
+        self.choices[self.clamp(internal_repr) as usize].clone()
+    }
+
+    fn is_single(&self) -> bool {
+        self.choices.len() == 1
+    }
+
+    fn clamp(&self, internal_repr: f64) -> f64 {
+        internal_repr.max(0.0).min((self.choices.len() - 1) as f64)
+    }
+}
+
+/// A true/false flag, stored internally as `1.0`/`0.0`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BoolDistribution;
+
+impl Distribution<bool> for BoolDistribution {
+    fn to_internal_repr(&self, external_repr: bool) -> f64 {
+        if external_repr {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn to_external_repr(&self, internal_repr: f64) -> bool {
+        internal_repr >= 0.5
+    }
+
+    fn is_single(&self) -> bool {
+        false
+    }
+
+    fn clamp(&self, internal_repr: f64) -> f64 {
+        internal_repr.clamp(0.0, 1.0)
+    }
+}
+
+/// see https://www.simonewebdesign.it/rust-hashmap-insert-values-multiple-types/
+///
+/// Serialized externally tagged, e.g. `{"IntUni": {"low": 0, "high": 10, ..}}`,
+/// which unlike an internally tagged enum also round-trips through bincode.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Distributions {
+    Uni(UniformDistribution),
+    IntUni(IntUniformDistribution),
+    Categorical(CategoricalDistribution),
+    LogUni(LogUniformDistribution),
+    DiscreteUni(DiscreteUniformDistribution),
+    Bool(BoolDistribution),
+}
+impl Distributions {
+    /// Name of the distribution type, ignoring its bounds and other attributes.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Distributions::Uni(_) => "UniformDistribution",
+            Distributions::IntUni(_) => "IntUniformDistribution",
+            Distributions::Categorical(_) => "CategoricalDistribution",
+            Distributions::LogUni(_) => "LogUniformDistribution",
+            Distributions::DiscreteUni(_) => "DiscreteUniformDistribution",
+            Distributions::Bool(_) => "BoolDistribution",
+        }
+    }
+
+    /// The distribution in the `{"name": .., "attributes": ..}` form Optuna
+    /// writes with `distribution_to_json`. Bools become categorical `[false, true]`,
+    /// whose indices match their internal values.
+    pub(crate) fn to_optuna_json(&self) -> Value {
+        let (name, attributes) = match self {
+            Distributions::Uni(dist) => (
+                "FloatDistribution",
+                json!({"low": dist.low, "high": dist.high, "log": false, "step": null}),
+            ),
+            Distributions::LogUni(dist) => (
+                "FloatDistribution",
+                json!({"low": dist.low, "high": dist.high, "log": true, "step": null}),
+            ),
+            Distributions::DiscreteUni(dist) => (
+                "FloatDistribution",
+                json!({"low": dist.low, "high": dist.high, "log": false, "step": dist.step}),
+            ),
+            Distributions::IntUni(dist) => (
+                "IntDistribution",
+                json!({"low": dist.low, "high": dist.high, "log": dist.log, "step": dist.step}),
+            ),
+            Distributions::Categorical(dist) => (
+                "CategoricalDistribution",
+                json!({"choices": dist.choices.iter().map(ExternalRepr::to_json).collect::<Vec<_>>()}),
+            ),
+            Distributions::Bool(_) => {
+                ("CategoricalDistribution", json!({"choices": [false, true]}))
+            }
+        };
+        json!({"name": name, "attributes": attributes})
+    }
+
+    /// Internal repr of `value` for the param `name`, or an error if its type
+    /// does not fit this distribution or it is not one of the categorical choices.
+    pub(crate) fn internal_repr_of(&self, name: &str, value: &ExternalRepr) -> Result<f64> {
+        match (self, value) {
+            (Distributions::Uni(dist), ExternalRepr::Float(value)) => {
+                Ok(dist.to_internal_repr(*value))
+            }
+            (Distributions::LogUni(dist), ExternalRepr::Float(value)) => {
+                Ok(dist.to_internal_repr(*value))
+            }
+            (Distributions::DiscreteUni(dist), ExternalRepr::Float(value)) => {
+                Ok(dist.to_internal_repr(*value))
+            }
+            (Distributions::IntUni(dist), ExternalRepr::Int(value)) => {
+                Ok(dist.to_internal_repr(*value))
+            }
+            (Distributions::Bool(dist), ExternalRepr::Bool(value)) => {
+                Ok(dist.to_internal_repr(*value))
+            }
+            (Distributions::Categorical(dist), value) => Ok(dist.index_of(name, value)? as f64),
+            _ => Err(anyhow!(
+                "Value {:?} of param {} does not fit {:?}",
+                value,
+                name,
+                self
+            )),
+        }
+    }
+
+    /// Like `internal_repr_of`, but also reads an integral float as an int and
+    /// an int as a float, as values from a config file may not tell them apart.
+    pub(crate) fn coerced_internal_repr_of(&self, name: &str, value: &ExternalRepr) -> Result<f64> {
+        match (self, value) {
+            (Distributions::IntUni(_), ExternalRepr::Float(float)) if float.fract() == 0.0 => {
+                self.internal_repr_of(name, &ExternalRepr::Int(*float as i64))
+            }
+            (
+                Distributions::Uni(_) | Distributions::LogUni(_) | Distributions::DiscreteUni(_),
+                ExternalRepr::Int(int),
+            ) => self.internal_repr_of(name, &ExternalRepr::Float(*int as f64)),
+            _ => self.internal_repr_of(name, value),
+        }
+    }
+
+    /// Like `internal_repr_of`, but also errors if `value` lies outside the
+    /// bounds or off the step grid of this distribution.
+    pub(crate) fn checked_internal_repr_of(&self, name: &str, value: &ExternalRepr) -> Result<f64> {
+        let internal_repr = self.internal_repr_of(name, value)?;
+        let contained = match (self, value) {
+            (Distributions::Uni(dist), ExternalRepr::Float(value)) => {
+                dist.low <= *value && *value <= dist.high
+            }
+            (Distributions::LogUni(dist), ExternalRepr::Float(value)) => {
+                dist.low <= *value && *value <= dist.high
+            }
+            (Distributions::DiscreteUni(dist), ExternalRepr::Float(value)) => {
+                dist.low <= *value
+                    && *value <= dist.high
+                    && (dist.snap(*value) - value).abs() <= 1e-8 * dist.step
+            }
+            (Distributions::IntUni(dist), ExternalRepr::Int(value)) => {
+                dist.low <= *value && *value <= dist.last() && (value - dist.low) % dist.step == 0
+            }
+            _ => true,
+        };
+        if !contained {
+            return Err(anyhow!(
+                "Value {:?} of param {} is out of {:?}",
+                value,
+                name,
+                self
+            ));
+        }
+        Ok(internal_repr)
+    }
+}
+
+/// External value of a param, also used as the choices of a categorical param.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ExternalRepr {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+}
+impl From<i64> for ExternalRepr {
+    fn from(value: i64) -> Self {
+        ExternalRepr::Int(value)
+    }
+}
+impl From<f64> for ExternalRepr {
+    fn from(value: f64) -> Self {
+        ExternalRepr::Float(value)
+    }
+}
+impl From<bool> for ExternalRepr {
+    fn from(value: bool) -> Self {
+        ExternalRepr::Bool(value)
+    }
+}
+impl From<String> for ExternalRepr {
+    fn from(value: String) -> Self {
+        ExternalRepr::Str(value)
+    }
+}
+impl From<&str> for ExternalRepr {
+    fn from(value: &str) -> Self {
+        ExternalRepr::Str(value.to_string())
+    }
+}
+impl ExternalRepr {
+    /// The bare JSON value, without the variant tag serde adds.
+    pub(crate) fn to_json(&self) -> Value {
+        match self {
+            ExternalRepr::Int(value) => json!(value),
+            ExternalRepr::Float(value) => json!(value),
+            ExternalRepr::Str(value) => json!(value),
+            ExternalRepr::Bool(value) => json!(value),
+        }
+    }
+}
+impl fmt::Display for ExternalRepr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExternalRepr::Int(value) => write!(f, "{}", value),
+            ExternalRepr::Float(value) => write!(f, "{}", value),
+            ExternalRepr::Str(value) => write!(f, "{}", value),
+            ExternalRepr::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Compares two sets of internal params. Categorical, int and bool params hold
+/// exact indices/values and must match exactly, while continuous params only
+/// have to agree within `rel_tol`/`abs_tol` since they may have gone through
+/// log or step conversions.
+pub fn params_approx_eq(
+    a: &HashMap<String, f64>,
+    b: &HashMap<String, f64>,
+    distributions: &HashMap<String, Distributions>,
+    rel_tol: f64,
+    abs_tol: f64,
+) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().all(|(name, &x)| {
+        let y = match b.get(name) {
+            Some(&y) => y,
+            None => return false,
+        };
+        match distributions.get(name) {
+            Some(Distributions::Categorical(_))
+            | Some(Distributions::IntUni(_))
+            | Some(Distributions::Bool(_)) => x == y,
+            _ => (x - y).abs() <= f64::max(rel_tol * f64::max(x.abs(), y.abs()), abs_tol),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(values: &[(&str, f64)]) -> HashMap<String, f64> {
+        values
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value))
+            .collect()
+    }
+
+    fn distributions() -> HashMap<String, Distributions> {
+        let mut distributions = HashMap::new();
+        distributions.insert(
+            "x".to_string(),
+            Distributions::Uni(UniformDistribution::new(0.0, 10.0)),
+        );
+        distributions.insert(
+            "n".to_string(),
+            Distributions::IntUni(IntUniformDistribution::new(0, 10)),
+        );
+        distributions.insert(
+            "c".to_string(),
+            Distributions::Categorical(CategoricalDistribution::new(vec![
+                ExternalRepr::Str("a".to_string()),
+                ExternalRepr::Str("b".to_string()),
+            ])),
+        );
+        distributions
+    }
+
+    #[test]
+    fn continuous_params_match_within_tolerance() {
+        let a = params(&[("x", 0.3), ("n", 2.0), ("c", 1.0)]);
+        let b = params(&[("x", 0.3 + 1e-12), ("n", 2.0), ("c", 1.0)]);
+        assert!(params_approx_eq(&a, &b, &distributions(), 1e-9, 1e-12));
+        let b = params(&[("x", 0.3 + 1e-6), ("n", 2.0), ("c", 1.0)]);
+        assert!(!params_approx_eq(&a, &b, &distributions(), 1e-9, 1e-12));
+        assert!(params_approx_eq(&a, &b, &distributions(), 1e-3, 0.0));
+    }
+
+    #[test]
+    fn int_and_categorical_params_match_exactly() {
+        let a = params(&[("n", 2.0), ("c", 1.0)]);
+        let b = params(&[("n", 2.0 + 1e-12), ("c", 1.0)]);
+        assert!(!params_approx_eq(&a, &b, &distributions(), 1e-3, 1e-3));
+        let b = params(&[("n", 2.0), ("c", 1.0 + 1e-12)]);
+        assert!(!params_approx_eq(&a, &b, &distributions(), 1e-3, 1e-3));
+    }
+
+    #[test]
+    fn params_have_to_share_their_names() {
+        let a = params(&[("x", 0.3)]);
+        let b = params(&[("x", 0.3), ("n", 2.0)]);
+        assert!(!params_approx_eq(&a, &b, &distributions(), 1e-9, 1e-12));
+        assert!(!params_approx_eq(&b, &a, &distributions(), 1e-9, 1e-12));
+        let b = params(&[("n", 0.3)]);
+        assert!(!params_approx_eq(&a, &b, &distributions(), 1e-9, 1e-12));
+    }
+
+    #[test]
+    fn linear_distributions_clamp_out_of_range_values() {
+        let uniform = UniformDistribution::new(-1.0, 1.0);
+        assert_eq!(uniform.to_external_repr(-3.0), -1.0);
+        assert_eq!(uniform.to_external_repr(1.5), 1.0);
+        assert_eq!(uniform.to_external_repr(0.5), 0.5);
+
+        let int = IntUniformDistribution::new(0, 9).with_step(2);
+        assert_eq!(int.to_external_repr(-4.0), 0);
+        assert_eq!(int.to_external_repr(12.0), 8);
+        assert_eq!(int.clamp(12.0), 8.0);
+
+        let discrete = DiscreteUniformDistribution::new(0.0, 1.0, 0.25);
+        assert_eq!(discrete.to_external_repr(-0.3), 0.0);
+        assert_eq!(discrete.to_external_repr(2.0), 1.0);
+    }
+
+    #[test]
+    fn log_distributions_clamp_out_of_range_values() {
+        let log_uniform = LogUniformDistribution::new(1e-3, 1e1);
+        let low = log_uniform.to_external_repr(1e-9_f64.ln());
+        assert!((1e-3..=1e1).contains(&low) && (low - 1e-3).abs() < 1e-15);
+        assert_eq!(log_uniform.to_external_repr(1e3_f64.ln()), 1e1);
+        assert_eq!(log_uniform.to_external_repr(f64::INFINITY), 1e1);
+        let inside = log_uniform.to_external_repr(1.0_f64.ln());
+        assert!((inside - 1.0).abs() < 1e-12);
+
+        let int_log = IntUniformDistribution::new(1, 100).with_log(true);
+        assert_eq!(int_log.to_external_repr(0.0), 1);
+        assert_eq!(int_log.to_external_repr(1e6), 100);
+    }
+
+    #[test]
+    fn invalid_weights_and_unknown_choices_are_errors() {
+        let choices = || vec![ExternalRepr::Int(1), ExternalRepr::Int(2)];
+        let dist = CategoricalDistribution::new(choices());
+        assert!(dist.clone().with_weights(vec![1.0]).is_err());
+        assert!(dist.clone().with_weights(vec![1.0, -1.0]).is_err());
+        assert!(dist.clone().with_weights(vec![0.0, 0.0]).is_err());
+        assert!(dist.clone().with_weights(vec![f64::NAN, 1.0]).is_err());
+        let weighted = dist.clone().with_weights(vec![0.0, 2.0]).unwrap();
+        assert_eq!(weighted.weights, Some(vec![0.0, 2.0]));
+
+        assert_eq!(dist.index_of("c", &ExternalRepr::Int(2)).unwrap(), 1);
+        let err = dist.index_of("c", &ExternalRepr::Int(3)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown choice Int(3) for param c, expected one of [Int(1), Int(2)]"
+        );
+        let categorical = Distributions::Categorical(dist);
+        assert!(categorical
+            .checked_internal_repr_of("c", &ExternalRepr::Str("1".to_string()))
+            .is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use crate::distributions::ExternalRepr;
+use std::error::Error;
+use std::fmt;
+
+/// Failures a caller may want to handle. The API returns `anyhow::Result`, so
+/// these are told apart with `err.downcast_ref::<RustunaError>()`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RustunaError {
+    TrialNotFound(usize),
+    TrialAlreadyFinished(usize),
+    StudyNotFound(usize),
+    StudyNameNotFound(String),
+    DuplicateStudyName(String),
+    InvalidBounds {
+        name: String,
+        low: f64,
+        high: f64,
+    },
+    UnknownCategory {
+        name: String,
+        value: ExternalRepr,
+        choices: Vec<ExternalRepr>,
+    },
+}
+
+impl fmt::Display for RustunaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RustunaError::TrialNotFound(trial_id) => write!(f, "Missing trial id: {}", trial_id),
+            RustunaError::TrialAlreadyFinished(_) => write!(f, "Cannot update finished trials"),
+            RustunaError::StudyNotFound(study_id) => write!(f, "Missing study id: {}", study_id),
+            RustunaError::StudyNameNotFound(study_name) => {
+                write!(f, "Missing study name: {}", study_name)
+            }
+            RustunaError::DuplicateStudyName(study_name) => {
+                write!(f, "Study name already exists: {}", study_name)
+            }
+            RustunaError::InvalidBounds { name, low, high } => write!(
+                f,
+                "Invalid bounds for param {}: low={} and high={} must be finite with low <= high",
+                name, low, high
+            ),
+            RustunaError::UnknownCategory {
+                name,
+                value,
+                choices,
+            } => write!(
+                f,
+                "Unknown choice {:?} for param {}, expected one of {:?}",
+                value, name, choices
+            ),
+        }
+    }
+}
+
+impl Error for RustunaError {}
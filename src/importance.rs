@@ -0,0 +1,180 @@
+use crate::trial::FrozenTrial;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+use std::collections::HashMap;
+
+const N_TREES: usize = 64;
+const MAX_DEPTH: usize = 8;
+
+/// One observation: the internal value of each param, `None` when the trial
+/// did not suggest it, and the objective value.
+struct Row {
+    features: Vec<Option<f64>>,
+    target: f64,
+}
+
+/// Importance of each param of `trials` as its mean decrease in impurity over
+/// a random forest regressing the objective value on the internal params.
+/// The importances sum to 1 unless there are no params.
+pub(crate) fn mean_decrease_impurity(
+    trials: &[FrozenTrial],
+    rng: &mut StdRng,
+) -> HashMap<String, f64> {
+    let mut names: Vec<&String> = trials
+        .iter()
+        .flat_map(|trial| trial.internal_params.keys())
+        .collect();
+    names.sort();
+    names.dedup();
+    let rows: Vec<Row> = trials
+        .iter()
+        .map(|trial| Row {
+            features: names
+                .iter()
+                .map(|&name| trial.internal_params.get(name).copied())
+                .collect(),
+            target: trial.value,
+        })
+        .collect();
+
+    let mut decreases = vec![0.0; names.len()];
+    for _ in 0..N_TREES {
+        let sample: Vec<&Row> = (0..rows.len())
+            .map(|_| &rows[rng.gen_range(0..rows.len())])
+            .collect();
+        grow(sample, MAX_DEPTH, rng, &mut decreases);
+    }
+    let total: f64 = decreases.iter().sum();
+    let n_params = names.len() as f64;
+    names
+        .into_iter()
+        .zip(decreases)
+        .map(|(name, decrease)| {
+            // Without any informative split every param is equally unimportant.
+            let importance = if total > 0.0 {
+                decrease / total
+            } else {
+                1.0 / n_params
+            };
+            (name.clone(), importance)
+        })
+        .collect()
+}
+
+/// Grows a regression tree on `rows`, adding the impurity decrease of each
+/// split to the feature it splits on. Only the rows having a feature take part
+/// in choosing and scoring a split on it; the others follow the larger child.
+fn grow(rows: Vec<&Row>, depth: usize, rng: &mut StdRng, decreases: &mut [f64]) {
+    if depth == 0 || rows.len() < 2 {
+        return;
+    }
+    // Each split looks at a random third of the features, as in a random forest.
+    let mut features: Vec<usize> = (0..decreases.len()).collect();
+    features.shuffle(rng);
+    features.truncate(decreases.len().div_ceil(3));
+
+    let mut best: Option<(usize, f64, f64)> = None;
+    for feature in features {
+        if let Some((threshold, decrease)) = best_split(&rows, feature) {
+            if best.is_none_or(|(_, _, best_decrease)| decrease > best_decrease) {
+                best = Some((feature, threshold, decrease));
+            }
+        }
+    }
+    let (feature, threshold, decrease) = match best {
+        Some(best) if best.2 > 0.0 => best,
+        _ => return,
+    };
+    decreases[feature] += decrease;
+
+    let (mut left, mut right, mut missing) = (vec![], vec![], vec![]);
+    for row in rows {
+        match row.features[feature] {
+            Some(x) if x <= threshold => left.push(row),
+            Some(_) => right.push(row),
+            None => missing.push(row),
+        }
+    }
+    if left.len() >= right.len() {
+        left.extend(missing);
+    } else {
+        right.extend(missing);
+    }
+    grow(left, depth - 1, rng, decreases);
+    grow(right, depth - 1, rng, decreases);
+}
+
+/// Threshold on `feature` minimizing the summed squared error of the two
+/// children, with the decrease it achieves over not splitting.
+fn best_split(rows: &[&Row], feature: usize) -> Option<(f64, f64)> {
+    let mut points: Vec<(f64, f64)> = rows
+        .iter()
+        .filter_map(|row| row.features[feature].map(|x| (x, row.target)))
+        .collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let n = points.len() as f64;
+    let total_sum: f64 = points.iter().map(|p| p.1).sum();
+    let total_sq: f64 = points.iter().map(|p| p.1 * p.1).sum();
+    let parent_error = total_sq - total_sum * total_sum / n;
+
+    let mut best: Option<(f64, f64)> = None;
+    let (mut left_sum, mut left_sq) = (0.0, 0.0);
+    for i in 0..points.len().saturating_sub(1) {
+        left_sum += points[i].1;
+        left_sq += points[i].1 * points[i].1;
+        if points[i].0 == points[i + 1].0 {
+            continue;
+        }
+        let n_left = (i + 1) as f64;
+        let n_right = n - n_left;
+        let right_sum = total_sum - left_sum;
+        let right_sq = total_sq - left_sq;
+        let error =
+            (left_sq - left_sum * left_sum / n_left) + (right_sq - right_sum * right_sum / n_right);
+        let decrease = parent_error - error;
+        if best.is_none_or(|(_, best_decrease)| decrease > best_decrease) {
+            best = Some(((points[i].0 + points[i + 1].0) / 2.0, decrease));
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::samplers::RandomSampler;
+    use crate::storages::InMemoryStorage;
+    use crate::study::{create_study, StudyDirection};
+    use crate::trial::Trial;
+    use anyhow::Result;
+
+    fn objective(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", -1.0, 1.0)?;
+        trial.suggest_uniform("noise", -1.0, 1.0)?;
+        Ok(x * x)
+    }
+
+    #[test]
+    fn the_param_driving_the_objective_is_the_most_important() {
+        let mut study = create_study(
+            InMemoryStorage::new(),
+            RandomSampler::new(),
+            StudyDirection::Minimize,
+        );
+        study.set_seed(0);
+        study.optimize(objective, 100).unwrap();
+        let importances = study.get_param_importances().unwrap();
+        assert_eq!(importances.len(), 2);
+        assert!(importances["x"] > 0.7, "{:?}", importances);
+        let total: f64 = importances.values().sum();
+        assert!((total - 1.0).abs() < 1e-12, "{:?}", importances);
+    }
+
+    #[test]
+    fn a_study_without_trials_has_no_importances() {
+        let study = create_study(
+            InMemoryStorage::new(),
+            RandomSampler::new(),
+            StudyDirection::Minimize,
+        );
+        assert!(study.get_param_importances().unwrap().is_empty());
+    }
+}
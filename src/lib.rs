@@ -0,0 +1,31 @@
+mod distributions;
+mod error;
+mod importance;
+mod pruners;
+mod samplers;
+mod storages;
+mod study;
+mod trial;
+
+pub use distributions::{
+    params_approx_eq, BoolDistribution, CategoricalDistribution, DiscreteUniformDistribution,
+    Distribution, Distributions, ExternalRepr, IntUniformDistribution, LogUniformDistribution,
+    UniformDistribution,
+};
+pub use error::RustunaError;
+pub use pruners::{
+    HyperbandPruner, MedianPruner, NopPruner, PatientPruner, PercentilePruner, Pruner,
+    SuccessiveHalvingPruner, ThresholdPruner,
+};
+pub use samplers::{
+    Acquisition, CmaEsSampler, GpSampler, GridSampler, NsgaIISampler, PartialFixedSampler,
+    QmcSampler, QueueSampler, RandomSampler, Sampler, TpeSampler, TracingSampler,
+};
+pub use storages::{InMemoryStorage, SqliteStorage, Storage};
+pub use study::{
+    create_named_study, create_study, dominates, load_study, stop_at_target_value, Callback,
+    MultiObjective, Objective, Study, StudyBuilder, StudyDirection, StudySummary,
+};
+pub use trial::{
+    FrozenTrial, FrozenTrialState, ParetoPoint, ReportPolicy, Trial, TrialRecord, TrialSummary,
+};
@@ -0,0 +1,107 @@
+use super::{Pruner, SuccessiveHalvingPruner};
+use crate::study::Study;
+use crate::trial::FrozenTrial;
+use anyhow::Result;
+
+/// Runs successive halving in several brackets that differ in how early they
+/// start pruning. Trials are spread over the brackets by their number in the
+/// study and only compete with trials of their own bracket.
+pub struct HyperbandPruner {
+    min_resource: usize,
+    max_resource: usize,
+    reduction_factor: usize,
+}
+
+impl HyperbandPruner {
+    /// `max_resource` is the last step a trial reports.
+    pub fn new(max_resource: usize) -> Self {
+        HyperbandPruner {
+            min_resource: 1,
+            max_resource,
+            reduction_factor: 3,
+        }
+    }
+
+    pub fn with_min_resource(mut self, min_resource: usize) -> Self {
+        assert!(min_resource >= 1, "min_resource must be at least 1");
+        self.min_resource = min_resource;
+        self
+    }
+
+    pub fn with_reduction_factor(mut self, reduction_factor: usize) -> Self {
+        assert!(
+            reduction_factor >= 2,
+            "reduction_factor must be at least 2, got {}",
+            reduction_factor
+        );
+        self.reduction_factor = reduction_factor;
+        self
+    }
+
+    fn n_brackets(&self) -> usize {
+        let mut n_brackets = 1;
+        let mut resource = self.min_resource * self.reduction_factor;
+        while resource <= self.max_resource {
+            n_brackets += 1;
+            resource *= self.reduction_factor;
+        }
+        n_brackets
+    }
+
+    /// Bracket of `trial`, from its number so each study fills all brackets.
+    fn bracket(&self, trial: &FrozenTrial) -> usize {
+        trial.number % self.n_brackets()
+    }
+}
+
+impl Pruner for HyperbandPruner {
+    fn prune(&self, study: &Study, trial: &FrozenTrial) -> Result<bool> {
+        let bracket = self.bracket(trial);
+        SuccessiveHalvingPruner::new()
+            .with_min_resource(self.min_resource)
+            .with_reduction_factor(self.reduction_factor)
+            .with_min_early_stopping_rate(bracket as u32)
+            .prune_among(study, trial, |other| self.bracket(other) == bracket)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trial::FrozenTrialState;
+
+    #[test]
+    #[should_panic(expected = "reduction_factor must be at least 2")]
+    fn reduction_factor_of_one_is_rejected() {
+        HyperbandPruner::new(100).with_reduction_factor(1);
+    }
+
+    #[test]
+    #[should_panic(expected = "min_resource must be at least 1")]
+    fn min_resource_of_zero_is_rejected() {
+        HyperbandPruner::new(100).with_min_resource(0);
+    }
+
+    #[test]
+    fn bracket_follows_the_number_not_the_id() {
+        let pruner = HyperbandPruner::new(9);
+        let brackets: Vec<usize> = (0..6)
+            .map(|number| {
+                let mut trial = FrozenTrial::new(10 + 3 * number, FrozenTrialState::Running, 0.0);
+                trial.number = number;
+                pruner.bracket(&trial)
+            })
+            .collect();
+        assert_eq!(brackets, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn n_brackets_follows_the_resource_range() {
+        assert_eq!(HyperbandPruner::new(1).n_brackets(), 1);
+        assert_eq!(HyperbandPruner::new(9).n_brackets(), 3);
+        let pruner = HyperbandPruner::new(100)
+            .with_min_resource(2)
+            .with_reduction_factor(2);
+        assert_eq!(pruner.n_brackets(), 6);
+    }
+}
@@ -0,0 +1,98 @@
+use super::{PercentilePruner, Pruner};
+use crate::study::Study;
+use crate::trial::FrozenTrial;
+use anyhow::Result;
+
+/// Prunes a trial whose latest intermediate value is worse than the median of
+/// the values completed trials reported at the same step.
+pub struct MedianPruner {
+    inner: PercentilePruner,
+}
+
+impl Default for MedianPruner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MedianPruner {
+    pub fn new() -> Self {
+        MedianPruner {
+            inner: PercentilePruner::new(50.0),
+        }
+    }
+
+    /// Number of trials that have to complete before anything is pruned.
+    pub fn with_n_startup_trials(mut self, n_startup_trials: usize) -> Self {
+        self.inner = self.inner.with_n_startup_trials(n_startup_trials);
+        self
+    }
+
+    /// Steps reported before this one never get a trial pruned.
+    pub fn with_n_warmup_steps(mut self, n_warmup_steps: usize) -> Self {
+        self.inner = self.inner.with_n_warmup_steps(n_warmup_steps);
+        self
+    }
+}
+
+impl Pruner for MedianPruner {
+    fn prune(&self, study: &Study, trial: &FrozenTrial) -> Result<bool> {
+        self.inner.prune(study, trial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pruners::testing::{running, study_with};
+    use crate::study::StudyDirection;
+
+    const CURVES: [&[f64]; 3] = [&[1.0, 0.5], &[2.0, 1.0], &[3.0, 1.5]];
+
+    #[test]
+    fn prunes_trials_worse_than_the_median() {
+        let study = study_with(StudyDirection::Minimize, &CURVES);
+        let pruner = MedianPruner::new().with_n_startup_trials(3);
+        assert!(pruner.prune(&study, &running(&study, &[2.5])).unwrap());
+        assert!(!pruner.prune(&study, &running(&study, &[2.0])).unwrap());
+        assert!(!pruner.prune(&study, &running(&study, &[9.0, 0.9])).unwrap());
+        assert!(!pruner.prune(&study, &running(&study, &[])).unwrap());
+    }
+
+    #[test]
+    fn maximize_prunes_trials_below_the_median() {
+        let study = study_with(StudyDirection::Maximize, &CURVES);
+        let pruner = MedianPruner::new().with_n_startup_trials(3);
+        assert!(pruner.prune(&study, &running(&study, &[1.5])).unwrap());
+        assert!(!pruner.prune(&study, &running(&study, &[2.5])).unwrap());
+    }
+
+    #[test]
+    fn nothing_is_pruned_before_the_startup_trials_complete() {
+        let study = study_with(StudyDirection::Minimize, &CURVES);
+        let pruner = MedianPruner::new().with_n_startup_trials(4);
+        assert!(!pruner.prune(&study, &running(&study, &[9.0])).unwrap());
+    }
+
+    #[test]
+    fn nothing_is_pruned_during_the_warmup_steps() {
+        let study = study_with(StudyDirection::Minimize, &CURVES);
+        let pruner = MedianPruner::new()
+            .with_n_startup_trials(0)
+            .with_n_warmup_steps(1);
+        assert!(!pruner.prune(&study, &running(&study, &[9.0])).unwrap());
+        assert!(pruner.prune(&study, &running(&study, &[9.0, 9.0])).unwrap());
+    }
+
+    #[test]
+    fn nan_reports_are_pruned_and_ignored_in_the_median() {
+        let study = study_with(
+            StudyDirection::Minimize,
+            &[&[1.0], &[2.0], &[f64::NAN], &[f64::NAN]],
+        );
+        let pruner = MedianPruner::new().with_n_startup_trials(0);
+        assert!(pruner.prune(&study, &running(&study, &[f64::NAN])).unwrap());
+        assert!(!pruner.prune(&study, &running(&study, &[1.5])).unwrap());
+        assert!(pruner.prune(&study, &running(&study, &[1.6])).unwrap());
+    }
+}
@@ -0,0 +1,69 @@
+use crate::study::Study;
+use crate::trial::FrozenTrial;
+use anyhow::Result;
+
+mod hyperband;
+mod median;
+mod patient;
+mod percentile;
+mod successive_halving;
+mod threshold;
+
+pub use hyperband::HyperbandPruner;
+pub use median::MedianPruner;
+pub use patient::PatientPruner;
+pub use percentile::PercentilePruner;
+pub use successive_halving::SuccessiveHalvingPruner;
+pub use threshold::ThresholdPruner;
+
+/// Decides from the intermediate values reported so far whether a running
+/// trial should be stopped early.
+pub trait Pruner: Send + Sync {
+    fn prune(&self, study: &Study, trial: &FrozenTrial) -> Result<bool>;
+}
+
+/// Pruner that never prunes, used when a study does not set one.
+pub struct NopPruner;
+
+impl Pruner for NopPruner {
+    fn prune(&self, _study: &Study, _trial: &FrozenTrial) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use crate::samplers::RandomSampler;
+    use crate::storages::InMemoryStorage;
+    use crate::study::{create_study, Study, StudyDirection};
+    use crate::trial::FrozenTrial;
+
+    /// Study with one completed trial per curve, each reporting its curve from
+    /// step 0 and finishing with the last value of it.
+    pub(crate) fn study_with(direction: StudyDirection, curves: &[&[f64]]) -> Study {
+        let study = create_study(InMemoryStorage::new(), RandomSampler::new(), direction);
+        for curve in curves {
+            let mut trial = study.ask().unwrap();
+            for (step, &value) in curve.iter().enumerate() {
+                trial.report(value, step).unwrap();
+            }
+            study.tell(trial, *curve.last().unwrap()).unwrap();
+        }
+        study
+    }
+
+    /// A running trial of `study` that reported `values` from step 0, as a
+    /// pruner sees it.
+    pub(crate) fn running(study: &Study, values: &[f64]) -> FrozenTrial {
+        let mut trial = study.ask().unwrap();
+        for (step, &value) in values.iter().enumerate() {
+            trial.report(value, step).unwrap();
+        }
+        study
+            .storage
+            .lock()
+            .unwrap()
+            .get_trial(trial.trial_id())
+            .unwrap()
+    }
+}
@@ -0,0 +1,150 @@
+use super::Pruner;
+use crate::study::{Study, StudyDirection};
+use crate::trial::FrozenTrial;
+use anyhow::Result;
+
+/// Lets the wrapped pruner decide only once a trial has gone `patience`
+/// reports without beating the best value it reported before them, so a
+/// trial whose curve dips for a while is not pruned.
+pub struct PatientPruner<P: Pruner> {
+    inner: P,
+    patience: usize,
+    min_delta: f64,
+}
+
+impl<P: Pruner> PatientPruner<P> {
+    pub fn new(inner: P, patience: usize) -> Self {
+        PatientPruner {
+            inner,
+            patience,
+            min_delta: 0.0,
+        }
+    }
+
+    /// How much a report has to beat the earlier best by to count as an improvement.
+    pub fn with_min_delta(mut self, min_delta: f64) -> Self {
+        assert!(min_delta >= 0.0, "min_delta must be non-negative");
+        self.min_delta = min_delta;
+        self
+    }
+}
+
+impl<P: Pruner> Pruner for PatientPruner<P> {
+    fn prune(&self, study: &Study, trial: &FrozenTrial) -> Result<bool> {
+        let values: Vec<f64> = trial.intermediate_values.values().copied().collect();
+        if values.len() <= self.patience + 1 {
+            return Ok(false);
+        }
+        let (before, recent) = values.split_at(values.len() - self.patience - 1);
+        // NaN reports are skipped; a side with nothing else never counts as stale.
+        let stale = match study.direction {
+            StudyDirection::Minimize => {
+                let best_before = before.iter().copied().fold(f64::NAN, f64::min);
+                let best_recent = recent.iter().copied().fold(f64::NAN, f64::min);
+                best_before + self.min_delta < best_recent
+            }
+            StudyDirection::Maximize => {
+                let best_before = before.iter().copied().fold(f64::NAN, f64::max);
+                let best_recent = recent.iter().copied().fold(f64::NAN, f64::max);
+                best_before - self.min_delta > best_recent
+            }
+        };
+        Ok(stale && self.inner.prune(study, trial)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pruners::testing::{running, study_with};
+    use crate::pruners::NopPruner;
+
+    struct AlwaysPrune;
+
+    impl Pruner for AlwaysPrune {
+        fn prune(&self, _study: &Study, _trial: &FrozenTrial) -> Result<bool> {
+            Ok(true)
+        }
+    }
+
+    fn prunes(direction: StudyDirection, pruner: &impl Pruner, values: &[f64]) -> bool {
+        let study = study_with(direction, &[]);
+        pruner.prune(&study, &running(&study, values)).unwrap()
+    }
+
+    #[test]
+    #[should_panic(expected = "min_delta must be non-negative")]
+    fn negative_min_delta_is_rejected() {
+        PatientPruner::new(NopPruner, 1).with_min_delta(-0.1);
+    }
+
+    #[test]
+    fn waits_for_a_full_patience_window() {
+        let pruner = PatientPruner::new(AlwaysPrune, 2);
+        assert!(!prunes(StudyDirection::Minimize, &pruner, &[1.0, 2.0, 3.0]));
+        assert!(prunes(
+            StudyDirection::Minimize,
+            &pruner,
+            &[1.0, 2.0, 3.0, 4.0]
+        ));
+
+        let pruner = PatientPruner::new(AlwaysPrune, 0);
+        assert!(!prunes(StudyDirection::Minimize, &pruner, &[1.0]));
+        assert!(prunes(StudyDirection::Minimize, &pruner, &[1.0, 2.0]));
+    }
+
+    #[test]
+    fn an_improvement_within_the_window_is_not_stale() {
+        let pruner = PatientPruner::new(AlwaysPrune, 2);
+        assert!(!prunes(
+            StudyDirection::Minimize,
+            &pruner,
+            &[4.0, 5.0, 6.0, 3.0]
+        ));
+        assert!(!prunes(
+            StudyDirection::Maximize,
+            &pruner,
+            &[1.0, 0.0, 0.0, 2.0]
+        ));
+        assert!(prunes(
+            StudyDirection::Maximize,
+            &pruner,
+            &[4.0, 3.0, 2.0, 1.0]
+        ));
+    }
+
+    #[test]
+    fn min_delta_tolerates_small_regressions() {
+        let values = [1.0, 1.05, 1.2, 1.3];
+        let pruner = PatientPruner::new(AlwaysPrune, 2);
+        assert!(prunes(StudyDirection::Minimize, &pruner, &values));
+        let pruner = PatientPruner::new(AlwaysPrune, 2).with_min_delta(0.1);
+        assert!(!prunes(StudyDirection::Minimize, &pruner, &values));
+    }
+
+    #[test]
+    fn stale_trials_are_left_to_the_inner_pruner() {
+        let pruner = PatientPruner::new(NopPruner, 1);
+        assert!(!prunes(StudyDirection::Minimize, &pruner, &[1.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn nan_reports_are_skipped() {
+        let pruner = PatientPruner::new(AlwaysPrune, 2);
+        assert!(prunes(
+            StudyDirection::Minimize,
+            &pruner,
+            &[1.0, f64::NAN, 3.0, 4.0]
+        ));
+        assert!(!prunes(
+            StudyDirection::Minimize,
+            &pruner,
+            &[f64::NAN, 2.0, 3.0, 4.0]
+        ));
+        assert!(!prunes(
+            StudyDirection::Minimize,
+            &pruner,
+            &[1.0, f64::NAN, f64::NAN, f64::NAN]
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+use super::Pruner;
+use crate::study::{Study, StudyDirection};
+use crate::trial::{FrozenTrial, FrozenTrialState};
+use anyhow::Result;
+
+/// Prunes a trial whose latest intermediate value is worse than the given
+/// percentile of the values completed trials reported at the same step.
+pub struct PercentilePruner {
+    percentile: f64,
+    n_startup_trials: usize,
+    n_warmup_steps: usize,
+}
+
+impl PercentilePruner {
+    /// `percentile` is in `[0, 100]`; lower values prune more aggressively.
+    pub fn new(percentile: f64) -> Self {
+        assert!(
+            (0.0..=100.0).contains(&percentile),
+            "percentile must be in [0, 100], got {}",
+            percentile
+        );
+        PercentilePruner {
+            percentile,
+            n_startup_trials: 5,
+            n_warmup_steps: 0,
+        }
+    }
+
+    /// Number of trials that have to complete before anything is pruned.
+    pub fn with_n_startup_trials(mut self, n_startup_trials: usize) -> Self {
+        self.n_startup_trials = n_startup_trials;
+        self
+    }
+
+    /// Steps reported before this one never get a trial pruned.
+    pub fn with_n_warmup_steps(mut self, n_warmup_steps: usize) -> Self {
+        self.n_warmup_steps = n_warmup_steps;
+        self
+    }
+}
+
+/// Linearly interpolated `percentile` of sorted `values`.
+fn percentile_of(values: &[f64], percentile: f64) -> f64 {
+    let rank = percentile / 100.0 * (values.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    values[lower] + (rank - lower as f64) * (values[upper] - values[lower])
+}
+
+impl Pruner for PercentilePruner {
+    fn prune(&self, study: &Study, trial: &FrozenTrial) -> Result<bool> {
+        let (&step, &value) = match trial.intermediate_values.iter().next_back() {
+            Some(last) => last,
+            None => return Ok(false),
+        };
+        if step < self.n_warmup_steps {
+            return Ok(false);
+        }
+        let completed_trials: Vec<FrozenTrial> = study
+            .trials()?
+            .into_iter()
+            .filter(|t| t.state == FrozenTrialState::Completed)
+            .collect();
+        if completed_trials.len() < self.n_startup_trials {
+            return Ok(false);
+        }
+        let mut values_at_step: Vec<f64> = completed_trials
+            .iter()
+            .filter_map(|t| t.intermediate_values.get(&step).copied())
+            .filter(|v| !v.is_nan())
+            .collect();
+        if values_at_step.is_empty() {
+            return Ok(false);
+        }
+        if value.is_nan() {
+            return Ok(true);
+        }
+        values_at_step.sort_by(|a, b| a.total_cmp(b));
+        Ok(match study.direction {
+            StudyDirection::Minimize => value > percentile_of(&values_at_step, self.percentile),
+            StudyDirection::Maximize => {
+                value < percentile_of(&values_at_step, 100.0 - self.percentile)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pruners::testing::{running, study_with};
+
+    const CURVES: [&[f64]; 5] = [&[1.0], &[2.0], &[3.0], &[4.0], &[5.0]];
+
+    #[test]
+    #[should_panic(expected = "percentile must be in [0, 100]")]
+    fn percentile_above_100_is_rejected() {
+        PercentilePruner::new(100.5);
+    }
+
+    #[test]
+    fn percentile_interpolates_between_values() {
+        assert_eq!(percentile_of(&[1.0, 2.0], 25.0), 1.25);
+        assert_eq!(percentile_of(&[1.0, 2.0, 4.0], 75.0), 3.0);
+        assert_eq!(percentile_of(&[1.0, 2.0, 4.0], 100.0), 4.0);
+        assert_eq!(percentile_of(&[7.0], 50.0), 7.0);
+    }
+
+    #[test]
+    fn minimize_prunes_above_the_percentile() {
+        let study = study_with(StudyDirection::Minimize, &CURVES);
+        let pruner = PercentilePruner::new(25.0);
+        assert!(pruner.prune(&study, &running(&study, &[2.5])).unwrap());
+        assert!(!pruner.prune(&study, &running(&study, &[2.0])).unwrap());
+    }
+
+    #[test]
+    fn maximize_prunes_below_the_mirrored_percentile() {
+        let study = study_with(StudyDirection::Maximize, &CURVES);
+        let pruner = PercentilePruner::new(25.0);
+        assert!(pruner.prune(&study, &running(&study, &[3.5])).unwrap());
+        assert!(!pruner.prune(&study, &running(&study, &[4.0])).unwrap());
+    }
+
+    #[test]
+    fn startup_trials_and_warmup_steps_gate_pruning() {
+        let study = study_with(StudyDirection::Minimize, &CURVES);
+        let pruner = PercentilePruner::new(25.0).with_n_startup_trials(6);
+        assert!(!pruner.prune(&study, &running(&study, &[9.0])).unwrap());
+
+        let pruner = PercentilePruner::new(25.0).with_n_warmup_steps(1);
+        assert!(!pruner.prune(&study, &running(&study, &[9.0])).unwrap());
+    }
+
+    #[test]
+    fn steps_no_completed_trial_reported_are_not_pruned() {
+        let study = study_with(StudyDirection::Minimize, &CURVES);
+        let pruner = PercentilePruner::new(25.0);
+        assert!(!pruner.prune(&study, &running(&study, &[0.0, 9.0])).unwrap());
+    }
+
+    #[test]
+    fn nan_reports_are_pruned() {
+        let study = study_with(StudyDirection::Maximize, &CURVES);
+        let pruner = PercentilePruner::new(100.0);
+        assert!(pruner.prune(&study, &running(&study, &[f64::NAN])).unwrap());
+    }
+}
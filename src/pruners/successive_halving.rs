@@ -0,0 +1,167 @@
+use super::Pruner;
+use crate::study::{Study, StudyDirection};
+use crate::trial::FrozenTrial;
+use anyhow::Result;
+
+/// Asynchronous successive halving. Rung `k` is reached at step
+/// `min_resource * reduction_factor^(min_early_stopping_rate + k)`, and a trial
+/// reaching a rung is only kept if its intermediate value there ranks in the
+/// top `1 / reduction_factor` of all trials that reached the same rung.
+pub struct SuccessiveHalvingPruner {
+    min_resource: usize,
+    reduction_factor: usize,
+    min_early_stopping_rate: u32,
+}
+
+impl Default for SuccessiveHalvingPruner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SuccessiveHalvingPruner {
+    pub fn new() -> Self {
+        SuccessiveHalvingPruner {
+            min_resource: 1,
+            reduction_factor: 4,
+            min_early_stopping_rate: 0,
+        }
+    }
+
+    /// Step of the first rung when `min_early_stopping_rate` is zero.
+    pub fn with_min_resource(mut self, min_resource: usize) -> Self {
+        assert!(min_resource >= 1, "min_resource must be at least 1");
+        self.min_resource = min_resource;
+        self
+    }
+
+    pub fn with_reduction_factor(mut self, reduction_factor: usize) -> Self {
+        assert!(
+            reduction_factor >= 2,
+            "reduction_factor must be at least 2, got {}",
+            reduction_factor
+        );
+        self.reduction_factor = reduction_factor;
+        self
+    }
+
+    /// Skips the first rungs, so trials run longer before they can be pruned.
+    pub fn with_min_early_stopping_rate(mut self, min_early_stopping_rate: u32) -> Self {
+        self.min_early_stopping_rate = min_early_stopping_rate;
+        self
+    }
+
+    /// Step at which rung `rung` is reached.
+    fn rung_step(&self, rung: u32) -> usize {
+        self.min_resource
+            * self
+                .reduction_factor
+                .pow(self.min_early_stopping_rate + rung)
+    }
+
+    /// Value `trial` reported on reaching the rung at `rung_step`.
+    fn rung_value(trial: &FrozenTrial, rung_step: usize) -> Option<f64> {
+        trial
+            .intermediate_values
+            .range(rung_step..)
+            .next()
+            .map(|(_, &value)| value)
+    }
+
+    /// Prunes `trial` against the trials accepted by `competes`.
+    pub(crate) fn prune_among(
+        &self,
+        study: &Study,
+        trial: &FrozenTrial,
+        competes: impl Fn(&FrozenTrial) -> bool,
+    ) -> Result<bool> {
+        let (&step, &value) = match trial.intermediate_values.iter().next_back() {
+            Some(last) => last,
+            None => return Ok(false),
+        };
+        // Only the report that first reaches a rung is judged, so a trial
+        // promoted past a rung is never pruned there later on.
+        let mut rung = 0;
+        while self.rung_step(rung + 1) <= step {
+            rung += 1;
+        }
+        let rung_step = self.rung_step(rung);
+        let first_step_in_rung = trial
+            .intermediate_values
+            .range(rung_step..)
+            .next()
+            .map(|(&first, _)| first);
+        if step < rung_step || first_step_in_rung != Some(step) {
+            return Ok(false);
+        }
+        if value.is_nan() {
+            return Ok(true);
+        }
+        let mut competing: Vec<f64> = study
+            .trials()?
+            .iter()
+            .filter(|&other| other.trial_id != trial.trial_id && competes(other))
+            .filter_map(|other| Self::rung_value(other, rung_step))
+            .filter(|other| !other.is_nan())
+            .collect();
+        competing.push(value);
+        competing.sort_by(|a, b| match study.direction {
+            StudyDirection::Minimize => a.total_cmp(b),
+            StudyDirection::Maximize => b.total_cmp(a),
+        });
+        let n_promoted = (competing.len() / self.reduction_factor).max(1);
+        let cutoff = competing[n_promoted - 1];
+        Ok(match study.direction {
+            StudyDirection::Minimize => value > cutoff,
+            StudyDirection::Maximize => value < cutoff,
+        })
+    }
+}
+
+impl Pruner for SuccessiveHalvingPruner {
+    fn prune(&self, study: &Study, trial: &FrozenTrial) -> Result<bool> {
+        self.prune_among(study, trial, |_| true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samplers::RandomSampler;
+    use crate::storages::InMemoryStorage;
+    use crate::study::create_study;
+
+    #[test]
+    #[should_panic(expected = "reduction_factor must be at least 2")]
+    fn reduction_factor_of_one_is_rejected() {
+        SuccessiveHalvingPruner::new().with_reduction_factor(1);
+    }
+
+    #[test]
+    #[should_panic(expected = "reduction_factor must be at least 2")]
+    fn reduction_factor_of_zero_is_rejected() {
+        SuccessiveHalvingPruner::new().with_reduction_factor(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "min_resource must be at least 1")]
+    fn min_resource_of_zero_is_rejected() {
+        SuccessiveHalvingPruner::new().with_min_resource(0);
+    }
+
+    #[test]
+    fn smallest_valid_settings_terminate() {
+        let pruner = SuccessiveHalvingPruner::new()
+            .with_min_resource(1)
+            .with_reduction_factor(2);
+        let study = create_study(
+            InMemoryStorage::new(),
+            RandomSampler::new(),
+            StudyDirection::Minimize,
+        );
+        let mut trial = study.ask().unwrap();
+        trial.report(0.5, 1000).unwrap();
+        let frozen = study.trials().unwrap().pop().unwrap();
+        assert!(!pruner.prune(&study, &frozen).unwrap());
+    }
+}
@@ -0,0 +1,113 @@
+use super::Pruner;
+use crate::study::Study;
+use crate::trial::FrozenTrial;
+use anyhow::Result;
+
+/// Prunes a trial as soon as its latest intermediate value falls outside
+/// `[lower, upper]`, regardless of the other trials.
+pub struct ThresholdPruner {
+    lower: Option<f64>,
+    upper: Option<f64>,
+    n_warmup_steps: usize,
+}
+
+impl Default for ThresholdPruner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ThresholdPruner {
+    pub fn new() -> Self {
+        ThresholdPruner {
+            lower: None,
+            upper: None,
+            n_warmup_steps: 0,
+        }
+    }
+
+    pub fn with_lower(mut self, lower: f64) -> Self {
+        self.lower = Some(lower);
+        self
+    }
+
+    pub fn with_upper(mut self, upper: f64) -> Self {
+        self.upper = Some(upper);
+        self
+    }
+
+    /// Steps reported before this one never get a trial pruned.
+    pub fn with_n_warmup_steps(mut self, n_warmup_steps: usize) -> Self {
+        self.n_warmup_steps = n_warmup_steps;
+        self
+    }
+}
+
+impl Pruner for ThresholdPruner {
+    fn prune(&self, _study: &Study, trial: &FrozenTrial) -> Result<bool> {
+        let (&step, &value) = match trial.intermediate_values.iter().next_back() {
+            Some(last) => last,
+            None => return Ok(false),
+        };
+        if step < self.n_warmup_steps {
+            return Ok(false);
+        }
+        if value.is_nan() {
+            return Ok(true);
+        }
+        Ok(self.lower.is_some_and(|lower| value < lower)
+            || self.upper.is_some_and(|upper| value > upper))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pruners::testing::{running, study_with};
+    use crate::study::StudyDirection;
+
+    #[test]
+    fn prunes_outside_the_bounds() {
+        let study = study_with(StudyDirection::Minimize, &[]);
+        let pruner = ThresholdPruner::new().with_lower(0.0).with_upper(1.0);
+        assert!(pruner.prune(&study, &running(&study, &[-0.1])).unwrap());
+        assert!(pruner.prune(&study, &running(&study, &[1.1])).unwrap());
+        assert!(!pruner.prune(&study, &running(&study, &[0.0])).unwrap());
+        assert!(!pruner.prune(&study, &running(&study, &[1.0])).unwrap());
+        assert!(!pruner.prune(&study, &running(&study, &[5.0, 0.5])).unwrap());
+    }
+
+    #[test]
+    fn a_missing_bound_never_prunes() {
+        let study = study_with(StudyDirection::Maximize, &[]);
+        let pruner = ThresholdPruner::new().with_upper(1.0);
+        assert!(!pruner.prune(&study, &running(&study, &[-1e9])).unwrap());
+        assert!(pruner.prune(&study, &running(&study, &[2.0])).unwrap());
+        assert!(!ThresholdPruner::new()
+            .prune(&study, &running(&study, &[1e9]))
+            .unwrap());
+    }
+
+    #[test]
+    fn nothing_is_pruned_during_the_warmup_steps() {
+        let study = study_with(StudyDirection::Minimize, &[]);
+        let pruner = ThresholdPruner::new()
+            .with_upper(1.0)
+            .with_n_warmup_steps(2);
+        assert!(!pruner.prune(&study, &running(&study, &[5.0, 5.0])).unwrap());
+        assert!(pruner
+            .prune(&study, &running(&study, &[5.0, 5.0, 5.0]))
+            .unwrap());
+        assert!(!pruner
+            .prune(&study, &running(&study, &[f64::NAN, f64::NAN]))
+            .unwrap());
+    }
+
+    #[test]
+    fn nan_reports_are_pruned_even_without_bounds() {
+        let study = study_with(StudyDirection::Minimize, &[]);
+        assert!(ThresholdPruner::new()
+            .prune(&study, &running(&study, &[0.5, f64::NAN]))
+            .unwrap());
+    }
+}
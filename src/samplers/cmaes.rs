@@ -0,0 +1,459 @@
+use super::{RandomSampler, Sampler};
+use crate::distributions::{Distributions, ExternalRepr};
+use crate::study::{Study, StudyDirection};
+use crate::trial::{FrozenTrial, FrozenTrialState};
+use anyhow::Result;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use std::collections::HashMap;
+
+/// CMA-ES sampler for continuous params.
+///
+/// The float params shared by all completed trials are mapped to `[0, 1]` from
+/// their internal representation and sampled jointly, through
+/// `Sampler::sample_relative`, from a multivariate normal whose
+/// mean, step size and covariance are updated after each generation of trials
+/// completes. Other params, and every param of the first `n_startup_trials`,
+/// are sampled at random.
+pub struct CmaEsSampler {
+    rng: StdRng,
+    random_sampler: RandomSampler,
+    n_startup_trials: usize,
+    state: Option<CmaState>,
+    /// Trials sampled from the current generation of `state`.
+    generation: Vec<usize>,
+}
+
+/// Evolution state over the params in `names`, in normalized coordinates.
+struct CmaState {
+    names: Vec<String>,
+    distributions: Vec<Distributions>,
+    mean: Vec<f64>,
+    sigma: f64,
+    cov: Vec<Vec<f64>>,
+    path_sigma: Vec<f64>,
+    path_c: Vec<f64>,
+    n_generations: i32,
+}
+
+impl Default for CmaEsSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CmaEsSampler {
+    pub fn new() -> Self {
+        CmaEsSampler {
+            rng: StdRng::from_entropy(),
+            random_sampler: RandomSampler::new(),
+            n_startup_trials: 1,
+            state: None,
+            generation: vec![],
+        }
+    }
+
+    pub fn with_n_startup_trials(mut self, n_startup_trials: usize) -> Self {
+        self.n_startup_trials = n_startup_trials;
+        self
+    }
+
+    fn completed_trials(study: &Study) -> Result<Vec<FrozenTrial>> {
+        Ok(study
+            .get_trials(Some(FrozenTrialState::Completed))?
+            .into_iter()
+            .filter(|trial| trial.value.is_finite())
+            .collect())
+    }
+}
+
+/// Internal value normalized to `[0, 1]` over the bounds of `distribution`.
+fn normalize(distribution: &Distributions, internal_repr: f64) -> f64 {
+    match distribution {
+        Distributions::Uni(dist) if dist.high > dist.low => {
+            (internal_repr - dist.low) / (dist.high - dist.low)
+        }
+        Distributions::LogUni(dist) if dist.high > dist.low => {
+            (internal_repr - dist.low.ln()) / (dist.high.ln() - dist.low.ln())
+        }
+        _ => 0.5,
+    }
+}
+
+/// Internal value at `u` in `[0, 1]` over the bounds of `distribution`.
+fn denormalize(distribution: &Distributions, u: f64) -> f64 {
+    match distribution {
+        Distributions::Uni(dist) => dist.low + u * (dist.high - dist.low),
+        Distributions::LogUni(dist) => dist.low.ln() + u * (dist.high.ln() - dist.low.ln()),
+        _ => 0.0,
+    }
+}
+
+impl CmaState {
+    fn new(space: Vec<(String, Distributions)>) -> Self {
+        let n = space.len();
+        let (names, distributions) = space.into_iter().unzip();
+        CmaState {
+            names,
+            distributions,
+            mean: vec![0.5; n],
+            sigma: 1.0 / 6.0,
+            cov: identity(n),
+            path_sigma: vec![0.0; n],
+            path_c: vec![0.0; n],
+            n_generations: 0,
+        }
+    }
+
+    fn population_size(&self) -> usize {
+        4 + (3.0 * (self.mean.len() as f64).ln()).floor() as usize
+    }
+
+    /// Moves the distribution towards `points`, sorted from best to worst.
+    fn update(&mut self, points: &[Vec<f64>]) {
+        let n = self.mean.len() as f64;
+        let mu = points.len() / 2;
+        let raw: Vec<f64> = (1..=mu)
+            .map(|i| (mu as f64 + 0.5).ln() - (i as f64).ln())
+            .collect();
+        let total: f64 = raw.iter().sum();
+        let weights: Vec<f64> = raw.iter().map(|w| w / total).collect();
+        let mu_eff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+
+        let c_sigma = (mu_eff + 2.0) / (n + mu_eff + 5.0);
+        let d_sigma = 1.0 + 2.0 * (((mu_eff - 1.0) / (n + 1.0)).sqrt() - 1.0).max(0.0) + c_sigma;
+        let c_c = (4.0 + mu_eff / n) / (n + 4.0 + 2.0 * mu_eff / n);
+        let c_1 = 2.0 / ((n + 1.3).powi(2) + mu_eff);
+        let c_mu =
+            (1.0 - c_1).min(2.0 * (mu_eff - 2.0 + 1.0 / mu_eff) / ((n + 2.0).powi(2) + mu_eff));
+        let chi_n = n.sqrt() * (1.0 - 1.0 / (4.0 * n) + 1.0 / (21.0 * n * n));
+
+        let steps: Vec<Vec<f64>> = points[..mu]
+            .iter()
+            .map(|x| {
+                x.iter()
+                    .zip(&self.mean)
+                    .map(|(x, m)| (x - m) / self.sigma)
+                    .collect()
+            })
+            .collect();
+        let step_w: Vec<f64> = (0..self.mean.len())
+            .map(|d| weights.iter().zip(&steps).map(|(w, y)| w * y[d]).sum())
+            .collect();
+        for (m, y) in self.mean.iter_mut().zip(&step_w) {
+            *m += self.sigma * y;
+        }
+
+        // The inverse Cholesky factor whitens the step in place of C^-1/2.
+        let whitened = solve_lower(&cholesky(&self.cov), &step_w);
+        let scale_sigma = (c_sigma * (2.0 - c_sigma) * mu_eff).sqrt();
+        for (p, z) in self.path_sigma.iter_mut().zip(&whitened) {
+            *p = (1.0 - c_sigma) * *p + scale_sigma * z;
+        }
+        self.n_generations += 1;
+        let norm_sigma = self.path_sigma.iter().map(|p| p * p).sum::<f64>().sqrt();
+        let h_sigma = norm_sigma / (1.0 - (1.0 - c_sigma).powi(2 * self.n_generations)).sqrt()
+            < (1.4 + 2.0 / (n + 1.0)) * chi_n;
+        let h_sigma = if h_sigma { 1.0 } else { 0.0 };
+        let scale_c = (c_c * (2.0 - c_c) * mu_eff).sqrt();
+        for (p, y) in self.path_c.iter_mut().zip(&step_w) {
+            *p = (1.0 - c_c) * *p + h_sigma * scale_c * y;
+        }
+
+        let dims = self.mean.len();
+        for i in 0..dims {
+            for j in 0..dims {
+                let rank_mu: f64 = weights
+                    .iter()
+                    .zip(&steps)
+                    .map(|(w, y)| w * y[i] * y[j])
+                    .sum();
+                self.cov[i][j] = (1.0 - c_1 - c_mu) * self.cov[i][j]
+                    + c_1
+                        * (self.path_c[i] * self.path_c[j]
+                            + (1.0 - h_sigma) * c_c * (2.0 - c_c) * self.cov[i][j])
+                    + c_mu * rank_mu;
+            }
+        }
+        self.sigma *= ((c_sigma / d_sigma) * (norm_sigma / chi_n - 1.0)).exp();
+    }
+
+    /// Draws a point from the current distribution, clipped to `[0, 1]`.
+    fn sample(&self, rng: &mut StdRng) -> Vec<f64> {
+        let lower = cholesky(&self.cov);
+        let z: Vec<f64> = (0..self.mean.len())
+            .map(|_| rng.sample(StandardNormal))
+            .collect();
+        (0..self.mean.len())
+            .map(|i| {
+                let offset: f64 = (0..=i).map(|j| lower[i][j] * z[j]).sum();
+                (self.mean[i] + self.sigma * offset).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+fn identity(n: usize) -> Vec<Vec<f64>> {
+    (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect()
+}
+
+/// Lower triangular `L` with `L * L^T = a`, flooring the pivots so a covariance
+/// that lost positive definiteness to rounding still factors.
+pub(super) fn cholesky(a: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = a.len();
+    let mut lower = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in 0..=i {
+            let dot: f64 = (0..j).map(|k| lower[i][k] * lower[j][k]).sum();
+            if i == j {
+                lower[i][j] = (a[i][i] - dot).max(1e-12).sqrt();
+            } else {
+                lower[i][j] = (a[i][j] - dot) / lower[j][j];
+            }
+        }
+    }
+    lower
+}
+
+/// Solves `lower * x = b` by forward substitution.
+pub(super) fn solve_lower(lower: &[Vec<f64>], b: &[f64]) -> Vec<f64> {
+    let mut x = vec![0.0; b.len()];
+    for i in 0..b.len() {
+        let dot: f64 = (0..i).map(|k| lower[i][k] * x[k]).sum();
+        x[i] = (b[i] - dot) / lower[i][i];
+    }
+    x
+}
+
+impl Sampler for CmaEsSampler {
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.random_sampler.reseed(seed);
+    }
+
+    fn infer_relative_search_space(
+        &self,
+        study: &Study,
+        _trial_id: usize,
+    ) -> Result<HashMap<String, Distributions>> {
+        let completed = Self::completed_trials(study)?;
+        if completed.is_empty() || completed.len() < self.n_startup_trials {
+            return Ok(HashMap::new());
+        }
+        let mut search_space = study.search_space()?;
+        search_space.retain(|_, distribution| {
+            matches!(
+                distribution,
+                Distributions::Uni(_) | Distributions::LogUni(_)
+            )
+        });
+        Ok(search_space)
+    }
+
+    fn sample_relative(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        search_space: &HashMap<String, Distributions>,
+    ) -> Result<HashMap<String, f64>> {
+        if search_space.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut space: Vec<(String, Distributions)> = search_space
+            .iter()
+            .map(|(name, distribution)| (name.clone(), distribution.clone()))
+            .collect();
+        space.sort_by(|a, b| a.0.cmp(&b.0));
+        if self
+            .state
+            .as_ref()
+            .is_none_or(|state| state.names.iter().ne(space.iter().map(|(name, _)| name)))
+        {
+            self.state = Some(CmaState::new(space));
+            self.generation.clear();
+        }
+        let completed = Self::completed_trials(study)?;
+        let mut finished: Vec<&FrozenTrial> = completed
+            .iter()
+            .filter(|trial| self.generation.contains(&trial.trial_id))
+            .collect();
+        let state = self.state.as_mut().unwrap();
+        if finished.len() >= state.population_size() {
+            finished.sort_by(|a, b| match study.direction() {
+                StudyDirection::Minimize => a.value.total_cmp(&b.value),
+                StudyDirection::Maximize => b.value.total_cmp(&a.value),
+            });
+            let points: Vec<Vec<f64>> = finished
+                .iter()
+                .map(|trial| {
+                    state
+                        .names
+                        .iter()
+                        .zip(&state.distributions)
+                        .map(|(name, distribution)| {
+                            normalize(distribution, trial.internal_params[name])
+                        })
+                        .collect()
+                })
+                .collect();
+            state.update(&points);
+            self.generation.clear();
+        }
+
+        let point = state.sample(&mut self.rng);
+        self.generation.push(trial_id);
+        Ok(state
+            .names
+            .iter()
+            .zip(&state.distributions)
+            .zip(point)
+            .map(|((name, distribution), u)| (name.clone(), denormalize(distribution, u)))
+            .collect())
+    }
+
+    fn sample_independent(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<f64> {
+        self.random_sampler
+            .sample_independent(study, trial_id, name, distribution)
+    }
+
+    fn sample_independent_int(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<i64> {
+        self.random_sampler
+            .sample_independent_int(study, trial_id, name, distribution)
+    }
+
+    fn sample_independent_category(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<ExternalRepr> {
+        self.random_sampler
+            .sample_independent_category(study, trial_id, name, distribution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distributions::UniformDistribution;
+    use crate::storages::InMemoryStorage;
+    use crate::study::create_study;
+    use crate::trial::Trial;
+
+    fn uniform() -> Distributions {
+        Distributions::Uni(UniformDistribution::new(-5.0, 5.0))
+    }
+
+    fn sphere(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", -5.0, 5.0)?;
+        let y = trial.suggest_uniform("y", -5.0, 5.0)?;
+        Ok((x - 1.0).powi(2) + (y + 2.0).powi(2))
+    }
+
+    #[test]
+    fn cholesky_factors_a_known_matrix() {
+        let a = vec![
+            vec![4.0, 12.0, -16.0],
+            vec![12.0, 37.0, -43.0],
+            vec![-16.0, -43.0, 98.0],
+        ];
+        let lower = cholesky(&a);
+        let expected = [[2.0, 0.0, 0.0], [6.0, 1.0, 0.0], [-8.0, 5.0, 3.0]];
+        for (row, expected) in lower.iter().zip(expected.iter()) {
+            for (value, expected) in row.iter().zip(expected.iter()) {
+                assert!((value - expected).abs() < 1e-12, "{:?}", lower);
+            }
+        }
+        // L * x = b for x = [1, -2, 3].
+        let x = solve_lower(&lower, &[2.0, 4.0, -9.0]);
+        for (value, expected) in x.iter().zip([1.0, -2.0, 3.0].iter()) {
+            assert!((value - expected).abs() < 1e-12, "{:?}", x);
+        }
+    }
+
+    #[test]
+    fn update_moves_the_mean_towards_the_best_points() {
+        let mut state = CmaState::new(vec![
+            ("x".to_string(), uniform()),
+            ("y".to_string(), uniform()),
+        ]);
+        let points: Vec<Vec<f64>> = (0..state.population_size())
+            .map(|i| vec![0.6 + 0.01 * i as f64, 0.4 - 0.01 * i as f64])
+            .collect();
+        state.update(&points);
+        assert_eq!(state.n_generations, 1);
+        assert!(
+            state.mean[0] > 0.5 && state.mean[0] < 0.65,
+            "{:?}",
+            state.mean
+        );
+        assert!(
+            state.mean[1] < 0.5 && state.mean[1] > 0.35,
+            "{:?}",
+            state.mean
+        );
+        assert!((state.cov[0][1] - state.cov[1][0]).abs() < 1e-12);
+        // The best points moved x up and y down together.
+        assert!(state.cov[0][1] < 0.0, "{:?}", state.cov);
+        assert!(state.sigma > 0.0);
+    }
+
+    #[test]
+    fn state_restarts_when_the_search_space_changes() {
+        let study = create_study(
+            InMemoryStorage::new(),
+            RandomSampler::new(),
+            StudyDirection::Minimize,
+        );
+        let mut sampler = CmaEsSampler::new();
+        sampler.reseed(0);
+        let mut space = HashMap::new();
+        space.insert("x".to_string(), uniform());
+        sampler.sample_relative(&study, 0, &space).unwrap();
+        sampler.sample_relative(&study, 1, &space).unwrap();
+        assert_eq!(sampler.generation, vec![0, 1]);
+
+        space.insert("y".to_string(), uniform());
+        let values = sampler.sample_relative(&study, 2, &space).unwrap();
+        assert_eq!(values.len(), 2);
+        let state = sampler.state.as_ref().unwrap();
+        assert_eq!(state.names, vec!["x".to_string(), "y".to_string()]);
+        assert_eq!(state.n_generations, 0);
+        assert_eq!(sampler.generation, vec![2]);
+    }
+
+    #[test]
+    fn seeded_run_converges_on_a_quadratic() {
+        let mut study = create_study(
+            InMemoryStorage::new(),
+            CmaEsSampler::new(),
+            StudyDirection::Minimize,
+        );
+        study.set_seed(7);
+        study.optimize(sphere, 200).unwrap();
+        assert!(study.best_value().unwrap().unwrap() < 1e-3);
+        // Late generations sample close to the optimum, unlike random sampling.
+        let trials = study.trials().unwrap();
+        let last: Vec<f64> = trials[trials.len() - 20..]
+            .iter()
+            .map(|trial| trial.value)
+            .collect();
+        let mean = last.iter().sum::<f64>() / last.len() as f64;
+        assert!(mean < 1e-2, "{:?}", last);
+    }
+}
@@ -0,0 +1,424 @@
+use super::cmaes::{cholesky, solve_lower};
+use super::tpe::normal_cdf;
+use super::{RandomSampler, Sampler};
+use crate::distributions::{Distributions, ExternalRepr};
+use crate::study::{Study, StudyDirection};
+use crate::trial::FrozenTrialState;
+use anyhow::Result;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use std::collections::HashMap;
+
+const LENGTH_SCALES: [f64; 6] = [0.05, 0.1, 0.2, 0.4, 0.8, 1.6];
+const NOISE: f64 = 1e-4;
+const N_CANDIDATES: usize = 1024;
+
+/// Acquisition function `GpSampler` maximizes to pick the next point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Acquisition {
+    /// Expected improvement over the best value so far.
+    ExpectedImprovement,
+    /// Optimistic bound `beta` standard deviations past the predicted mean.
+    UpperConfidenceBound { beta: f64 },
+}
+
+/// Bayesian optimization sampler for expensive objectives.
+///
+/// The float and int params shared by all completed trials are mapped to
+/// `[0, 1]` and sampled jointly, through `Sampler::sample_relative`, at the
+/// point maximizing the acquisition of a Gaussian process fitted to the
+/// completed trials. Categorical and bool params, and every param of the first
+/// `n_startup_trials`, are sampled at random.
+pub struct GpSampler {
+    rng: StdRng,
+    random_sampler: RandomSampler,
+    n_startup_trials: usize,
+    acquisition: Acquisition,
+}
+
+impl Default for GpSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GpSampler {
+    pub fn new() -> Self {
+        GpSampler {
+            rng: StdRng::from_entropy(),
+            random_sampler: RandomSampler::new(),
+            n_startup_trials: 10,
+            acquisition: Acquisition::ExpectedImprovement,
+        }
+    }
+
+    pub fn with_n_startup_trials(mut self, n_startup_trials: usize) -> Self {
+        self.n_startup_trials = n_startup_trials;
+        self
+    }
+
+    pub fn with_acquisition(mut self, acquisition: Acquisition) -> Self {
+        if let Acquisition::UpperConfidenceBound { beta } = acquisition {
+            assert!(beta >= 0.0, "beta must be non-negative");
+        }
+        self.acquisition = acquisition;
+        self
+    }
+
+    /// Acquisition of a candidate predicted at `mean` with `std`, for a
+    /// minimized objective whose best value so far is `best`.
+    fn score(&self, mean: f64, std: f64, best: f64) -> f64 {
+        match self.acquisition {
+            Acquisition::ExpectedImprovement => {
+                if std <= 0.0 {
+                    return (best - mean).max(0.0);
+                }
+                let z = (best - mean) / std;
+                let pdf = (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt();
+                (best - mean) * normal_cdf(z) + std * pdf
+            }
+            Acquisition::UpperConfidenceBound { beta } => beta * std - mean,
+        }
+    }
+}
+
+/// Matern 5/2 kernel with unit variance.
+fn kernel(a: &[f64], b: &[f64], length_scale: f64) -> f64 {
+    let distance = a
+        .iter()
+        .zip(b)
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f64>()
+        .sqrt();
+    let r = 5f64.sqrt() * distance / length_scale;
+    (1.0 + r + r * r / 3.0) * (-r).exp()
+}
+
+/// Solves `lower^T * x = b` by back substitution.
+fn solve_upper(lower: &[Vec<f64>], b: &[f64]) -> Vec<f64> {
+    let mut x = vec![0.0; b.len()];
+    for i in (0..b.len()).rev() {
+        let dot: f64 = (i + 1..b.len()).map(|k| lower[k][i] * x[k]).sum();
+        x[i] = (b[i] - dot) / lower[i][i];
+    }
+    x
+}
+
+/// Gaussian process regression over points in `[0, 1]^d`.
+struct GaussianProcess {
+    points: Vec<Vec<f64>>,
+    length_scale: f64,
+    lower: Vec<Vec<f64>>,
+    alpha: Vec<f64>,
+}
+
+impl GaussianProcess {
+    fn new(points: Vec<Vec<f64>>, targets: &[f64], length_scale: f64) -> Self {
+        let n = points.len();
+        let gram: Vec<Vec<f64>> = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| {
+                        let noise = if i == j { NOISE } else { 0.0 };
+                        kernel(&points[i], &points[j], length_scale) + noise
+                    })
+                    .collect()
+            })
+            .collect();
+        let lower = cholesky(&gram);
+        let alpha = solve_upper(&lower, &solve_lower(&lower, targets));
+        GaussianProcess {
+            points,
+            length_scale,
+            lower,
+            alpha,
+        }
+    }
+
+    /// Fits the length scale maximizing the marginal likelihood of `targets`.
+    fn fit(points: Vec<Vec<f64>>, targets: &[f64]) -> Self {
+        LENGTH_SCALES
+            .iter()
+            .map(|&length_scale| {
+                let gp = GaussianProcess::new(points.clone(), targets, length_scale);
+                let fit: f64 = targets.iter().zip(&gp.alpha).map(|(y, a)| y * a).sum();
+                let log_det: f64 = (0..gp.lower.len()).map(|i| gp.lower[i][i].ln()).sum();
+                (gp, -0.5 * fit - log_det)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(gp, _)| gp)
+            .unwrap()
+    }
+
+    /// Predicted mean and standard deviation at `x`.
+    fn predict(&self, x: &[f64]) -> (f64, f64) {
+        let k: Vec<f64> = self
+            .points
+            .iter()
+            .map(|point| kernel(point, x, self.length_scale))
+            .collect();
+        let mean = k.iter().zip(&self.alpha).map(|(k, a)| k * a).sum();
+        let v = solve_lower(&self.lower, &k);
+        let variance = 1.0 - v.iter().map(|v| v * v).sum::<f64>();
+        (mean, variance.max(0.0).sqrt())
+    }
+}
+
+/// Bounds of the internal values of `distribution`, in the space the GP is
+/// fitted in, with whether that space is logarithmic.
+fn bounds(distribution: &Distributions) -> (f64, f64, bool) {
+    match distribution {
+        Distributions::Uni(dist) => (dist.low, dist.high, false),
+        Distributions::LogUni(dist) => (dist.low.ln(), dist.high.ln(), false),
+        Distributions::DiscreteUni(dist) => (dist.low, dist.high, false),
+        Distributions::IntUni(dist) if dist.log => {
+            ((dist.low as f64).ln(), (dist.last() as f64).ln(), true)
+        }
+        Distributions::IntUni(dist) => (dist.low as f64, dist.last() as f64, false),
+        _ => (0.0, 0.0, false),
+    }
+}
+
+/// Internal value normalized to `[0, 1]` over the bounds of `distribution`.
+fn normalize(distribution: &Distributions, internal_repr: f64) -> f64 {
+    let (low, high, log) = bounds(distribution);
+    let x = if log {
+        internal_repr.ln()
+    } else {
+        internal_repr
+    };
+    if high > low {
+        (x - low) / (high - low)
+    } else {
+        0.5
+    }
+}
+
+/// Internal value at `u` in `[0, 1]` over the bounds of `distribution`.
+fn denormalize(distribution: &Distributions, u: f64) -> f64 {
+    let (low, high, log) = bounds(distribution);
+    let x = low + u * (high - low);
+    if log {
+        x.exp()
+    } else {
+        x
+    }
+}
+
+impl Sampler for GpSampler {
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.random_sampler.reseed(seed);
+    }
+
+    fn infer_relative_search_space(
+        &self,
+        study: &Study,
+        _trial_id: usize,
+    ) -> Result<HashMap<String, Distributions>> {
+        let n_completed = study
+            .get_trial_summaries(Some(FrozenTrialState::Completed))?
+            .len();
+        if n_completed == 0 || n_completed < self.n_startup_trials {
+            return Ok(HashMap::new());
+        }
+        let mut search_space = study.search_space()?;
+        search_space.retain(|_, distribution| {
+            !matches!(
+                distribution,
+                Distributions::Categorical(_) | Distributions::Bool(_)
+            )
+        });
+        Ok(search_space)
+    }
+
+    fn sample_relative(
+        &mut self,
+        study: &Study,
+        _trial_id: usize,
+        search_space: &HashMap<String, Distributions>,
+    ) -> Result<HashMap<String, f64>> {
+        if search_space.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut space: Vec<(&String, &Distributions)> = search_space.iter().collect();
+        space.sort_by(|a, b| a.0.cmp(b.0));
+        let mut points = vec![];
+        let mut targets = vec![];
+        for trial in study.get_trials(Some(FrozenTrialState::Completed))? {
+            if !trial.value.is_finite() {
+                continue;
+            }
+            points.push(
+                space
+                    .iter()
+                    .map(|(name, distribution)| {
+                        normalize(distribution, trial.internal_params[*name])
+                    })
+                    .collect::<Vec<f64>>(),
+            );
+            targets.push(match study.direction() {
+                StudyDirection::Minimize => trial.value,
+                StudyDirection::Maximize => -trial.value,
+            });
+        }
+        if points.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        // Standardized so the unit variance kernel fits any scale of values.
+        let n = targets.len() as f64;
+        let mean = targets.iter().sum::<f64>() / n;
+        let std = (targets.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / n).sqrt();
+        let std = if std > 0.0 { std } else { 1.0 };
+        let targets: Vec<f64> = targets.iter().map(|y| (y - mean) / std).collect();
+        let best_idx = (0..targets.len())
+            .min_by(|&a, &b| targets[a].total_cmp(&targets[b]))
+            .unwrap();
+        let best = targets[best_idx];
+        let incumbent = points[best_idx].clone();
+        let gp = GaussianProcess::fit(points, &targets);
+
+        // Half the candidates explore the whole space, half refine the best trial.
+        let mut best_candidate = incumbent.clone();
+        let mut best_score = f64::NEG_INFINITY;
+        for i in 0..N_CANDIDATES {
+            let candidate: Vec<f64> = if i % 2 == 0 {
+                (0..space.len()).map(|_| self.rng.gen::<f64>()).collect()
+            } else {
+                incumbent
+                    .iter()
+                    .map(|x| {
+                        let step: f64 = self.rng.sample(StandardNormal);
+                        (x + 0.05 * step).clamp(0.0, 1.0)
+                    })
+                    .collect()
+            };
+            let (mean, std) = gp.predict(&candidate);
+            let score = self.score(mean, std, best);
+            if score > best_score {
+                best_score = score;
+                best_candidate = candidate;
+            }
+        }
+        Ok(space
+            .iter()
+            .zip(best_candidate)
+            .map(|((name, distribution), u)| ((*name).clone(), denormalize(distribution, u)))
+            .collect())
+    }
+
+    fn sample_independent(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<f64> {
+        self.random_sampler
+            .sample_independent(study, trial_id, name, distribution)
+    }
+
+    fn sample_independent_int(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<i64> {
+        self.random_sampler
+            .sample_independent_int(study, trial_id, name, distribution)
+    }
+
+    fn sample_independent_category(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<ExternalRepr> {
+        self.random_sampler
+            .sample_independent_category(study, trial_id, name, distribution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storages::InMemoryStorage;
+    use crate::study::create_study;
+    use crate::trial::Trial;
+
+    fn quadratic(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", -5.0, 5.0)?;
+        let n = trial.suggest_int("n", -5, 5)?;
+        Ok((x - 1.5).powi(2) + (n as f64 + 2.0).powi(2))
+    }
+
+    fn best_value(sampler: impl Sampler + 'static, seed: u64) -> f64 {
+        let mut study = create_study(InMemoryStorage::new(), sampler, StudyDirection::Minimize);
+        study.set_seed(seed);
+        study.optimize(quadratic, 25).unwrap();
+        study.best_value().unwrap().unwrap()
+    }
+
+    #[test]
+    fn gaussian_process_interpolates_its_training_points() {
+        let points = vec![
+            vec![0.1, 0.2],
+            vec![0.5, 0.9],
+            vec![0.8, 0.4],
+            vec![0.3, 0.6],
+        ];
+        let targets = [1.0, -0.5, 0.25, 2.0];
+        let gp = GaussianProcess::fit(points.clone(), &targets);
+        for (point, target) in points.iter().zip(targets.iter()) {
+            let (mean, std) = gp.predict(point);
+            assert!((mean - target).abs() < 1e-2, "{} vs {}", mean, target);
+            assert!(std < 0.05, "{}", std);
+        }
+        let (_, far_std) = gp.predict(&[1.0, 0.0]);
+        assert!(far_std > 0.1, "{}", far_std);
+    }
+
+    #[test]
+    fn acquisitions_prefer_low_means_and_uncertainty() {
+        let ei = GpSampler::new();
+        assert!(ei.score(0.0, 0.1, 1.0) > ei.score(0.5, 0.1, 1.0));
+        assert!(ei.score(1.0, 0.5, 1.0) > ei.score(1.0, 0.1, 1.0));
+        assert_eq!(ei.score(0.25, 0.0, 1.0), 0.75);
+        assert_eq!(ei.score(2.0, 0.0, 1.0), 0.0);
+
+        let ucb =
+            GpSampler::new().with_acquisition(Acquisition::UpperConfidenceBound { beta: 2.0 });
+        assert_eq!(ucb.score(1.0, 0.5, 0.0), 0.0);
+        assert!(ucb.score(0.0, 0.5, 0.0) > ucb.score(0.0, 0.1, 0.0));
+    }
+
+    #[test]
+    fn sampled_values_stay_within_bounds() {
+        let mut study = create_study(
+            InMemoryStorage::new(),
+            GpSampler::new().with_n_startup_trials(5),
+            StudyDirection::Minimize,
+        );
+        study.set_seed(1);
+        study.optimize(quadratic, 15).unwrap();
+        for trial in study.trials().unwrap() {
+            let x = trial.internal_params["x"];
+            let n = trial.internal_params["n"];
+            assert!((-5.0..=5.0).contains(&x) && (-5.0..=5.0).contains(&n));
+            assert_eq!(n.fract(), 0.0);
+        }
+    }
+
+    #[test]
+    fn beats_random_sampling_on_a_seeded_quadratic() {
+        let (mut gp, mut random) = (0.0, 0.0);
+        for seed in 0..4 {
+            gp += best_value(GpSampler::new().with_n_startup_trials(5), seed);
+            random += best_value(RandomSampler::new(), seed);
+        }
+        assert!(gp < random, "gp={} random={}", gp, random);
+    }
+}
@@ -0,0 +1,196 @@
+use super::{RandomSampler, Sampler};
+use crate::distributions::{Distributions, ExternalRepr};
+use crate::study::Study;
+use anyhow::Result;
+use log::warn;
+use std::collections::HashMap;
+
+/// Runs every combination of the given param values exactly once, in order.
+/// Params outside the grid are sampled at random. The combination is picked
+/// when a trial is asked for.
+pub struct GridSampler {
+    search_space: HashMap<String, Vec<ExternalRepr>>,
+    /// Param names in the order they vary within the grid, last name fastest.
+    names: Vec<String>,
+    n_combinations: usize,
+    next_combination: usize,
+    /// Combination assigned to each trial that got one, by trial id.
+    assigned: HashMap<usize, usize>,
+    random_sampler: RandomSampler,
+}
+
+impl GridSampler {
+    pub fn new(search_space: HashMap<String, Vec<ExternalRepr>>) -> Self {
+        let mut names: Vec<String> = search_space.keys().cloned().collect();
+        names.sort();
+        let n_combinations = search_space.values().map(Vec::len).product();
+        GridSampler {
+            search_space,
+            names,
+            n_combinations,
+            next_combination: 0,
+            assigned: HashMap::new(),
+            random_sampler: RandomSampler::new(),
+        }
+    }
+
+    /// Value of `name` in the combination assigned to `trial_id`.
+    fn grid_value(&self, trial_id: usize, name: &str) -> Option<&ExternalRepr> {
+        let mut idx = *self.assigned.get(&trial_id)?;
+        for grid_name in self.names.iter().rev() {
+            let values = &self.search_space[grid_name];
+            if grid_name == name {
+                return values.get(idx % values.len());
+            }
+            idx /= values.len();
+        }
+        None
+    }
+}
+
+impl Sampler for GridSampler {
+    fn reseed(&mut self, seed: u64) {
+        self.random_sampler.reseed(seed);
+    }
+
+    fn before_trial(&mut self, _study: &Study, trial_id: usize) -> Result<()> {
+        if self.next_combination < self.n_combinations {
+            self.assigned.insert(trial_id, self.next_combination);
+            self.next_combination += 1;
+        }
+        Ok(())
+    }
+
+    fn is_exhausted(&self, _study: &Study) -> bool {
+        self.next_combination >= self.n_combinations
+    }
+
+    fn sample_independent(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<f64> {
+        match self.grid_value(trial_id, name) {
+            Some(ExternalRepr::Float(value)) => Ok(*value),
+            Some(ExternalRepr::Int(value)) => Ok(*value as f64),
+            Some(ExternalRepr::Bool(value)) => Ok(*value as u8 as f64),
+            other => {
+                if other.is_some() {
+                    warn!(
+                        "Grid value of param {} is not a float, sampling it instead",
+                        name
+                    );
+                }
+                self.random_sampler
+                    .sample_independent(study, trial_id, name, distribution)
+            }
+        }
+    }
+
+    fn sample_independent_int(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<i64> {
+        match self.grid_value(trial_id, name) {
+            Some(ExternalRepr::Int(value)) => Ok(*value),
+            other => {
+                if other.is_some() {
+                    warn!(
+                        "Grid value of param {} is not an int, sampling it instead",
+                        name
+                    );
+                }
+                self.random_sampler
+                    .sample_independent_int(study, trial_id, name, distribution)
+            }
+        }
+    }
+
+    fn sample_independent_category(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<ExternalRepr> {
+        let value = self.grid_value(trial_id, name);
+        match (value, &distribution) {
+            (Some(value), Distributions::Categorical(dist)) if dist.choices.contains(value) => {
+                Ok(value.clone())
+            }
+            _ => {
+                if value.is_some() {
+                    warn!(
+                        "Grid value of param {} is not one of its choices, sampling it instead",
+                        name
+                    );
+                }
+                self.random_sampler
+                    .sample_independent_category(study, trial_id, name, distribution)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storages::InMemoryStorage;
+    use crate::study::{create_study, StudyDirection};
+    use crate::trial::{FrozenTrialState, Trial};
+    use std::collections::BTreeSet;
+
+    fn grid() -> GridSampler {
+        let mut search_space = HashMap::new();
+        search_space.insert(
+            "x".to_string(),
+            (0..3).map(ExternalRepr::Int).collect::<Vec<_>>(),
+        );
+        search_space.insert(
+            "y".to_string(),
+            (0..2).map(ExternalRepr::Int).collect::<Vec<_>>(),
+        );
+        GridSampler::new(search_space)
+    }
+
+    fn suggest(trial: &mut Trial) -> Result<(i64, i64)> {
+        Ok((trial.suggest_int("x", 0, 2)?, trial.suggest_int("y", 0, 1)?))
+    }
+
+    #[test]
+    fn trials_asked_together_get_their_own_combinations() {
+        let study = create_study(InMemoryStorage::new(), grid(), StudyDirection::Minimize);
+        let mut first = study.ask().unwrap();
+        let mut second = study.ask().unwrap();
+        // The later trial suggests first, which used to hand both the same combination.
+        assert_eq!(suggest(&mut second).unwrap(), (0, 1));
+        assert_eq!(suggest(&mut first).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn parallel_optimize_runs_each_combination_once() {
+        let study = create_study(InMemoryStorage::new(), grid(), StudyDirection::Minimize);
+        study
+            .optimize_parallel(|trial| suggest(trial).map(|(x, y)| (x + y) as f64), 20, 3)
+            .unwrap();
+        let combinations: BTreeSet<(i64, i64)> = study
+            .get_trials(Some(FrozenTrialState::Completed))
+            .unwrap()
+            .iter()
+            .map(|trial| {
+                let params = trial.params().unwrap();
+                match (&params["x"], &params["y"]) {
+                    (ExternalRepr::Int(x), ExternalRepr::Int(y)) => (*x, *y),
+                    other => panic!("unexpected params {:?}", other),
+                }
+            })
+            .collect();
+        assert_eq!(study.n_trials().unwrap(), 6);
+        assert_eq!(combinations.len(), 6);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::distributions::{Distributions, ExternalRepr};
+use crate::study::Study;
+use anyhow::Result;
+use std::collections::HashMap;
+
+mod cmaes;
+mod gp;
+mod grid;
+mod nsga2;
+mod partial_fixed;
+mod qmc;
+mod queue;
+mod random;
+mod tpe;
+mod tracing;
+
+pub use cmaes::CmaEsSampler;
+pub use gp::{Acquisition, GpSampler};
+pub use grid::GridSampler;
+pub use nsga2::NsgaIISampler;
+pub use partial_fixed::PartialFixedSampler;
+pub use qmc::QmcSampler;
+pub use queue::QueueSampler;
+pub use random::RandomSampler;
+pub use tpe::TpeSampler;
+pub use tracing::TracingSampler;
+
+pub trait Sampler: Send {
+    fn reseed(&mut self, seed: u64);
+
+    /// Called by `Study::ask` right after a new trial is created.
+    fn before_trial(&mut self, _study: &Study, _trial_id: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether the sampler has nothing left to propose, which stops `optimize`.
+    fn is_exhausted(&self, _study: &Study) -> bool {
+        false
+    }
+
+    /// Params of the trial to sample jointly with `sample_relative`, e.g. those
+    /// set with the same distribution by every completed trial.
+    fn infer_relative_search_space(
+        &self,
+        _study: &Study,
+        _trial_id: usize,
+    ) -> Result<HashMap<String, Distributions>> {
+        Ok(HashMap::new())
+    }
+
+    /// Internal values for the params of `search_space`, sampled jointly when a
+    /// trial is asked for. Params left out, or suggested with another
+    /// distribution, go through the `sample_independent*` methods.
+    fn sample_relative(
+        &mut self,
+        _study: &Study,
+        _trial_id: usize,
+        _search_space: &HashMap<String, Distributions>,
+    ) -> Result<HashMap<String, f64>> {
+        Ok(HashMap::new())
+    }
+
+    /// Value of a float or bool param, or an error failing the suggest call.
+    fn sample_independent(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<f64>;
+    fn sample_independent_int(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<i64>;
+    fn sample_independent_category(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<ExternalRepr>;
+}
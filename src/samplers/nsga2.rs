@@ -0,0 +1,359 @@
+use super::{RandomSampler, Sampler};
+use crate::distributions::{Distribution, Distributions, ExternalRepr};
+use crate::study::{dominates, Study, StudyDirection};
+use crate::trial::{FrozenTrial, FrozenTrialState};
+use anyhow::Result;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+
+/// NSGA-II sampler for multi-objective studies.
+///
+/// The best `population_size` completed trials by non-dominated rank and
+/// crowding distance form the parent population. Each new trial takes its
+/// params from two parents picked by binary tournament, mixed by uniform
+/// crossover, and each param is resampled at random with `mutation_prob`.
+/// Trials reporting constraints are ranked by constraint dominance: a feasible
+/// trial beats an infeasible one, and infeasible trials compare by violation.
+/// The parents are selected again once `population_size` more trials have
+/// completed, so each generation is bred from the same parents.
+/// Until `population_size` trials have completed it samples uniformly at random.
+pub struct NsgaIISampler {
+    rng: StdRng,
+    random_sampler: RandomSampler,
+    population_size: usize,
+    crossover_prob: f64,
+    mutation_prob: f64,
+    /// Parents of the current generation with their rank and crowding distance.
+    population: Vec<(FrozenTrial, usize, f64)>,
+    /// Number of completed trials when `population` was selected.
+    n_completed_at_selection: usize,
+    /// Params inherited by each running trial, by trial id, with the
+    /// distribution they were drawn from.
+    children: HashMap<usize, HashMap<String, (Distributions, f64)>>,
+}
+
+impl Default for NsgaIISampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NsgaIISampler {
+    pub fn new() -> Self {
+        NsgaIISampler {
+            rng: StdRng::from_entropy(),
+            random_sampler: RandomSampler::new(),
+            population_size: 50,
+            crossover_prob: 0.9,
+            mutation_prob: 0.1,
+            population: vec![],
+            n_completed_at_selection: 0,
+            children: HashMap::new(),
+        }
+    }
+
+    pub fn with_population_size(mut self, population_size: usize) -> Self {
+        self.population_size = population_size;
+        self
+    }
+
+    pub fn with_crossover_prob(mut self, crossover_prob: f64) -> Self {
+        self.crossover_prob = crossover_prob;
+        self
+    }
+
+    pub fn with_mutation_prob(mut self, mutation_prob: f64) -> Self {
+        self.mutation_prob = mutation_prob;
+        self
+    }
+
+    /// Selects the parent population from the completed trials, each paired
+    /// with its non-dominated rank and crowding distance.
+    fn select_population(
+        &self,
+        trials: Vec<FrozenTrial>,
+        directions: &[StudyDirection],
+    ) -> Vec<(FrozenTrial, usize, f64)> {
+        let mut remaining = trials;
+        let mut population = vec![];
+        let mut rank = 0;
+        while !remaining.is_empty() && population.len() < self.population_size {
+            let (front, rest): (Vec<FrozenTrial>, Vec<FrozenTrial>) =
+                remaining.iter().cloned().partition(|trial| {
+                    !remaining
+                        .iter()
+                        .any(|other| constrained_dominates(other, trial, directions))
+                });
+            let distances = crowding_distances(&front);
+            let mut front: Vec<(FrozenTrial, usize, f64)> = front
+                .into_iter()
+                .zip(distances)
+                .map(|(trial, distance)| (trial, rank, distance))
+                .collect();
+            front.sort_by(|a, b| b.2.total_cmp(&a.2));
+            front.truncate(self.population_size - population.len());
+            population.extend(front);
+            remaining = rest;
+            rank += 1;
+        }
+        population
+    }
+
+    fn tournament<'a>(
+        rng: &mut StdRng,
+        population: &'a [(FrozenTrial, usize, f64)],
+    ) -> &'a (FrozenTrial, usize, f64) {
+        let a = &population[rng.gen_range(0..population.len())];
+        let b = &population[rng.gen_range(0..population.len())];
+        if a.1 < b.1 || (a.1 == b.1 && a.2 >= b.2) {
+            a
+        } else {
+            b
+        }
+    }
+
+    /// Inherited internal value of `name`, if `trial_id` has one for `distribution`.
+    fn inherited(&self, trial_id: usize, name: &str, distribution: &Distributions) -> Option<f64> {
+        match self.children.get(&trial_id)?.get(name) {
+            Some((parent_distribution, value)) if parent_distribution == distribution => {
+                Some(*value)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether `a` beats `b`, looking at the objectives only when both are feasible.
+fn constrained_dominates(a: &FrozenTrial, b: &FrozenTrial, directions: &[StudyDirection]) -> bool {
+    match (a.is_feasible(), b.is_feasible()) {
+        (true, true) => dominates(&a.values, &b.values, directions),
+        (true, false) => true,
+        (false, true) => false,
+        (false, false) => a.constraint_violation() < b.constraint_violation(),
+    }
+}
+
+/// Crowding distance of each trial within a single front, infinite at the boundaries.
+fn crowding_distances(front: &[FrozenTrial]) -> Vec<f64> {
+    let mut distances = vec![0.0; front.len()];
+    let n_objectives = front.first().map_or(0, |trial| trial.values.len());
+    for objective in 0..n_objectives {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| front[a].values[objective].total_cmp(&front[b].values[objective]));
+        let low = front[order[0]].values[objective];
+        let high = front[order[order.len() - 1]].values[objective];
+        distances[order[0]] = f64::INFINITY;
+        distances[order[order.len() - 1]] = f64::INFINITY;
+        if high == low {
+            continue;
+        }
+        for i in 1..order.len().saturating_sub(1) {
+            let gap = front[order[i + 1]].values[objective] - front[order[i - 1]].values[objective];
+            distances[order[i]] += gap / (high - low);
+        }
+    }
+    distances
+}
+
+impl Sampler for NsgaIISampler {
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.random_sampler.reseed(seed);
+    }
+
+    fn before_trial(&mut self, study: &Study, trial_id: usize) -> Result<()> {
+        let summaries = study.get_trial_summaries(None)?;
+        let running: HashSet<usize> = summaries
+            .iter()
+            .filter(|summary| summary.state == FrozenTrialState::Running)
+            .map(|summary| summary.trial_id)
+            .collect();
+        self.children.retain(|id, _| running.contains(id));
+        let n_completed = summaries
+            .iter()
+            .filter(|summary| summary.state == FrozenTrialState::Completed)
+            .count();
+        if n_completed < self.population_size {
+            return Ok(());
+        }
+        if self.population.is_empty()
+            || n_completed >= self.n_completed_at_selection + self.population_size
+        {
+            let directions = study.directions();
+            let completed: Vec<FrozenTrial> = study
+                .get_trials(Some(FrozenTrialState::Completed))?
+                .into_iter()
+                .filter(|trial| trial.values.len() == directions.len())
+                .filter(|trial| trial.values.iter().all(|value| value.is_finite()))
+                .collect();
+            if completed.len() < self.population_size {
+                return Ok(());
+            }
+            self.population = self.select_population(completed, directions);
+            self.n_completed_at_selection = n_completed;
+        }
+        let first = Self::tournament(&mut self.rng, &self.population).0.clone();
+        let second = Self::tournament(&mut self.rng, &self.population).0.clone();
+        let crossover = self.rng.gen_bool(self.crossover_prob);
+        let mut names: Vec<&String> = first.distributions.keys().collect();
+        names.sort();
+        let mut child = HashMap::new();
+        for name in names {
+            let distribution = &first.distributions[name];
+            let mut parent = &first;
+            if crossover
+                && second.distributions.get(name) == Some(distribution)
+                && self.rng.gen_bool(0.5)
+            {
+                parent = &second;
+            }
+            if self.rng.gen_bool(self.mutation_prob) {
+                continue;
+            }
+            child.insert(
+                name.clone(),
+                (distribution.clone(), parent.internal_params[name]),
+            );
+        }
+        self.children.insert(trial_id, child);
+        Ok(())
+    }
+
+    fn sample_independent(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<f64> {
+        match (self.inherited(trial_id, name, &distribution), &distribution) {
+            (Some(value), Distributions::Uni(dist)) => Ok(dist.to_external_repr(value)),
+            (Some(value), Distributions::LogUni(dist)) => Ok(dist.to_external_repr(value)),
+            (Some(value), Distributions::DiscreteUni(dist)) => Ok(dist.to_external_repr(value)),
+            (Some(value), Distributions::Bool(_)) => Ok(value),
+            _ => self
+                .random_sampler
+                .sample_independent(study, trial_id, name, distribution),
+        }
+    }
+
+    fn sample_independent_int(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<i64> {
+        match (self.inherited(trial_id, name, &distribution), &distribution) {
+            (Some(value), Distributions::IntUni(dist)) => Ok(dist.to_external_repr(value)),
+            _ => self
+                .random_sampler
+                .sample_independent_int(study, trial_id, name, distribution),
+        }
+    }
+
+    fn sample_independent_category(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<ExternalRepr> {
+        match (self.inherited(trial_id, name, &distribution), &distribution) {
+            (Some(value), Distributions::Categorical(dist)) => Ok(dist.to_external_repr(value)),
+            _ => {
+                self.random_sampler
+                    .sample_independent_category(study, trial_id, name, distribution)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distributions::UniformDistribution;
+    use crate::storages::InMemoryStorage;
+    use crate::trial::Trial;
+
+    fn objective(trial: &mut Trial) -> Result<Vec<f64>> {
+        let x = trial.suggest_uniform("x", 0.0, 1.0)?;
+        Ok(vec![x, 1.0 - x])
+    }
+
+    fn study() -> Study {
+        let directions = vec![StudyDirection::Minimize, StudyDirection::Minimize];
+        Study::new_multi_objective(InMemoryStorage::new(), RandomSampler::new(), directions)
+            .unwrap()
+    }
+
+    fn sampler() -> NsgaIISampler {
+        NsgaIISampler::new()
+            .with_population_size(4)
+            .with_crossover_prob(0.0)
+            .with_mutation_prob(0.0)
+    }
+
+    #[test]
+    fn trials_asked_together_keep_their_own_parents() {
+        let study = study();
+        study.optimize_multi_objective(objective, 4).unwrap();
+        let mut sampler = sampler();
+        sampler.reseed(0);
+        let first = study.ask().unwrap().trial_id();
+        let second = study.ask().unwrap().trial_id();
+        sampler.before_trial(&study, first).unwrap();
+        sampler.before_trial(&study, second).unwrap();
+        let inherited = |sampler: &NsgaIISampler, trial_id| sampler.children[&trial_id]["x"].1;
+        let (first_x, second_x) = (inherited(&sampler, first), inherited(&sampler, second));
+
+        let distribution = Distributions::Uni(UniformDistribution {
+            low: 0.0,
+            high: 1.0,
+        });
+        let sampled = sampler
+            .sample_independent(&study, first, "x", distribution.clone())
+            .unwrap();
+        assert_eq!(sampled, first_x);
+        let sampled = sampler
+            .sample_independent(&study, second, "x", distribution)
+            .unwrap();
+        assert_eq!(sampled, second_x);
+    }
+
+    #[test]
+    fn children_of_finished_trials_are_dropped() {
+        let study = study();
+        study.optimize_multi_objective(objective, 4).unwrap();
+        let mut sampler = sampler();
+        let finished = study.ask().unwrap();
+        sampler.before_trial(&study, finished.trial_id()).unwrap();
+        let finished_id = finished.trial_id();
+        finished.fail().unwrap();
+        let running = study.ask().unwrap().trial_id();
+        sampler.before_trial(&study, running).unwrap();
+        assert!(!sampler.children.contains_key(&finished_id));
+        assert!(sampler.children.contains_key(&running));
+    }
+
+    #[test]
+    fn parents_are_selected_once_per_generation() {
+        let study = study();
+        study.optimize_multi_objective(objective, 4).unwrap();
+        let mut sampler = sampler();
+        sampler
+            .before_trial(&study, study.ask().unwrap().trial_id())
+            .unwrap();
+        assert_eq!(sampler.n_completed_at_selection, 4);
+        study.optimize_multi_objective(objective, 3).unwrap();
+        sampler
+            .before_trial(&study, study.ask().unwrap().trial_id())
+            .unwrap();
+        assert_eq!(sampler.n_completed_at_selection, 4);
+        study.optimize_multi_objective(objective, 1).unwrap();
+        sampler
+            .before_trial(&study, study.ask().unwrap().trial_id())
+            .unwrap();
+        assert_eq!(sampler.n_completed_at_selection, 8);
+    }
+}
@@ -0,0 +1,185 @@
+use super::Sampler;
+use crate::distributions::{Distributions, ExternalRepr};
+use crate::study::Study;
+use anyhow::Result;
+use log::warn;
+use std::collections::HashMap;
+
+/// Suggests the given values for some params in every trial and lets the
+/// wrapped sampler propose the others. A fixed value whose type does not fit
+/// the suggested distribution is ignored with a warning.
+pub struct PartialFixedSampler<S: Sampler> {
+    inner: S,
+    fixed_params: HashMap<String, ExternalRepr>,
+}
+
+impl<S: Sampler> PartialFixedSampler<S> {
+    pub fn new(inner: S, fixed_params: HashMap<String, ExternalRepr>) -> Self {
+        PartialFixedSampler {
+            inner,
+            fixed_params,
+        }
+    }
+
+    /// Fixed value of `name`, if there is one that fits `distribution`.
+    fn fixed(&self, name: &str, distribution: &Distributions) -> Option<&ExternalRepr> {
+        let value = self.fixed_params.get(name)?;
+        if let Err(err) = distribution.internal_repr_of(name, value) {
+            warn!("Ignoring fixed value of param {}: {}", name, err);
+            return None;
+        }
+        Some(value)
+    }
+}
+
+impl<S: Sampler> Sampler for PartialFixedSampler<S> {
+    fn reseed(&mut self, seed: u64) {
+        self.inner.reseed(seed);
+    }
+
+    fn before_trial(&mut self, study: &Study, trial_id: usize) -> Result<()> {
+        self.inner.before_trial(study, trial_id)
+    }
+
+    fn is_exhausted(&self, study: &Study) -> bool {
+        self.inner.is_exhausted(study)
+    }
+
+    fn infer_relative_search_space(
+        &self,
+        study: &Study,
+        trial_id: usize,
+    ) -> Result<HashMap<String, Distributions>> {
+        let mut search_space = self.inner.infer_relative_search_space(study, trial_id)?;
+        // Fixed params are left to `sample_independent*`, which returns them.
+        search_space.retain(|name, _| !self.fixed_params.contains_key(name));
+        Ok(search_space)
+    }
+
+    fn sample_relative(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        search_space: &HashMap<String, Distributions>,
+    ) -> Result<HashMap<String, f64>> {
+        self.inner.sample_relative(study, trial_id, search_space)
+    }
+
+    fn sample_independent(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<f64> {
+        match self.fixed(name, &distribution) {
+            Some(ExternalRepr::Float(value)) => Ok(*value),
+            // Bools are returned in internal form, as by the other samplers.
+            Some(value @ ExternalRepr::Bool(_)) => distribution.internal_repr_of(name, value),
+            _ => self
+                .inner
+                .sample_independent(study, trial_id, name, distribution),
+        }
+    }
+
+    fn sample_independent_int(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<i64> {
+        match self.fixed(name, &distribution) {
+            Some(ExternalRepr::Int(value)) => Ok(*value),
+            _ => self
+                .inner
+                .sample_independent_int(study, trial_id, name, distribution),
+        }
+    }
+
+    fn sample_independent_category(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<ExternalRepr> {
+        match self.fixed(name, &distribution) {
+            Some(value) => Ok(value.clone()),
+            None => self
+                .inner
+                .sample_independent_category(study, trial_id, name, distribution),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samplers::TpeSampler;
+    use crate::storages::InMemoryStorage;
+    use crate::study::{create_study, StudyDirection};
+    use crate::trial::Trial;
+
+    fn objective(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", 0.0, 1.0)?;
+        let n = trial.suggest_int("n", 0, 9)?;
+        let c = trial.suggest_categorical(
+            "c",
+            vec![
+                ExternalRepr::Str("a".to_string()),
+                ExternalRepr::Str("b".to_string()),
+            ],
+        )?;
+        let flag = trial.suggest_bool("flag")?;
+        let y = trial.suggest_uniform("y", -1.0, 1.0)?;
+        let c = if c == ExternalRepr::Str("b".to_string()) {
+            1.0
+        } else {
+            0.0
+        };
+        Ok(x + n as f64 + c + f64::from(u8::from(flag)) + y * y)
+    }
+
+    #[test]
+    fn fixed_params_are_suggested_in_every_trial() {
+        let fixed_params: HashMap<String, ExternalRepr> = vec![
+            ("x", ExternalRepr::Float(0.25)),
+            ("n", ExternalRepr::Int(7)),
+            ("c", ExternalRepr::Str("b".to_string())),
+            ("flag", ExternalRepr::Bool(true)),
+            ("y", ExternalRepr::Str("not a float".to_string())),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+        let sampler = PartialFixedSampler::new(
+            TpeSampler::new()
+                .with_n_startup_trials(3)
+                .with_multivariate(true),
+            fixed_params,
+        );
+        let mut study = create_study(InMemoryStorage::new(), sampler, StudyDirection::Minimize);
+        study.set_seed(5);
+        study.optimize(objective, 10).unwrap();
+
+        let trials = study.trials().unwrap();
+        assert_eq!(trials.len(), 10);
+        let mut ys = vec![];
+        for trial in &trials {
+            let params = trial.params().unwrap();
+            assert_eq!(params["x"], ExternalRepr::Float(0.25));
+            assert_eq!(params["n"], ExternalRepr::Int(7));
+            assert_eq!(params["c"], ExternalRepr::Str("b".to_string()));
+            assert_eq!(params["flag"], ExternalRepr::Bool(true));
+            match params["y"] {
+                ExternalRepr::Float(y) => ys.push(y),
+                ref other => panic!("y should be a float, got {:?}", other),
+            }
+        }
+        ys.sort_by(f64::total_cmp);
+        ys.dedup();
+        assert_eq!(ys.len(), 10, "the unfixed param should vary: {:?}", ys);
+        assert!(ys.iter().all(|y| (-1.0..=1.0).contains(y)));
+    }
+}
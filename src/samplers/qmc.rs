@@ -0,0 +1,195 @@
+use super::Sampler;
+use crate::distributions::{Distribution, Distributions, ExternalRepr};
+use crate::study::Study;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Quasi-Monte Carlo sampler drawing params from a Halton sequence, which
+/// covers the search space more evenly than uniform random sampling.
+///
+/// Trial number `n` takes the `n + 1`-th point of the sequence. Each param gets
+/// its own dimension, with the next unused prime as its base, the first time it
+/// is suggested, so the search space may be discovered while optimizing. The
+/// sequence is deterministic, so `reseed` has no effect.
+pub struct QmcSampler {
+    /// Halton base of each param seen so far.
+    bases: HashMap<String, u64>,
+    /// Position in the sequence of the current trial.
+    index: u64,
+}
+
+impl Default for QmcSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QmcSampler {
+    pub fn new() -> Self {
+        QmcSampler {
+            bases: HashMap::new(),
+            index: 1,
+        }
+    }
+
+    /// Coordinate in `[0, 1)` of the current point along the dimension of `name`.
+    fn coordinate(&mut self, name: &str) -> f64 {
+        let next_base = next_prime(self.bases.values().copied().max().unwrap_or(1));
+        let base = *self.bases.entry(name.to_string()).or_insert(next_base);
+        radical_inverse(self.index, base)
+    }
+}
+
+/// The digits of `index` in `base`, mirrored around the radix point.
+fn radical_inverse(mut index: u64, base: u64) -> f64 {
+    let mut inverse = 0.0;
+    let mut scale = 1.0 / base as f64;
+    while index > 0 {
+        inverse += (index % base) as f64 * scale;
+        index /= base;
+        scale /= base as f64;
+    }
+    inverse
+}
+
+fn next_prime(after: u64) -> u64 {
+    (after + 1..)
+        .find(|&n| (2..n).take_while(|d| d * d <= n).all(|d| n % d != 0))
+        .unwrap()
+}
+
+impl Sampler for QmcSampler {
+    fn reseed(&mut self, _seed: u64) {}
+
+    fn before_trial(&mut self, study: &Study, trial_id: usize) -> Result<()> {
+        let trial = study.storage.lock().unwrap().get_trial(trial_id)?;
+        self.index = trial.number as u64 + 1;
+        Ok(())
+    }
+
+    fn sample_independent(
+        &mut self,
+        _study: &Study,
+        _trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<f64> {
+        let u = self.coordinate(name);
+        Ok(match distribution {
+            Distributions::Uni(dist) => dist.low + u * (dist.high - dist.low),
+            Distributions::LogUni(dist) => {
+                let log_low = dist.low.ln();
+                (log_low + u * (dist.high.ln() - log_low)).exp()
+            }
+            Distributions::DiscreteUni(dist) => {
+                let idx = ((u * dist.n_steps() as f64) as usize).min(dist.n_steps() - 1);
+                dist.low + idx as f64 * dist.step
+            }
+            Distributions::Bool(dist) => dist.to_internal_repr(u >= 0.5),
+            _ => 0.0,
+        })
+    }
+
+    fn sample_independent_int(
+        &mut self,
+        _study: &Study,
+        _trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<i64> {
+        let u = self.coordinate(name);
+        Ok(match distribution {
+            Distributions::IntUni(dist) if dist.log => {
+                let log_low = (dist.low as f64 - 0.5).ln();
+                let log_high = (dist.last() as f64 + 0.5).ln();
+                dist.snap((log_low + u * (log_high - log_low)).exp())
+            }
+            Distributions::IntUni(dist) => {
+                let n_steps = (dist.last() - dist.low) / dist.step + 1;
+                let idx = ((u * n_steps as f64) as i64).min(n_steps - 1);
+                dist.low + idx * dist.step
+            }
+            _ => 0,
+        })
+    }
+
+    fn sample_independent_category(
+        &mut self,
+        _study: &Study,
+        _trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<ExternalRepr> {
+        let u = self.coordinate(name);
+        Ok(match distribution {
+            Distributions::Categorical(dist) => {
+                let idx = ((u * dist.choices.len() as f64) as usize).min(dist.choices.len() - 1);
+                dist.choices[idx].clone()
+            }
+            _ => ExternalRepr::Str(String::new()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distributions::UniformDistribution;
+    use crate::storages::InMemoryStorage;
+    use crate::study::{create_study, StudyDirection};
+    use crate::trial::Trial;
+
+    fn objective(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", -1.0, 1.0)?;
+        let n = trial.suggest_int("n", 0, 9)?;
+        Ok(x + n as f64)
+    }
+
+    #[test]
+    fn radical_inverse_gives_the_halton_values() {
+        let base_2: Vec<f64> = (1..=4).map(|i| radical_inverse(i, 2)).collect();
+        assert_eq!(base_2, vec![0.5, 0.25, 0.75, 0.125]);
+        let base_3: Vec<f64> = (1..=4).map(|i| radical_inverse(i, 3)).collect();
+        let expected = [1.0 / 3.0, 2.0 / 3.0, 1.0 / 9.0, 4.0 / 9.0];
+        for (value, expected) in base_3.iter().zip(expected.iter()) {
+            assert!((value - expected).abs() < 1e-15, "{:?}", base_3);
+        }
+    }
+
+    #[test]
+    fn each_param_gets_its_own_prime_base() {
+        let mut sampler = QmcSampler::new();
+        for name in ["a", "b", "c", "d", "e"].iter() {
+            sampler.coordinate(name);
+        }
+        sampler.coordinate("a");
+        let mut bases: Vec<u64> = sampler.bases.values().copied().collect();
+        bases.sort_unstable();
+        assert_eq!(bases, vec![2, 3, 5, 7, 11]);
+        assert_eq!(sampler.bases["a"], 2);
+        assert_eq!(sampler.bases["e"], 11);
+    }
+
+    #[test]
+    fn a_trial_number_reproduces_its_point() {
+        let study = create_study(
+            InMemoryStorage::new(),
+            QmcSampler::new(),
+            StudyDirection::Minimize,
+        );
+        study.optimize(objective, 4).unwrap();
+        let trials = study.trials().unwrap();
+        assert_eq!(trials[0].internal_params["x"], 0.0);
+        assert_eq!(trials[1].internal_params["x"], -0.5);
+
+        let mut sampler = QmcSampler::new();
+        let uniform = Distributions::Uni(UniformDistribution::new(-1.0, 1.0));
+        for trial in trials.iter().rev() {
+            sampler.before_trial(&study, trial.trial_id).unwrap();
+            let x = sampler
+                .sample_independent(&study, trial.trial_id, "x", uniform.clone())
+                .unwrap();
+            assert_eq!(x, trial.internal_params["x"]);
+        }
+    }
+}
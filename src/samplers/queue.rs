@@ -0,0 +1,172 @@
+use super::Sampler;
+use crate::distributions::{Distributions, ExternalRepr};
+use crate::study::Study;
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, VecDeque};
+
+/// Suggests prescribed values, popping the next value of a param each time it
+/// is suggested, to test an objective on known inputs.
+///
+/// `optimize` stops once every queue is empty. Suggesting a param whose queue
+/// is empty or missing, or whose next value does not fit the distribution,
+/// returns an error from the suggest call, which fails the trial.
+pub struct QueueSampler {
+    values: HashMap<String, VecDeque<ExternalRepr>>,
+}
+
+impl QueueSampler {
+    pub fn new(values: HashMap<String, Vec<ExternalRepr>>) -> Self {
+        QueueSampler {
+            values: values
+                .into_iter()
+                .map(|(name, values)| (name, values.into()))
+                .collect(),
+        }
+    }
+
+    fn pop(&mut self, name: &str) -> Result<ExternalRepr> {
+        self.values
+            .get_mut(name)
+            .and_then(VecDeque::pop_front)
+            .ok_or_else(|| anyhow!("QueueSampler has no values left for param {}", name))
+    }
+}
+
+impl Sampler for QueueSampler {
+    fn reseed(&mut self, _seed: u64) {}
+
+    fn is_exhausted(&self, _study: &Study) -> bool {
+        self.values.values().all(VecDeque::is_empty)
+    }
+
+    fn sample_independent(
+        &mut self,
+        _study: &Study,
+        _trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<f64> {
+        match (self.pop(name)?, &distribution) {
+            (ExternalRepr::Float(value), _) => Ok(value),
+            // Bools are returned in internal form, as by the other samplers.
+            (value @ ExternalRepr::Bool(_), Distributions::Bool(_)) => {
+                distribution.internal_repr_of(name, &value)
+            }
+            (value, _) => Err(anyhow!(
+                "Queued value {:?} of param {} is not a float",
+                value,
+                name
+            )),
+        }
+    }
+
+    fn sample_independent_int(
+        &mut self,
+        _study: &Study,
+        _trial_id: usize,
+        name: &str,
+        _distribution: Distributions,
+    ) -> Result<i64> {
+        match self.pop(name)? {
+            ExternalRepr::Int(value) => Ok(value),
+            value => Err(anyhow!(
+                "Queued value {:?} of param {} is not an int",
+                value,
+                name
+            )),
+        }
+    }
+
+    fn sample_independent_category(
+        &mut self,
+        _study: &Study,
+        _trial_id: usize,
+        name: &str,
+        _distribution: Distributions,
+    ) -> Result<ExternalRepr> {
+        self.pop(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storages::InMemoryStorage;
+    use crate::study::{create_study, StudyDirection};
+    use crate::trial::{FrozenTrialState, Trial};
+
+    fn values(values: &[(&str, Vec<f64>)]) -> HashMap<String, Vec<ExternalRepr>> {
+        values
+            .iter()
+            .map(|(name, values)| {
+                let values = values.iter().map(|&v| ExternalRepr::Float(v)).collect();
+                (name.to_string(), values)
+            })
+            .collect()
+    }
+
+    fn objective(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", 0.0, 1.0)?;
+        let y = trial.suggest_uniform("y", 0.0, 1.0)?;
+        Ok(x + y)
+    }
+
+    #[test]
+    fn suggest_errors_once_a_queue_is_empty() {
+        let study = create_study(
+            InMemoryStorage::new(),
+            QueueSampler::new(values(&[("x", vec![0.5])])),
+            StudyDirection::Minimize,
+        );
+        let mut trial = study.ask().unwrap();
+        assert_eq!(trial.suggest_uniform("x", 0.0, 1.0).unwrap(), 0.5);
+        let err = trial.suggest_uniform("y", 0.0, 1.0).unwrap_err();
+        assert!(err.to_string().contains("no values left for param y"));
+        let err = study
+            .ask()
+            .unwrap()
+            .suggest_uniform("x", 0.0, 1.0)
+            .unwrap_err();
+        assert!(err.to_string().contains("no values left for param x"));
+    }
+
+    #[test]
+    fn suggest_errors_on_a_value_of_the_wrong_type() {
+        let mut queued = HashMap::new();
+        queued.insert("x".to_string(), vec![ExternalRepr::Int(1)]);
+        let study = create_study(
+            InMemoryStorage::new(),
+            QueueSampler::new(queued),
+            StudyDirection::Minimize,
+        );
+        let err = study
+            .ask()
+            .unwrap()
+            .suggest_uniform("x", 0.0, 1.0)
+            .unwrap_err();
+        assert!(err.to_string().contains("is not a float"));
+    }
+
+    #[test]
+    fn running_out_fails_the_trial_and_optimize_carries_on() {
+        let mut study = create_study(
+            InMemoryStorage::new(),
+            QueueSampler::new(values(&[("x", vec![0.1, 0.2, 0.3]), ("y", vec![0.1])])),
+            StudyDirection::Minimize,
+        );
+        study.set_catch(true);
+        study.optimize(objective, 10).unwrap();
+        let completed = study.get_trials(Some(FrozenTrialState::Completed)).unwrap();
+        assert_eq!(completed.len(), 1);
+        assert!((completed[0].value - 0.2).abs() < 1e-12);
+        assert_eq!(
+            study
+                .get_trials(Some(FrozenTrialState::Failed))
+                .unwrap()
+                .len(),
+            2
+        );
+        // The sampler lock is not poisoned, so the study can still be used.
+        assert!(study.ask().is_ok());
+    }
+}
@@ -0,0 +1,284 @@
+use super::Sampler;
+use crate::distributions::{CategoricalDistribution, Distribution, Distributions, ExternalRepr};
+use crate::study::Study;
+use crate::trial::FrozenTrialState;
+use anyhow::{anyhow, Result};
+use rand::{
+    distributions::{Uniform, WeightedIndex},
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
+
+/// Samples every param independently and uniformly at random with the RNG `R`,
+/// which defaults to `StdRng`. Weighted categorical choices are drawn by weight.
+#[derive(Clone)]
+pub struct RandomSampler<R = StdRng> {
+    rng: R,
+    /// Creates the RNG for a seed in `reseed`, or `None` if `R` cannot be seeded.
+    seed_rng: Option<fn(u64) -> R>,
+    coverage_categorical: bool,
+}
+
+impl Default for RandomSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RandomSampler {
+    pub fn new() -> Self {
+        RandomSampler {
+            rng: StdRng::from_entropy(),
+            seed_rng: Some(StdRng::seed_from_u64),
+            coverage_categorical: false,
+        }
+    }
+
+    /// Creates a sampler whose draws are fully determined by `seed`.
+    pub fn from_seed(seed: u64) -> Self {
+        RandomSampler {
+            rng: StdRng::seed_from_u64(seed),
+            seed_rng: Some(StdRng::seed_from_u64),
+            coverage_categorical: false,
+        }
+    }
+}
+
+impl<R: Rng> RandomSampler<R> {
+    /// Creates a sampler drawing from `rng`, e.g. a mock RNG such as `StepRng`.
+    /// `reseed` leaves the RNG untouched, so seeded studies do not reseed it;
+    /// use `from_seedable_rng` for an RNG that can be seeded.
+    pub fn from_rng(rng: R) -> Self {
+        RandomSampler {
+            rng,
+            seed_rng: None,
+            coverage_categorical: false,
+        }
+    }
+
+    /// Draws categorical choices inversely proportional to how often past trials picked them.
+    pub fn with_coverage_categorical(mut self, coverage_categorical: bool) -> Self {
+        self.coverage_categorical = coverage_categorical;
+        self
+    }
+
+    fn count_categorical_choices(
+        study: &Study,
+        name: &str,
+        distribution: &CategoricalDistribution,
+    ) -> Result<Vec<usize>> {
+        let mut counts = vec![0; distribution.choices.len()];
+        for trial in study.trials()? {
+            // Running trials count as well, so concurrently asked trials spread over
+            // the choices instead of all chasing the same rare one.
+            if trial.state == FrozenTrialState::Failed {
+                continue;
+            }
+            if let (Some(Distributions::Categorical(dist)), Some(&internal_repr)) = (
+                trial.distributions.get(name),
+                trial.internal_params.get(name),
+            ) {
+                let choice = dist.to_external_repr(internal_repr);
+                if let Some(idx) = distribution.choices.iter().position(|c| *c == choice) {
+                    counts[idx] += 1;
+                }
+            }
+        }
+        Ok(counts)
+    }
+}
+
+impl<R: Rng + SeedableRng> RandomSampler<R> {
+    /// Creates a sampler drawing from `rng`, e.g. a `SmallRng`, which `reseed`
+    /// replaces with one of the same type seeded from the given seed.
+    pub fn from_seedable_rng(rng: R) -> Self {
+        RandomSampler {
+            rng,
+            seed_rng: Some(R::seed_from_u64),
+            coverage_categorical: false,
+        }
+    }
+}
+
+impl<R: Rng + Send> Sampler for RandomSampler<R> {
+    fn reseed(&mut self, seed: u64) {
+        if let Some(seed_rng) = self.seed_rng {
+            self.rng = seed_rng(seed);
+        }
+    }
+
+    fn sample_independent(
+        &mut self,
+        _study: &Study,
+        _trial_id: usize,
+        _name: &str,
+        distribution: Distributions,
+    ) -> Result<f64> {
+        Ok(match distribution {
+            Distributions::Uni(dist) => {
+                let dice = rand::distributions::Uniform::from(dist.low..=dist.high);
+                self.rng.sample(dice)
+            }
+            Distributions::LogUni(dist) => {
+                let log_low = dist.low.ln();
+                let log_high = dist.high.ln();
+                let dice = Uniform::from(log_low..=log_high);
+                let n = self.rng.sample(dice);
+                n.exp()
+            }
+            Distributions::DiscreteUni(dist) => {
+                let idx = self.rng.gen_range(0..dist.n_steps());
+                dist.low + idx as f64 * dist.step
+            }
+            Distributions::Bool(dist) => dist.to_internal_repr(self.rng.gen_bool(0.5)),
+            _ => 0.0,
+        })
+    }
+
+    fn sample_independent_int(
+        &mut self,
+        _study: &Study,
+        _trial_id: usize,
+        _name: &str,
+        distribution: Distributions,
+    ) -> Result<i64> {
+        Ok(match distribution {
+            Distributions::IntUni(dist) if dist.log => {
+                let log_low = (dist.low as f64 - 0.5).ln();
+                let log_high = (dist.last() as f64 + 0.5).ln();
+                let dice = Uniform::from(log_low..=log_high);
+                dist.snap(self.rng.sample(dice).exp())
+            }
+            Distributions::IntUni(dist) => {
+                let dice = Uniform::from(0..=(dist.last() - dist.low) / dist.step);
+                dist.low + self.rng.sample(dice) * dist.step
+            }
+            _ => 0,
+        })
+    }
+
+    fn sample_independent_category(
+        &mut self,
+        study: &Study,
+        _trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<ExternalRepr> {
+        Ok(match distribution {
+            Distributions::Categorical(dist) => {
+                let mut weights = dist
+                    .weights
+                    .clone()
+                    .unwrap_or_else(|| vec![1.0; dist.choices.len()]);
+                if self.coverage_categorical {
+                    let counts = Self::count_categorical_choices(study, name, &dist)?;
+                    for (weight, count) in weights.iter_mut().zip(counts) {
+                        *weight /= count as f64 + 1.0;
+                    }
+                } else if dist.weights.is_none() {
+                    let idx = self.rng.gen_range(0..dist.choices.len());
+                    return Ok(dist.choices[idx].clone());
+                }
+                let dice = WeightedIndex::new(weights)
+                    .map_err(|err| anyhow!("Invalid weights of param {}: {}", name, err))?;
+                dist.choices[self.rng.sample(dice)].clone()
+            }
+            _ => ExternalRepr::Str(String::new()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distributions::{IntUniformDistribution, UniformDistribution};
+    use crate::storages::InMemoryStorage;
+    use crate::study::{create_study, StudyDirection};
+    use rand::rngs::mock::StepRng;
+
+    const N_CHOICES: i64 = 8;
+
+    /// Number of trials `sampler` needs until every choice was suggested once.
+    fn n_trials_to_cover(sampler: RandomSampler) -> usize {
+        let study = create_study(InMemoryStorage::new(), sampler, StudyDirection::Minimize);
+        let mut seen = vec![false; N_CHOICES as usize];
+        let mut n_trials = 0;
+        while seen.contains(&false) {
+            let mut trial = study.ask().unwrap();
+            let choices = (0..N_CHOICES).map(ExternalRepr::Int).collect();
+            if let ExternalRepr::Int(choice) = trial.suggest_categorical("c", choices).unwrap() {
+                seen[choice as usize] = true;
+            }
+            study.tell(trial, 0.0).unwrap();
+            n_trials += 1;
+        }
+        n_trials
+    }
+
+    #[test]
+    fn coverage_categorical_visits_every_choice_faster_than_uniform() {
+        let (mut n_uniform, mut n_coverage) = (0, 0);
+        for seed in 0..20 {
+            n_uniform += n_trials_to_cover(RandomSampler::from_seed(seed));
+            n_coverage +=
+                n_trials_to_cover(RandomSampler::from_seed(seed).with_coverage_categorical(true));
+        }
+        assert!(n_coverage < n_uniform, "{} >= {}", n_coverage, n_uniform);
+    }
+
+    #[test]
+    fn seeded_rng_gives_the_exact_sequence() {
+        let study = create_study(
+            InMemoryStorage::new(),
+            RandomSampler::new(),
+            StudyDirection::Minimize,
+        );
+        let mut sampler = RandomSampler::from_seedable_rng(StdRng::seed_from_u64(42));
+        let uniform = Distributions::Uni(UniformDistribution::new(-1.0, 1.0));
+        let int = Distributions::IntUni(IntUniformDistribution::new(0, 9));
+        let mut values = vec![];
+        let mut ints = vec![];
+        for _ in 0..4 {
+            values.push(
+                sampler
+                    .sample_independent(&study, 0, "x", uniform.clone())
+                    .unwrap(),
+            );
+            ints.push(
+                sampler
+                    .sample_independent_int(&study, 0, "n", int.clone())
+                    .unwrap(),
+            );
+        }
+        assert_eq!(
+            values,
+            vec![
+                0.05311481800554785,
+                0.2729301982877901,
+                -0.9313143640900878,
+                0.47484885544878686
+            ]
+        );
+        assert_eq!(ints, vec![5, 4, 4, 8]);
+
+        sampler.reseed(42);
+        let first = sampler.sample_independent(&study, 0, "x", uniform).unwrap();
+        assert_eq!(first, 0.05311481800554785);
+    }
+
+    #[test]
+    fn mock_rng_samples_in_a_seeded_study() {
+        let mut study = create_study(
+            InMemoryStorage::new(),
+            RandomSampler::from_rng(StepRng::new(0, 0)),
+            StudyDirection::Minimize,
+        );
+        study.set_seed(1);
+        for _ in 0..3 {
+            let mut trial = study.ask().unwrap();
+            assert_eq!(trial.suggest_uniform("x", -1.0, 1.0).unwrap(), -1.0);
+            assert_eq!(trial.suggest_int("n", 3, 9).unwrap(), 3);
+            study.tell(trial, 0.0).unwrap();
+        }
+    }
+}
@@ -0,0 +1,954 @@
+use super::{RandomSampler, Sampler};
+use crate::distributions::{Distributions, ExternalRepr};
+use crate::study::{Study, StudyDirection};
+use crate::trial::{FrozenTrial, FrozenTrialState};
+use anyhow::Result;
+use rand::{distributions::WeightedIndex, rngs::StdRng, Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use std::collections::HashMap;
+
+/// Tree-structured Parzen Estimator sampler.
+///
+/// Completed trials are split into a "good" and a "bad" group by objective value,
+/// each group is modeled by a Parzen estimator `l(x)` / `g(x)`, and the candidate
+/// drawn from `l(x)` that maximizes `l(x) / g(x)` is proposed. Like Optuna,
+/// both estimators mix in a prior by default, see `with_consider_prior`. Until
+/// `n_startup_trials` trials have completed it samples uniformly at random.
+/// Each param is modeled from only the trials that suggested it with the same
+/// distribution, so params that only some trials suggest are handled too.
+///
+/// With `with_multivariate`, the params every completed trial suggested with
+/// the same distribution are instead modeled jointly and sampled together
+/// through `Sampler::sample_relative`, which captures their interactions.
+pub struct TpeSampler {
+    rng: StdRng,
+    random_sampler: RandomSampler,
+    n_startup_trials: usize,
+    n_ei_candidates: usize,
+    consider_prior: bool,
+    prior_weight: f64,
+    multivariate: bool,
+    constant_liar: bool,
+}
+
+impl Default for TpeSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TpeSampler {
+    pub fn new() -> Self {
+        TpeSampler {
+            rng: StdRng::from_entropy(),
+            random_sampler: RandomSampler::new(),
+            n_startup_trials: 10,
+            n_ei_candidates: 24,
+            consider_prior: true,
+            prior_weight: 1.0,
+            multivariate: false,
+            constant_liar: false,
+        }
+    }
+
+    /// Completed trials sampled at random before the estimators are used.
+    pub fn with_n_startup_trials(mut self, n_startup_trials: usize) -> Self {
+        self.n_startup_trials = n_startup_trials;
+        self
+    }
+
+    /// Candidates drawn from `l(x)` and scored by `l(x) / g(x)` per suggestion.
+    pub fn with_n_ei_candidates(mut self, n_ei_candidates: usize) -> Self {
+        assert!(n_ei_candidates > 0, "n_ei_candidates must be positive");
+        self.n_ei_candidates = n_ei_candidates;
+        self
+    }
+
+    /// Whether a prior spanning the whole range is mixed into `l(x)` and `g(x)`,
+    /// which smooths them while there are few observations.
+    pub fn with_consider_prior(mut self, consider_prior: bool) -> Self {
+        self.consider_prior = consider_prior;
+        self
+    }
+
+    /// Weight of the prior relative to each observation, which weighs 1.
+    pub fn with_prior_weight(mut self, prior_weight: f64) -> Self {
+        assert!(prior_weight > 0.0, "prior_weight must be positive");
+        self.prior_weight = prior_weight;
+        self
+    }
+
+    /// Whether the params shared by all completed trials are sampled jointly.
+    pub fn with_multivariate(mut self, multivariate: bool) -> Self {
+        self.multivariate = multivariate;
+        self
+    }
+
+    /// Whether running trials join the bad group with the worst possible value,
+    /// so workers of `Study::optimize_parallel` do not all propose the same
+    /// point. Only completed trials count towards `n_startup_trials`.
+    pub fn with_constant_liar(mut self, constant_liar: bool) -> Self {
+        self.constant_liar = constant_liar;
+        self
+    }
+
+    /// Completed trials with a finite value and, with `constant_liar`, running
+    /// trials given the worst value in the study direction.
+    fn history(&self, study: &Study) -> Result<Vec<FrozenTrial>> {
+        let liar_value = match study.direction {
+            StudyDirection::Minimize => f64::INFINITY,
+            StudyDirection::Maximize => f64::NEG_INFINITY,
+        };
+        Ok(study
+            .trials()?
+            .into_iter()
+            .filter_map(|mut trial| match trial.state {
+                FrozenTrialState::Completed if trial.value.is_finite() => Some(trial),
+                FrozenTrialState::Running if self.constant_liar => {
+                    trial.value = liar_value;
+                    Some(trial)
+                }
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn prior_weight(&self) -> Option<f64> {
+        self.consider_prior.then_some(self.prior_weight)
+    }
+
+    /// Returns the internal values of `name` in completed trials that suggested
+    /// it from `distribution`, split into the good and the bad group, or `None`
+    /// while there are too few of them.
+    fn split_observations(
+        &self,
+        study: &Study,
+        name: &str,
+        distribution: &Distributions,
+    ) -> Result<Option<(Vec<f64>, Vec<f64>)>> {
+        // Trials that did not suggest `name`, or drew it from other bounds or
+        // choices, say nothing about its current distribution.
+        let trials: Vec<FrozenTrial> = self
+            .history(study)?
+            .into_iter()
+            .filter(|trial| trial.distributions.get(name) == Some(distribution))
+            .collect();
+        let n_completed = trials
+            .iter()
+            .filter(|trial| trial.state == FrozenTrialState::Completed)
+            .count();
+        if n_completed < self.n_startup_trials {
+            return Ok(None);
+        }
+        let mut observations: Vec<(f64, f64)> = trials
+            .iter()
+            .filter_map(|trial| Some((*trial.internal_params.get(name)?, trial.value)))
+            .collect();
+        observations.sort_by(|a, b| match study.direction {
+            StudyDirection::Minimize => a.1.total_cmp(&b.1),
+            StudyDirection::Maximize => b.1.total_cmp(&a.1),
+        });
+        let n_below = ((observations.len() as f64 * 0.1).ceil() as usize)
+            .min(25)
+            .min(n_completed);
+        let values: Vec<f64> = observations.iter().map(|(x, _)| *x).collect();
+        let (below, above) = values.split_at(n_below);
+        Ok(Some((below.to_vec(), above.to_vec())))
+    }
+
+    /// The `history` trials that set every param of `search_space`, sorted
+    /// from best to worst and split into the good and the bad group, or `None`
+    /// while too few have completed.
+    fn split_trials(
+        &self,
+        study: &Study,
+        search_space: &HashMap<String, Distributions>,
+    ) -> Result<Option<(Vec<FrozenTrial>, Vec<FrozenTrial>)>> {
+        let mut trials: Vec<FrozenTrial> = self
+            .history(study)?
+            .into_iter()
+            .filter(|trial| {
+                search_space
+                    .iter()
+                    .all(|(name, distribution)| trial.distributions.get(name) == Some(distribution))
+            })
+            .collect();
+        let n_completed = trials
+            .iter()
+            .filter(|trial| trial.state == FrozenTrialState::Completed)
+            .count();
+        if n_completed == 0 || n_completed < self.n_startup_trials {
+            return Ok(None);
+        }
+        trials.sort_by(|a, b| match study.direction {
+            StudyDirection::Minimize => a.value.total_cmp(&b.value),
+            StudyDirection::Maximize => b.value.total_cmp(&a.value),
+        });
+        let n_below = ((trials.len() as f64 * 0.1).ceil() as usize)
+            .min(25)
+            .min(n_completed);
+        let above = trials.split_off(n_below);
+        Ok(Some((trials, above)))
+    }
+
+    fn sample_numerical(&mut self, low: f64, high: f64, below: &[f64], above: &[f64]) -> f64 {
+        let l = ParzenEstimator::new(below, low, high, self.prior_weight());
+        let g = ParzenEstimator::new(above, low, high, self.prior_weight());
+        let mut best = (f64::NEG_INFINITY, low);
+        for _ in 0..self.n_ei_candidates {
+            let candidate = l.sample(&mut self.rng);
+            let score = l.log_pdf(candidate) - g.log_pdf(candidate);
+            if score > best.0 {
+                best = (score, candidate);
+            }
+        }
+        best.1
+    }
+
+    fn sample_categorical_index(
+        &mut self,
+        n_choices: usize,
+        below: &[f64],
+        above: &[f64],
+    ) -> usize {
+        let prior_weight = self.prior_weight();
+        let weights = |observations: &[f64]| {
+            // Without a prior, a group with no observations stays uniform.
+            let prior_weight = match prior_weight {
+                None if observations.is_empty() => 1.0,
+                prior_weight => prior_weight.unwrap_or(0.0),
+            };
+            let mut weights = vec![prior_weight / n_choices as f64; n_choices];
+            for &idx in observations {
+                if let Some(weight) = weights.get_mut(idx as usize) {
+                    *weight += 1.0;
+                }
+            }
+            let total: f64 = weights.iter().sum();
+            weights.into_iter().map(|w| w / total).collect::<Vec<f64>>()
+        };
+        let l = weights(below);
+        let g = weights(above);
+        let dice = WeightedIndex::new(&l).unwrap();
+        let mut best = (f64::NEG_INFINITY, 0);
+        for _ in 0..self.n_ei_candidates {
+            let candidate = self.rng.sample(&dice);
+            let score = l[candidate].ln() - g[candidate].ln();
+            if score > best.0 {
+                best = (score, candidate);
+            }
+        }
+        best.1
+    }
+}
+
+impl Sampler for TpeSampler {
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.random_sampler.reseed(seed);
+    }
+
+    fn infer_relative_search_space(
+        &self,
+        study: &Study,
+        _trial_id: usize,
+    ) -> Result<HashMap<String, Distributions>> {
+        if !self.multivariate {
+            return Ok(HashMap::new());
+        }
+        study.search_space()
+    }
+
+    fn sample_relative(
+        &mut self,
+        study: &Study,
+        _trial_id: usize,
+        search_space: &HashMap<String, Distributions>,
+    ) -> Result<HashMap<String, f64>> {
+        if search_space.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let (below, above) = match self.split_trials(study, search_space)? {
+            Some(split) => split,
+            None => return Ok(HashMap::new()),
+        };
+        let mut space: Vec<(&String, &Distributions)> = search_space.iter().collect();
+        space.sort_by(|a, b| a.0.cmp(b.0));
+        let dimensions: Vec<Dimension> = space
+            .iter()
+            .map(|(_, distribution)| Dimension::of(distribution))
+            .collect();
+        let points = |trials: &[FrozenTrial]| -> Vec<Vec<f64>> {
+            trials
+                .iter()
+                .map(|trial| {
+                    space
+                        .iter()
+                        .zip(&dimensions)
+                        .map(|((name, _), dimension)| {
+                            dimension.to_model(trial.internal_params[*name])
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+        let prior_weight = self.prior_weight();
+        let l = MultivariateParzenEstimator::new(&points(&below), &dimensions, prior_weight);
+        let g = MultivariateParzenEstimator::new(&points(&above), &dimensions, prior_weight);
+        let mut best = (f64::NEG_INFINITY, vec![]);
+        for _ in 0..self.n_ei_candidates {
+            let candidate = l.sample(&mut self.rng);
+            let score = l.log_pdf(&candidate) - g.log_pdf(&candidate);
+            if score > best.0 || best.1.is_empty() {
+                best = (score, candidate);
+            }
+        }
+        Ok(space
+            .iter()
+            .zip(&dimensions)
+            .zip(best.1)
+            .map(|(((name, _), dimension), x)| ((*name).clone(), dimension.to_internal(x)))
+            .collect())
+    }
+
+    fn sample_independent(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<f64> {
+        let (below, above) = match self.split_observations(study, name, &distribution)? {
+            Some(split) => split,
+            None => {
+                return self
+                    .random_sampler
+                    .sample_independent(study, trial_id, name, distribution)
+            }
+        };
+        Ok(match distribution {
+            Distributions::Uni(dist) => self.sample_numerical(dist.low, dist.high, &below, &above),
+            Distributions::LogUni(dist) => {
+                // Internal params of log distributions are already in log space.
+                self.sample_numerical(dist.low.ln(), dist.high.ln(), &below, &above)
+                    .exp()
+                    .max(dist.low)
+                    .min(dist.high)
+            }
+            Distributions::DiscreteUni(dist) => {
+                let low = dist.low - 0.5 * dist.step;
+                let high = dist.snap(dist.high) + 0.5 * dist.step;
+                dist.snap(self.sample_numerical(low, high, &below, &above))
+            }
+            Distributions::Bool(_) => self.sample_categorical_index(2, &below, &above) as f64,
+            _ => 0.0,
+        })
+    }
+
+    fn sample_independent_int(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<i64> {
+        let (below, above) = match self.split_observations(study, name, &distribution)? {
+            Some(split) => split,
+            None => {
+                return self.random_sampler.sample_independent_int(
+                    study,
+                    trial_id,
+                    name,
+                    distribution,
+                )
+            }
+        };
+        Ok(match distribution {
+            Distributions::IntUni(dist) if dist.log => {
+                let below: Vec<f64> = below.iter().map(|v| v.ln()).collect();
+                let above: Vec<f64> = above.iter().map(|v| v.ln()).collect();
+                let low = (dist.low as f64 - 0.5).ln();
+                let high = (dist.last() as f64 + 0.5).ln();
+                dist.snap(self.sample_numerical(low, high, &below, &above).exp())
+            }
+            Distributions::IntUni(dist) => {
+                let low = dist.low as f64 - 0.5 * dist.step as f64;
+                let high = dist.last() as f64 + 0.5 * dist.step as f64;
+                dist.snap(self.sample_numerical(low, high, &below, &above))
+            }
+            _ => 0,
+        })
+    }
+
+    fn sample_independent_category(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<ExternalRepr> {
+        let (below, above) = match self.split_observations(study, name, &distribution)? {
+            Some(split) => split,
+            None => {
+                return self.random_sampler.sample_independent_category(
+                    study,
+                    trial_id,
+                    name,
+                    distribution,
+                )
+            }
+        };
+        Ok(match distribution {
+            Distributions::Categorical(dist) => {
+                let idx = self.sample_categorical_index(dist.choices.len(), &below, &above);
+                dist.choices[idx].clone()
+            }
+            _ => ExternalRepr::Str(String::new()),
+        })
+    }
+}
+
+/// Mixture of gaussians truncated to `[low, high]`, one centered at each
+/// observation plus, if `prior_weight` is given or there are no observations,
+/// a wide prior centered in the middle of the range. Observations weigh 1 each.
+struct ParzenEstimator {
+    mus: Vec<f64>,
+    sigmas: Vec<f64>,
+    weights: Vec<f64>,
+    low: f64,
+    high: f64,
+}
+
+impl ParzenEstimator {
+    fn new(observations: &[f64], low: f64, high: f64, prior_weight: Option<f64>) -> Self {
+        let prior_mu = 0.5 * (low + high);
+        let prior_sigma = high - low;
+        let prior_weight = match prior_weight {
+            None if observations.is_empty() => Some(1.0),
+            prior_weight => prior_weight,
+        };
+        // Each component is its mean and, for the prior, its weight.
+        let mut components: Vec<(f64, Option<f64>)> =
+            observations.iter().map(|&mu| (mu, None)).collect();
+        if let Some(prior_weight) = prior_weight {
+            components.push((prior_mu, Some(prior_weight)));
+        }
+        components.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mus: Vec<f64> = components.iter().map(|&(mu, _)| mu).collect();
+
+        let mut sigmas = neighbor_sigmas(&mus, low, high);
+        for (sigma, component) in sigmas.iter_mut().zip(&components) {
+            if component.1.is_some() {
+                *sigma = prior_sigma;
+            }
+        }
+        let total: f64 = components.iter().map(|c| c.1.unwrap_or(1.0)).sum();
+        let weights = components
+            .iter()
+            .map(|c| c.1.unwrap_or(1.0) / total)
+            .collect();
+        ParzenEstimator {
+            mus,
+            sigmas,
+            weights,
+            low,
+            high,
+        }
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        let idx = rng.sample(WeightedIndex::new(&self.weights).unwrap());
+        // Rejection sampling from the truncated gaussian; falls back to clipping
+        // if the component barely overlaps the range.
+        for _ in 0..100 {
+            let z: f64 = rng.sample(StandardNormal);
+            let x = self.mus[idx] + self.sigmas[idx] * z;
+            if self.low <= x && x <= self.high {
+                return x;
+            }
+        }
+        self.mus[idx].max(self.low).min(self.high)
+    }
+
+    fn log_pdf(&self, x: f64) -> f64 {
+        let log_densities: Vec<f64> = self
+            .mus
+            .iter()
+            .zip(self.sigmas.iter())
+            .zip(self.weights.iter())
+            .map(|((&mu, &sigma), &weight)| {
+                weight.ln() + truncated_normal_log_pdf(x, mu, sigma, self.low, self.high)
+            })
+            .collect();
+        let max = log_densities
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        max + log_densities
+            .iter()
+            .map(|d| (d - max).exp())
+            .sum::<f64>()
+            .ln()
+    }
+}
+
+/// How a param is modeled by the multivariate estimator: numerical params in
+/// the same space as by the independent sampler, the others by choice index.
+enum Dimension {
+    Numerical {
+        low: f64,
+        high: f64,
+        distribution: Distributions,
+    },
+    Categorical(usize),
+}
+
+impl Dimension {
+    fn of(distribution: &Distributions) -> Self {
+        let (low, high) = match distribution {
+            Distributions::Uni(dist) => (dist.low, dist.high),
+            // Internal params of log distributions are already in log space.
+            Distributions::LogUni(dist) => (dist.low.ln(), dist.high.ln()),
+            Distributions::DiscreteUni(dist) => (
+                dist.low - 0.5 * dist.step,
+                dist.snap(dist.high) + 0.5 * dist.step,
+            ),
+            Distributions::IntUni(dist) if dist.log => (
+                (dist.low as f64 - 0.5).ln(),
+                (dist.last() as f64 + 0.5).ln(),
+            ),
+            Distributions::IntUni(dist) => (
+                dist.low as f64 - 0.5 * dist.step as f64,
+                dist.last() as f64 + 0.5 * dist.step as f64,
+            ),
+            Distributions::Categorical(dist) => return Dimension::Categorical(dist.choices.len()),
+            Distributions::Bool(_) => return Dimension::Categorical(2),
+        };
+        Dimension::Numerical {
+            low,
+            high,
+            distribution: distribution.clone(),
+        }
+    }
+
+    fn to_model(&self, internal_repr: f64) -> f64 {
+        match self {
+            Dimension::Numerical {
+                distribution: Distributions::IntUni(dist),
+                ..
+            } if dist.log => internal_repr.ln(),
+            _ => internal_repr,
+        }
+    }
+
+    fn to_internal(&self, x: f64) -> f64 {
+        match self {
+            Dimension::Numerical { distribution, .. } => match distribution {
+                Distributions::LogUni(dist) => x.max(dist.low.ln()).min(dist.high.ln()),
+                Distributions::DiscreteUni(dist) => dist.snap(x),
+                Distributions::IntUni(dist) if dist.log => dist.snap(x.exp()) as f64,
+                Distributions::IntUni(dist) => dist.snap(x) as f64,
+                _ => x,
+            },
+            Dimension::Categorical(_) => x,
+        }
+    }
+}
+
+/// Mixture of product kernels, one centered at each observed point plus, as
+/// for `ParzenEstimator`, a prior spanning the whole space. Numerical kernels
+/// are truncated gaussians with the univariate bandwidths of their dimension;
+/// a categorical kernel puts half its mass on the observed choice.
+struct MultivariateParzenEstimator<'a> {
+    dimensions: &'a [Dimension],
+    /// Per component, its point, `None` for the prior.
+    points: Vec<Option<Vec<f64>>>,
+    /// Per dimension, the bandwidth of each component.
+    sigmas: Vec<Vec<f64>>,
+    weights: Vec<f64>,
+}
+
+impl<'a> MultivariateParzenEstimator<'a> {
+    fn new(points: &[Vec<f64>], dimensions: &'a [Dimension], prior_weight: Option<f64>) -> Self {
+        let prior_weight = match prior_weight {
+            None if points.is_empty() => Some(1.0),
+            prior_weight => prior_weight,
+        };
+        let mut components: Vec<Option<Vec<f64>>> = points.iter().cloned().map(Some).collect();
+        let mut weights = vec![1.0; points.len()];
+        if let Some(prior_weight) = prior_weight {
+            components.push(None);
+            weights.push(prior_weight);
+        }
+        let total: f64 = weights.iter().sum();
+        weights.iter_mut().for_each(|weight| *weight /= total);
+        let sigmas = dimensions
+            .iter()
+            .enumerate()
+            .map(|(d, dimension)| match dimension {
+                Dimension::Numerical { low, high, .. } => {
+                    let mus: Vec<f64> = components
+                        .iter()
+                        .map(|point| point.as_ref().map_or(0.5 * (low + high), |point| point[d]))
+                        .collect();
+                    let mut sigmas = neighbor_sigmas(&mus, *low, *high);
+                    for (sigma, point) in sigmas.iter_mut().zip(&components) {
+                        if point.is_none() {
+                            *sigma = high - low;
+                        }
+                    }
+                    sigmas
+                }
+                Dimension::Categorical(_) => vec![],
+            })
+            .collect();
+        MultivariateParzenEstimator {
+            dimensions,
+            points: components,
+            sigmas,
+            weights,
+        }
+    }
+
+    /// Probability of `choice` under the kernel centered on `observed`.
+    fn categorical_mass(n_choices: usize, observed: Option<f64>, choice: usize) -> f64 {
+        let uniform = 1.0 / n_choices as f64;
+        match observed {
+            Some(observed) if observed as usize == choice => 0.5 + 0.5 * uniform,
+            Some(_) => 0.5 * uniform,
+            None => uniform,
+        }
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> Vec<f64> {
+        let k = rng.sample(WeightedIndex::new(&self.weights).unwrap());
+        let point = self.points[k].as_ref();
+        self.dimensions
+            .iter()
+            .enumerate()
+            .map(|(d, dimension)| match dimension {
+                Dimension::Numerical { low, high, .. } => {
+                    let mu = point.map_or(0.5 * (low + high), |point| point[d]);
+                    let sigma = self.sigmas[d][k];
+                    for _ in 0..100 {
+                        let z: f64 = rng.sample(StandardNormal);
+                        let x = mu + sigma * z;
+                        if *low <= x && x <= *high {
+                            return x;
+                        }
+                    }
+                    mu.max(*low).min(*high)
+                }
+                &Dimension::Categorical(n_choices) => {
+                    let masses: Vec<f64> = (0..n_choices)
+                        .map(|choice| {
+                            Self::categorical_mass(n_choices, point.map(|p| p[d]), choice)
+                        })
+                        .collect();
+                    rng.sample(WeightedIndex::new(&masses).unwrap()) as f64
+                }
+            })
+            .collect()
+    }
+
+    fn log_pdf(&self, x: &[f64]) -> f64 {
+        let log_densities: Vec<f64> = (0..self.points.len())
+            .map(|k| {
+                let point = self.points[k].as_ref();
+                let log_kernel: f64 = self
+                    .dimensions
+                    .iter()
+                    .enumerate()
+                    .map(|(d, dimension)| match dimension {
+                        Dimension::Numerical { low, high, .. } => {
+                            let mu = point.map_or(0.5 * (low + high), |point| point[d]);
+                            truncated_normal_log_pdf(x[d], mu, self.sigmas[d][k], *low, *high)
+                        }
+                        &Dimension::Categorical(n_choices) => {
+                            Self::categorical_mass(n_choices, point.map(|p| p[d]), x[d] as usize)
+                                .ln()
+                        }
+                    })
+                    .sum();
+                self.weights[k].ln() + log_kernel
+            })
+            .collect();
+        let max = log_densities
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        max + log_densities
+            .iter()
+            .map(|d| (d - max).exp())
+            .sum::<f64>()
+            .ln()
+    }
+}
+
+/// Bandwidth of a kernel at each of `mus`, in their order: the larger gap to
+/// its neighbors or the bounds, clipped to a range depending on the count.
+fn neighbor_sigmas(mus: &[f64], low: f64, high: f64) -> Vec<f64> {
+    let max_sigma = high - low;
+    let min_sigma = max_sigma / f64::min(100.0, 1.0 + mus.len() as f64);
+    let mut order: Vec<usize> = (0..mus.len()).collect();
+    order.sort_by(|&a, &b| mus[a].total_cmp(&mus[b]));
+    let mut sigmas = vec![0.0; mus.len()];
+    for (i, &idx) in order.iter().enumerate() {
+        let left = if i == 0 {
+            mus[idx] - low
+        } else {
+            mus[idx] - mus[order[i - 1]]
+        };
+        let right = if i + 1 == order.len() {
+            high - mus[idx]
+        } else {
+            mus[order[i + 1]] - mus[idx]
+        };
+        sigmas[idx] = left.max(right).max(min_sigma).min(max_sigma);
+    }
+    sigmas
+}
+
+fn truncated_normal_log_pdf(x: f64, mu: f64, sigma: f64, low: f64, high: f64) -> f64 {
+    let z = (x - mu) / sigma;
+    let mass = normal_cdf((high - mu) / sigma) - normal_cdf((low - mu) / sigma);
+    -0.5 * z * z
+        - (sigma * (2.0 * std::f64::consts::PI).sqrt()).ln()
+        - mass.max(f64::MIN_POSITIVE).ln()
+}
+
+pub(super) fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+/// Abramowitz and Stegun formula 7.1.26, accurate to about 1.5e-7.
+fn erf(x: f64) -> f64 {
+    let sign = if x < 0.0 { -1.0 } else { 1.0 };
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let y = 1.0
+        - (((((1.061405429 * t - 1.453152027) * t) + 1.421413741) * t - 0.284496736) * t
+            + 0.254829592)
+            * t
+            * (-x * x).exp();
+    sign * y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distributions::{
+        BoolDistribution, CategoricalDistribution, DiscreteUniformDistribution, Distribution,
+        IntUniformDistribution, LogUniformDistribution, UniformDistribution,
+    };
+    use crate::storages::InMemoryStorage;
+    use crate::study::create_study;
+    use crate::trial::Trial;
+
+    fn choices() -> Vec<ExternalRepr> {
+        vec![
+            ExternalRepr::Str("a".to_string()),
+            ExternalRepr::Str("b".to_string()),
+            ExternalRepr::Str("c".to_string()),
+        ]
+    }
+
+    fn distributions() -> Vec<(&'static str, Distributions)> {
+        vec![
+            ("x", Distributions::Uni(UniformDistribution::new(-2.0, 2.0))),
+            (
+                "lr",
+                Distributions::LogUni(LogUniformDistribution::new(1e-4, 1e-1)),
+            ),
+            (
+                "d",
+                Distributions::DiscreteUni(DiscreteUniformDistribution::new(0.0, 1.0, 0.25)),
+            ),
+            (
+                "n",
+                Distributions::IntUni(IntUniformDistribution::new(0, 9).with_step(3)),
+            ),
+            (
+                "m",
+                Distributions::IntUni(IntUniformDistribution::new(1, 64).with_log(true)),
+            ),
+            ("b", Distributions::Bool(BoolDistribution)),
+            (
+                "c",
+                Distributions::Categorical(CategoricalDistribution::new(choices())),
+            ),
+        ]
+    }
+
+    /// Suggests every param of `distributions`, scoring only some of them.
+    fn every_kind(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", -2.0, 2.0)?;
+        let lr = trial.suggest_log("lr", 1e-4, 1e-1)?;
+        trial.suggest_discrete_uniform("d", 0.0, 1.0, 0.25)?;
+        let n = trial.suggest_int_step("n", 0, 9, 3)?;
+        trial.suggest_int_log("m", 1, 64)?;
+        let b = trial.suggest_bool("b")?;
+        let c = trial.suggest_categorical("c", choices())?;
+        let c = if c == ExternalRepr::Str("b".to_string()) {
+            0.0
+        } else {
+            1.0
+        };
+        Ok(x * x + (lr.log10() + 2.0).powi(2) + n as f64 + b as u8 as f64 + c)
+    }
+
+    fn quadratic(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", -5.0, 5.0)?;
+        let y = trial.suggest_uniform("y", -5.0, 5.0)?;
+        Ok((x - 1.0).powi(2) + (y + 2.0).powi(2))
+    }
+
+    /// Asserts that `internal_repr` is a value `distribution` can take.
+    fn assert_within(name: &str, distribution: &Distributions, internal_repr: f64) {
+        let within = match distribution {
+            Distributions::Uni(dist) => dist.low <= internal_repr && internal_repr <= dist.high,
+            Distributions::LogUni(dist) => {
+                dist.low.ln() <= internal_repr && internal_repr <= dist.high.ln()
+            }
+            Distributions::DiscreteUni(dist) => dist.snap(internal_repr) == internal_repr,
+            Distributions::IntUni(dist) => dist.snap(internal_repr) as f64 == internal_repr,
+            Distributions::Bool(_) => internal_repr == 0.0 || internal_repr == 1.0,
+            Distributions::Categorical(dist) => {
+                internal_repr.fract() == 0.0 && (internal_repr as usize) < dist.choices.len()
+            }
+        };
+        assert!(
+            within,
+            "{}={} is out of {:?}",
+            name, internal_repr, distribution
+        );
+    }
+
+    #[test]
+    fn samples_at_random_before_n_startup_trials() {
+        let study = create_study(
+            InMemoryStorage::new(),
+            RandomSampler::new(),
+            StudyDirection::Minimize,
+        );
+        let uniform = Distributions::Uni(UniformDistribution::new(-2.0, 2.0));
+        let mut tpe = TpeSampler::new().with_n_startup_trials(5);
+        let mut random = RandomSampler::new();
+        for n_completed in 0..5 {
+            assert!(tpe
+                .split_observations(&study, "x", &uniform)
+                .unwrap()
+                .is_none());
+            tpe.reseed(n_completed);
+            random.reseed(n_completed);
+            let value = tpe
+                .sample_independent(&study, 0, "x", uniform.clone())
+                .unwrap();
+            let expected = random
+                .sample_independent(&study, 0, "x", uniform.clone())
+                .unwrap();
+            assert_eq!(value, expected);
+            let mut trial = study.ask().unwrap();
+            let x = trial.suggest_uniform("x", -2.0, 2.0).unwrap();
+            study.tell(trial, x).unwrap();
+        }
+        let (below, above) = tpe
+            .split_observations(&study, "x", &uniform)
+            .unwrap()
+            .unwrap();
+        assert_eq!((below.len(), above.len()), (1, 4));
+        let best = study.best_trial().unwrap().unwrap();
+        assert_eq!(below[0], best.internal_params["x"]);
+    }
+
+    #[test]
+    fn independent_values_stay_within_every_distribution() {
+        let mut study = create_study(
+            InMemoryStorage::new(),
+            TpeSampler::new().with_n_startup_trials(5),
+            StudyDirection::Minimize,
+        );
+        study.set_seed(1);
+        study.optimize(every_kind, 20).unwrap();
+        for trial in study.trials().unwrap() {
+            for (name, distribution) in distributions() {
+                assert_within(name, &distribution, trial.internal_params[name]);
+            }
+        }
+
+        let mut tpe = TpeSampler::new().with_n_startup_trials(5);
+        tpe.reseed(2);
+        for _ in 0..20 {
+            for (name, distribution) in distributions() {
+                let internal_repr = match &distribution {
+                    Distributions::IntUni(dist) => dist.to_internal_repr(
+                        tpe.sample_independent_int(&study, 0, name, distribution.clone())
+                            .unwrap(),
+                    ),
+                    Distributions::Categorical(dist) => dist
+                        .index_of(
+                            name,
+                            &tpe.sample_independent_category(&study, 0, name, distribution.clone())
+                                .unwrap(),
+                        )
+                        .unwrap() as f64,
+                    Distributions::LogUni(_) => tpe
+                        .sample_independent(&study, 0, name, distribution.clone())
+                        .unwrap()
+                        .ln(),
+                    _ => tpe
+                        .sample_independent(&study, 0, name, distribution.clone())
+                        .unwrap(),
+                };
+                assert_within(name, &distribution, internal_repr);
+            }
+        }
+    }
+
+    #[test]
+    fn multivariate_samples_every_param_of_the_search_space() {
+        let mut study = create_study(
+            InMemoryStorage::new(),
+            TpeSampler::new().with_n_startup_trials(5),
+            StudyDirection::Minimize,
+        );
+        study.set_seed(3);
+        study.optimize(every_kind, 10).unwrap();
+
+        let mut tpe = TpeSampler::new()
+            .with_n_startup_trials(5)
+            .with_multivariate(true);
+        tpe.reseed(4);
+        let search_space = tpe.infer_relative_search_space(&study, 0).unwrap();
+        assert_eq!(search_space.len(), distributions().len());
+        for _ in 0..10 {
+            let values = tpe.sample_relative(&study, 0, &search_space).unwrap();
+            assert_eq!(values.len(), search_space.len());
+            for (name, distribution) in &search_space {
+                assert_within(name, distribution, values[name]);
+            }
+        }
+        let single = TpeSampler::new().with_n_startup_trials(5);
+        assert!(single
+            .infer_relative_search_space(&study, 0)
+            .unwrap()
+            .is_empty());
+    }
+
+    fn best_value(sampler: impl Sampler + 'static, seed: u64) -> f64 {
+        let mut study = create_study(InMemoryStorage::new(), sampler, StudyDirection::Minimize);
+        study.set_seed(seed);
+        study.optimize(quadratic, 60).unwrap();
+        study.best_value().unwrap().unwrap()
+    }
+
+    #[test]
+    fn beats_random_sampling_on_a_seeded_quadratic() {
+        let (mut tpe, mut random) = (0.0, 0.0);
+        for seed in 0..5 {
+            tpe += best_value(TpeSampler::new(), seed);
+            random += best_value(RandomSampler::new(), seed);
+        }
+        assert!(tpe < random, "tpe={} random={}", tpe, random);
+    }
+}
@@ -0,0 +1,269 @@
+use super::Sampler;
+use crate::distributions::{Distribution, Distributions, ExternalRepr};
+use crate::study::Study;
+use anyhow::Result;
+use log::{debug, warn};
+use std::collections::HashMap;
+
+/// Logs every proposal of the wrapped sampler at debug level without altering it.
+pub struct TracingSampler<S: Sampler> {
+    inner: S,
+}
+
+impl<S: Sampler> TracingSampler<S> {
+    pub fn new(inner: S) -> Self {
+        TracingSampler { inner }
+    }
+}
+
+impl<S: Sampler> Sampler for TracingSampler<S> {
+    fn reseed(&mut self, seed: u64) {
+        debug!("reseeding sampler with seed={}", seed);
+        self.inner.reseed(seed);
+    }
+
+    fn before_trial(&mut self, study: &Study, trial_id: usize) -> Result<()> {
+        self.inner.before_trial(study, trial_id)
+    }
+
+    fn is_exhausted(&self, study: &Study) -> bool {
+        self.inner.is_exhausted(study)
+    }
+
+    fn infer_relative_search_space(
+        &self,
+        study: &Study,
+        trial_id: usize,
+    ) -> Result<HashMap<String, Distributions>> {
+        self.inner.infer_relative_search_space(study, trial_id)
+    }
+
+    fn sample_relative(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        search_space: &HashMap<String, Distributions>,
+    ) -> Result<HashMap<String, f64>> {
+        let values = self.inner.sample_relative(study, trial_id, search_space)?;
+        for (name, internal_repr) in &values {
+            debug!(
+                "sampled param={} jointly from {:?}: internal={}",
+                name, search_space[name], internal_repr
+            );
+        }
+        Ok(values)
+    }
+
+    fn sample_independent(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<f64> {
+        let value = self
+            .inner
+            .sample_independent(study, trial_id, name, distribution.clone())?;
+        let internal_repr = match &distribution {
+            Distributions::Uni(dist) => dist.to_internal_repr(value),
+            Distributions::LogUni(dist) => dist.to_internal_repr(value),
+            Distributions::DiscreteUni(dist) => dist.to_internal_repr(value),
+            _ => value,
+        };
+        debug!(
+            "sampled param={} from {:?}: internal={} external={}",
+            name, distribution, internal_repr, value
+        );
+        Ok(value)
+    }
+
+    fn sample_independent_int(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<i64> {
+        let value =
+            self.inner
+                .sample_independent_int(study, trial_id, name, distribution.clone())?;
+        let internal_repr = match &distribution {
+            Distributions::IntUni(dist) => dist.to_internal_repr(value),
+            _ => value as f64,
+        };
+        debug!(
+            "sampled param={} from {:?}: internal={} external={}",
+            name, distribution, internal_repr, value
+        );
+        Ok(value)
+    }
+
+    fn sample_independent_category(
+        &mut self,
+        study: &Study,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+    ) -> Result<ExternalRepr> {
+        let value =
+            self.inner
+                .sample_independent_category(study, trial_id, name, distribution.clone())?;
+        // The choice is only logged here; a sampler proposing an unknown one
+        // fails later when the trial stores the param.
+        match &distribution {
+            Distributions::Categorical(dist) => match dist.index_of(name, &value) {
+                Ok(idx) => debug!(
+                    "sampled param={} from {:?}: internal={} external={:?}",
+                    name, distribution, idx, value
+                ),
+                Err(err) => warn!(
+                    "sampled param={} from {:?}: external={:?} ({})",
+                    name, distribution, value, err
+                ),
+            },
+            _ => debug!(
+                "sampled param={} from {:?}: external={:?}",
+                name, distribution, value
+            ),
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samplers::RandomSampler;
+    use crate::storages::InMemoryStorage;
+    use crate::study::{create_study, StudyDirection};
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use std::sync::{Mutex, Once};
+
+    static RECORDS: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+    static INIT: Once = Once::new();
+
+    struct CaptureLogger;
+
+    impl Log for CaptureLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            RECORDS
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Records logged so far mentioning `name`, which each test keeps unique
+    /// since tests running in parallel share the logger.
+    fn records_of(name: &str) -> Vec<(Level, String)> {
+        INIT.call_once(|| {
+            log::set_logger(&CaptureLogger).unwrap();
+            log::set_max_level(LevelFilter::Debug);
+        });
+        let pattern = format!("param={} ", name);
+        RECORDS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, message)| message.contains(&pattern))
+            .cloned()
+            .collect()
+    }
+
+    fn suggest_all(sampler: impl Sampler + 'static, prefix: &str) -> Vec<String> {
+        let study = create_study(InMemoryStorage::new(), sampler, StudyDirection::Minimize);
+        let mut values = vec![];
+        for _ in 0..3 {
+            let mut trial = study.ask().unwrap();
+            let x = trial
+                .suggest_uniform(&format!("{}_x", prefix), 0.0, 1.0)
+                .unwrap();
+            let n = trial.suggest_int(&format!("{}_n", prefix), 0, 9).unwrap();
+            let c = trial
+                .suggest_categorical(
+                    &format!("{}_c", prefix),
+                    vec![
+                        ExternalRepr::Str("a".to_string()),
+                        ExternalRepr::Str("b".to_string()),
+                    ],
+                )
+                .unwrap();
+            values.push(format!("{} {} {:?}", x, n, c));
+            study.tell(trial, x).unwrap();
+        }
+        values
+    }
+
+    #[test]
+    fn tracing_logs_proposals_without_altering_them() {
+        records_of("traced_x");
+        let plain = suggest_all(RandomSampler::from_seed(11), "plain");
+        let traced = suggest_all(TracingSampler::new(RandomSampler::from_seed(11)), "traced");
+        assert_eq!(traced, plain);
+        for name in ["traced_x", "traced_n", "traced_c"].iter() {
+            let records = records_of(name);
+            assert_eq!(records.len(), 3, "{:?}", records);
+            assert!(records.iter().all(|(level, _)| *level == Level::Debug));
+        }
+        assert!(records_of("plain_x").is_empty());
+    }
+
+    /// Proposes a choice outside of every categorical distribution.
+    struct UnknownChoiceSampler;
+
+    impl Sampler for UnknownChoiceSampler {
+        fn reseed(&mut self, _seed: u64) {}
+
+        fn sample_independent(
+            &mut self,
+            _study: &Study,
+            _trial_id: usize,
+            _name: &str,
+            _distribution: Distributions,
+        ) -> Result<f64> {
+            Ok(0.0)
+        }
+
+        fn sample_independent_int(
+            &mut self,
+            _study: &Study,
+            _trial_id: usize,
+            _name: &str,
+            _distribution: Distributions,
+        ) -> Result<i64> {
+            Ok(0)
+        }
+
+        fn sample_independent_category(
+            &mut self,
+            _study: &Study,
+            _trial_id: usize,
+            _name: &str,
+            _distribution: Distributions,
+        ) -> Result<ExternalRepr> {
+            Ok(ExternalRepr::Str("unknown".to_string()))
+        }
+    }
+
+    #[test]
+    fn tracing_warns_about_unknown_choices() {
+        records_of("unknown_c");
+        let study = create_study(
+            InMemoryStorage::new(),
+            TracingSampler::new(UnknownChoiceSampler),
+            StudyDirection::Minimize,
+        );
+        let mut trial = study.ask().unwrap();
+        let choices = vec![ExternalRepr::Int(1), ExternalRepr::Int(2)];
+        assert!(trial.suggest_categorical("unknown_c", choices).is_err());
+        let records = records_of("unknown_c");
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].0, Level::Warn);
+    }
+}
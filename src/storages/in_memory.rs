@@ -0,0 +1,474 @@
+use super::Storage;
+use crate::distributions::Distributions;
+use crate::error::RustunaError;
+use crate::study::StudyDirection;
+use crate::trial::{FrozenTrial, FrozenTrialState, TrialSummary};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredStudy {
+    study_name: String,
+    directions: Vec<StudyDirection>,
+    user_attrs: HashMap<String, String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InMemoryStorage {
+    #[serde(default)]
+    studies: BTreeMap<usize, StoredStudy>,
+    trials: Vec<FrozenTrial>,
+    /// Per study, indices of the completed trials with the lowest and highest
+    /// values, updated as trials complete so `get_best_trial` needs no scan.
+    #[serde(skip)]
+    min_trial_idx: HashMap<usize, usize>,
+    #[serde(skip)]
+    max_trial_idx: HashMap<usize, usize>,
+    /// Maps trial ids to their index in `trials`.
+    #[serde(skip)]
+    trial_idx: HashMap<usize, usize>,
+    /// One past the highest trial id, since loaded ids need not be contiguous.
+    #[serde(skip)]
+    next_trial_id: usize,
+    /// Per study, one past the highest trial number.
+    #[serde(skip)]
+    next_trial_number: HashMap<usize, usize>,
+}
+impl Default for InMemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InMemoryStorage {
+    pub fn new() -> Self {
+        Self::from_trials(vec![])
+    }
+
+    pub fn from_trials(trials: Vec<FrozenTrial>) -> Self {
+        let mut storage = InMemoryStorage {
+            studies: BTreeMap::new(),
+            trials,
+            min_trial_idx: HashMap::new(),
+            max_trial_idx: HashMap::new(),
+            trial_idx: HashMap::new(),
+            next_trial_id: 0,
+            next_trial_number: HashMap::new(),
+        };
+        storage.build_indices();
+        storage
+    }
+
+    /// Appends `trials` to the study `study_id`, keeping their ids and numbers.
+    pub(crate) fn import_trials(&mut self, study_id: usize, trials: Vec<FrozenTrial>) {
+        for mut trial in trials {
+            trial.study_id = study_id;
+            self.trials.push(trial);
+        }
+        self.build_indices();
+    }
+
+    /// Rebuilds the fields skipped by serde from `trials`.
+    fn build_indices(&mut self) {
+        self.trial_idx.clear();
+        self.min_trial_idx.clear();
+        self.max_trial_idx.clear();
+        self.next_trial_number.clear();
+        for idx in 0..self.trials.len() {
+            let trial = &self.trials[idx];
+            self.trial_idx.insert(trial.trial_id, idx);
+            self.next_trial_id = self.next_trial_id.max(trial.trial_id + 1);
+            let next_number = self.next_trial_number.entry(trial.study_id).or_insert(0);
+            *next_number = (*next_number).max(trial.number + 1);
+            self.update_best_trial(idx);
+        }
+    }
+
+    /// Index of `trial_id` in `trials`, or an error if it is missing or finished.
+    fn updatable_idx(&self, trial_id: usize) -> Result<usize> {
+        let idx = *self
+            .trial_idx
+            .get(&trial_id)
+            .ok_or(RustunaError::TrialNotFound(trial_id))?;
+        if self.trials[idx].is_finised() {
+            return Err(RustunaError::TrialAlreadyFinished(trial_id).into());
+        }
+        Ok(idx)
+    }
+
+    fn stored_study(&self, study_id: usize) -> Result<&StoredStudy> {
+        self.studies
+            .get(&study_id)
+            .ok_or_else(|| RustunaError::StudyNotFound(study_id).into())
+    }
+
+    /// Folds the trial at `idx` into the cached best trials if it is completed.
+    fn update_best_trial(&mut self, idx: usize) {
+        let trial = &self.trials[idx];
+        if trial.state != FrozenTrialState::Completed || !trial.value.is_finite() {
+            return;
+        }
+        // Feasible trials rank first, then the objective decides.
+        let key = |trial: &FrozenTrial, sign: f64| (!trial.is_feasible(), sign * trial.value);
+        let study_id = trial.study_id;
+        let is_min = self
+            .min_trial_idx
+            .get(&study_id)
+            .is_none_or(|&best| key(trial, 1.0) < key(&self.trials[best], 1.0));
+        let is_max = self
+            .max_trial_idx
+            .get(&study_id)
+            .is_none_or(|&best| key(trial, -1.0) < key(&self.trials[best], -1.0));
+        if is_min {
+            self.min_trial_idx.insert(study_id, idx);
+        }
+        if is_max {
+            self.max_trial_idx.insert(study_id, idx);
+        }
+    }
+
+    pub fn save_json(&self, path: &str) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    pub fn load_json(path: &str) -> Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut storage: Self = serde_json::from_reader(reader)?;
+        storage.build_indices();
+        Ok(storage)
+    }
+
+    pub fn save_bincode(&self, path: &str) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(writer, self)?;
+        Ok(())
+    }
+
+    pub fn load_bincode(path: &str) -> Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut storage: Self = bincode::deserialize_from(reader)?;
+        storage.build_indices();
+        Ok(storage)
+    }
+}
+
+impl Storage for InMemoryStorage {
+    fn create_study(&mut self, study_name: &str, directions: &[StudyDirection]) -> Result<usize> {
+        if self.get_study_id(study_name).is_ok() {
+            return Err(RustunaError::DuplicateStudyName(study_name.to_string()).into());
+        }
+        let study_id = self.studies.keys().next_back().map_or(0, |id| id + 1);
+        self.studies.insert(
+            study_id,
+            StoredStudy {
+                study_name: study_name.to_string(),
+                directions: directions.to_vec(),
+                user_attrs: HashMap::new(),
+            },
+        );
+        Ok(study_id)
+    }
+
+    fn delete_study(&mut self, study_name: &str) -> Result<()> {
+        let study_id = self.get_study_id(study_name)?;
+        self.studies.remove(&study_id);
+        self.trials.retain(|trial| trial.study_id != study_id);
+        self.build_indices();
+        Ok(())
+    }
+
+    fn get_study_id(&self, study_name: &str) -> Result<usize> {
+        self.studies
+            .iter()
+            .find(|(_, study)| study.study_name == study_name)
+            .map(|(&study_id, _)| study_id)
+            .ok_or_else(|| RustunaError::StudyNameNotFound(study_name.to_string()).into())
+    }
+
+    fn get_study_directions(&self, study_id: usize) -> Result<Vec<StudyDirection>> {
+        Ok(self.stored_study(study_id)?.directions.clone())
+    }
+
+    fn get_all_study_names(&self) -> Result<Vec<String>> {
+        Ok(self
+            .studies
+            .values()
+            .map(|study| study.study_name.clone())
+            .collect())
+    }
+
+    fn create_new_trial(&mut self, study_id: usize) -> Result<usize> {
+        self.stored_study(study_id)?;
+        let trial_id = self.next_trial_id;
+        self.next_trial_id += 1;
+        let mut trial = FrozenTrial::new(trial_id, FrozenTrialState::Running, 0_f64);
+        trial.study_id = study_id;
+        let next_number = self.next_trial_number.entry(study_id).or_insert(0);
+        trial.number = *next_number;
+        *next_number += 1;
+        trial.datetime_start = Some(SystemTime::now());
+        self.trial_idx.insert(trial_id, self.trials.len());
+        self.trials.push(trial);
+        Ok(trial_id)
+    }
+
+    fn get_trial(&self, trial_id: usize) -> Result<FrozenTrial> {
+        match self.trial_idx.get(&trial_id) {
+            Some(&idx) => Ok(self.trials[idx].clone()),
+            None => Err(RustunaError::TrialNotFound(trial_id).into()),
+        }
+    }
+
+    fn get_all_trials(&self) -> Result<Vec<FrozenTrial>> {
+        Ok(self.trials.clone())
+    }
+
+    fn get_study_trials(&self, study_id: usize) -> Result<Vec<FrozenTrial>> {
+        Ok(self
+            .trials
+            .iter()
+            .filter(|trial| trial.study_id == study_id)
+            .cloned()
+            .collect())
+    }
+
+    fn get_study_trial_summaries(&self, study_id: usize) -> Result<Vec<TrialSummary>> {
+        Ok(self
+            .trials
+            .iter()
+            .filter(|trial| trial.study_id == study_id)
+            .map(FrozenTrial::summary)
+            .collect())
+    }
+
+    fn get_best_trial(
+        &self,
+        study_id: usize,
+        direction: StudyDirection,
+    ) -> Result<Option<FrozenTrial>> {
+        let best_idx = match direction {
+            StudyDirection::Minimize => self.min_trial_idx.get(&study_id),
+            StudyDirection::Maximize => self.max_trial_idx.get(&study_id),
+        };
+        Ok(best_idx.map(|&idx| self.trials[idx].clone()))
+    }
+
+    fn set_trial_value(&mut self, trial_id: usize, value: f64) -> Result<()> {
+        self.set_trial_values(trial_id, &[value])
+    }
+
+    fn set_trial_values(&mut self, trial_id: usize, values: &[f64]) -> Result<()> {
+        let target_idx = self.updatable_idx(trial_id)?;
+        let value = *values
+            .first()
+            .ok_or_else(|| anyhow!("No values given for trial id: {}", trial_id))?;
+        self.trials[target_idx].value = value;
+        self.trials[target_idx].values = values.to_vec();
+        self.update_best_trial(target_idx);
+        Ok(())
+    }
+
+    fn set_trial_state(&mut self, trial_id: usize, state: FrozenTrialState) -> Result<()> {
+        let target_idx = self.updatable_idx(trial_id)?;
+        self.trials[target_idx].state = state;
+        if state != FrozenTrialState::Running {
+            self.trials[target_idx].datetime_complete = Some(SystemTime::now());
+        }
+        self.update_best_trial(target_idx);
+        Ok(())
+    }
+
+    fn set_trial_param(
+        &mut self,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+        value: f64,
+    ) -> Result<()> {
+        let target_idx = self.updatable_idx(trial_id)?;
+        self.trials[target_idx]
+            .internal_params
+            .insert(name.to_string(), value);
+        self.trials[target_idx]
+            .distributions
+            .insert(name.to_string(), distribution);
+        Ok(())
+    }
+
+    fn set_trial_intermediate_value(
+        &mut self,
+        trial_id: usize,
+        step: usize,
+        value: f64,
+    ) -> Result<()> {
+        let target_idx = self.updatable_idx(trial_id)?;
+        self.trials[target_idx]
+            .intermediate_values
+            .insert(step, value);
+        Ok(())
+    }
+
+    fn set_trial_seed(&mut self, trial_id: usize, seed: u64) -> Result<()> {
+        let target_idx = self.updatable_idx(trial_id)?;
+        self.trials[target_idx].seed = Some(seed);
+        Ok(())
+    }
+
+    fn set_trial_user_attr(&mut self, trial_id: usize, key: &str, value: &str) -> Result<()> {
+        let target_idx = self.updatable_idx(trial_id)?;
+        self.trials[target_idx]
+            .user_attrs
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn set_trial_constraints(&mut self, trial_id: usize, constraints: &[f64]) -> Result<()> {
+        let target_idx = self.updatable_idx(trial_id)?;
+        self.trials[target_idx].constraints = constraints.to_vec();
+        Ok(())
+    }
+
+    fn record_heartbeat(&mut self, trial_id: usize) -> Result<()> {
+        let target_idx = self.updatable_idx(trial_id)?;
+        self.trials[target_idx].heartbeat = Some(SystemTime::now());
+        Ok(())
+    }
+
+    fn fail_stale_trials(&mut self, study_id: usize, max_age: Duration) -> Result<Vec<usize>> {
+        self.stored_study(study_id)?;
+        let now = SystemTime::now();
+        let stale: Vec<usize> = self
+            .trials
+            .iter()
+            .filter(|trial| trial.study_id == study_id && !trial.is_finised())
+            .filter(|trial| {
+                trial.heartbeat.is_some_and(|heartbeat| {
+                    now.duration_since(heartbeat).is_ok_and(|age| age > max_age)
+                })
+            })
+            .map(|trial| trial.trial_id)
+            .collect();
+        for &trial_id in &stale {
+            self.set_trial_state(trial_id, FrozenTrialState::Failed)?;
+        }
+        Ok(stale)
+    }
+
+    fn set_study_user_attr(&mut self, study_id: usize, key: &str, value: &str) -> Result<()> {
+        self.studies
+            .get_mut(&study_id)
+            .ok_or(RustunaError::StudyNotFound(study_id))?
+            .user_attrs
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn get_study_user_attrs(&self, study_id: usize) -> Result<HashMap<String, String>> {
+        Ok(self
+            .studies
+            .get(&study_id)
+            .map(|study| study.user_attrs.clone())
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trip_keeps_nan_values() {
+        let mut storage = InMemoryStorage::new();
+        let directions = [StudyDirection::Minimize, StudyDirection::Maximize];
+        let study_id = storage.create_study("study", &directions).unwrap();
+        let trial_id = storage.create_new_trial(study_id).unwrap();
+        storage
+            .set_trial_intermediate_value(trial_id, 0, f64::NAN)
+            .unwrap();
+        storage
+            .set_trial_intermediate_value(trial_id, 1, 0.5)
+            .unwrap();
+        storage
+            .set_trial_constraints(trial_id, &[f64::NAN, -1.0])
+            .unwrap();
+        storage
+            .set_trial_values(trial_id, &[f64::NAN, 2.0])
+            .unwrap();
+        storage
+            .set_trial_state(trial_id, FrozenTrialState::Failed)
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("rustuna-nan-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        storage.save_json(path).unwrap();
+        let loaded = InMemoryStorage::load_json(path);
+        std::fs::remove_file(path).unwrap();
+
+        let trial = loaded.unwrap().get_trial(trial_id).unwrap();
+        assert!(trial.value.is_nan());
+        assert!(trial.values[0].is_nan());
+        assert_eq!(trial.values[1], 2.0);
+        assert!(trial.constraints[0].is_nan());
+        assert_eq!(trial.constraints[1], -1.0);
+        assert!(trial.intermediate_values[&0].is_nan());
+        assert_eq!(trial.intermediate_values[&1], 0.5);
+    }
+
+    #[test]
+    fn trial_numbers_count_per_study() {
+        let mut storage = InMemoryStorage::new();
+        let directions = [StudyDirection::Minimize];
+        let first = storage.create_study("first", &directions).unwrap();
+        let second = storage.create_study("second", &directions).unwrap();
+        let mut numbers = vec![];
+        for &study_id in [first, second, first, first, second].iter() {
+            let trial_id = storage.create_new_trial(study_id).unwrap();
+            numbers.push(storage.get_trial(trial_id).unwrap().number);
+        }
+        assert_eq!(numbers, vec![0, 0, 1, 2, 1]);
+
+        let json = serde_json::to_string(&storage).unwrap();
+        let mut loaded: InMemoryStorage = serde_json::from_str(&json).unwrap();
+        loaded.build_indices();
+        let trial_id = loaded.create_new_trial(first).unwrap();
+        assert_eq!(loaded.get_trial(trial_id).unwrap().number, 3);
+    }
+
+    #[test]
+    fn trial_index_follows_ids_that_are_not_positions() {
+        let mut storage = InMemoryStorage::new();
+        let directions = [StudyDirection::Minimize];
+        let first = storage.create_study("first", &directions).unwrap();
+        let second = storage.create_study("second", &directions).unwrap();
+        let mut kept = vec![];
+        for i in 0..10_000 {
+            let study_id = if i % 3 == 0 { first } else { second };
+            let trial_id = storage.create_new_trial(study_id).unwrap();
+            if study_id == second {
+                kept.push(trial_id);
+            }
+        }
+        // Deleting a study shifts the remaining trials, so ids no longer match
+        // their positions in `trials`.
+        storage.delete_study("first").unwrap();
+        assert_eq!(storage.trial_idx.len(), kept.len());
+        for &trial_id in &kept {
+            let idx = storage.trial_idx[&trial_id];
+            assert_eq!(storage.trials[idx].trial_id, trial_id);
+        }
+        let last = *kept.last().unwrap();
+        assert_ne!(storage.trial_idx[&last], last);
+        storage.set_trial_value(last, 1.0).unwrap();
+        storage
+            .set_trial_state(last, FrozenTrialState::Completed)
+            .unwrap();
+        assert_eq!(storage.get_trial(last).unwrap().value, 1.0);
+        assert!(storage.get_trial(0).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+use crate::distributions::Distributions;
+use crate::study::StudyDirection;
+use crate::trial::{FrozenTrial, FrozenTrialState, TrialSummary};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Duration;
+
+mod in_memory;
+mod sqlite;
+
+pub use in_memory::InMemoryStorage;
+pub use sqlite::SqliteStorage;
+
+/// Backend persisting the trials of one or more named studies. Trial ids are
+/// unique across studies. Updating a finished trial must be rejected with an error.
+pub trait Storage: Send {
+    /// Registers a study under a name not used by any other study in this storage.
+    fn create_study(&mut self, study_name: &str, directions: &[StudyDirection]) -> Result<usize>;
+    /// Removes a study together with all of its trials.
+    fn delete_study(&mut self, study_name: &str) -> Result<()>;
+    fn get_study_id(&self, study_name: &str) -> Result<usize>;
+    fn get_study_directions(&self, study_id: usize) -> Result<Vec<StudyDirection>>;
+    fn get_all_study_names(&self) -> Result<Vec<String>>;
+    fn create_new_trial(&mut self, study_id: usize) -> Result<usize>;
+    fn get_trial(&self, trial_id: usize) -> Result<FrozenTrial>;
+    /// Trials of every study in this storage.
+    fn get_all_trials(&self) -> Result<Vec<FrozenTrial>>;
+    fn get_study_trials(&self, study_id: usize) -> Result<Vec<FrozenTrial>>;
+    /// Trials of the study without their params and other per-trial maps.
+    fn get_study_trial_summaries(&self, study_id: usize) -> Result<Vec<TrialSummary>> {
+        Ok(self
+            .get_study_trials(study_id)?
+            .iter()
+            .map(FrozenTrial::summary)
+            .collect())
+    }
+    /// Best completed trial, preferring feasible trials over ones violating constraints.
+    fn get_best_trial(
+        &self,
+        study_id: usize,
+        direction: StudyDirection,
+    ) -> Result<Option<FrozenTrial>>;
+    fn set_trial_value(&mut self, trial_id: usize, value: f64) -> Result<()>;
+    /// Records the values of a multi-objective trial, the first becoming its `value`.
+    fn set_trial_values(&mut self, trial_id: usize, values: &[f64]) -> Result<()>;
+    fn set_trial_state(&mut self, trial_id: usize, state: FrozenTrialState) -> Result<()>;
+    fn set_trial_param(
+        &mut self,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+        value: f64,
+    ) -> Result<()>;
+    fn set_trial_intermediate_value(
+        &mut self,
+        trial_id: usize,
+        step: usize,
+        value: f64,
+    ) -> Result<()>;
+    fn set_trial_seed(&mut self, trial_id: usize, seed: u64) -> Result<()>;
+    fn set_trial_user_attr(&mut self, trial_id: usize, key: &str, value: &str) -> Result<()>;
+    fn set_trial_constraints(&mut self, trial_id: usize, constraints: &[f64]) -> Result<()>;
+    /// Sets the heartbeat of a running trial to now.
+    fn record_heartbeat(&mut self, trial_id: usize) -> Result<()>;
+    /// Marks the running trials of the study whose heartbeat is older than
+    /// `max_age` as failed, returning their ids. Trials without a heartbeat are kept.
+    fn fail_stale_trials(&mut self, study_id: usize, max_age: Duration) -> Result<Vec<usize>>;
+    fn set_study_user_attr(&mut self, study_id: usize, key: &str, value: &str) -> Result<()>;
+    fn get_study_user_attrs(&self, study_id: usize) -> Result<HashMap<String, String>>;
+}
@@ -0,0 +1,693 @@
+use super::Storage;
+use crate::distributions::Distributions;
+use crate::error::RustunaError;
+use crate::study::StudyDirection;
+use crate::trial::{FrozenTrial, FrozenTrialState, TrialSummary};
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Storage persisting trials to a SQLite database file, so that a study can be
+/// resumed after the process restarts.
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, creating it and its tables if missing.
+    pub fn open(path: &str) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS studies (
+                study_id INTEGER PRIMARY KEY,
+                study_name TEXT NOT NULL UNIQUE
+            );
+            CREATE TABLE IF NOT EXISTS study_directions (
+                study_id INTEGER NOT NULL REFERENCES studies(study_id),
+                objective INTEGER NOT NULL,
+                direction INTEGER NOT NULL,
+                PRIMARY KEY (study_id, objective)
+            );
+            CREATE TABLE IF NOT EXISTS trials (
+                trial_id INTEGER PRIMARY KEY,
+                study_id INTEGER NOT NULL REFERENCES studies(study_id),
+                number INTEGER NOT NULL,
+                state INTEGER NOT NULL,
+                value REAL,
+                seed INTEGER,
+                datetime_start INTEGER,
+                datetime_complete INTEGER,
+                heartbeat INTEGER
+            );
+            CREATE TABLE IF NOT EXISTS trial_params (
+                trial_id INTEGER NOT NULL REFERENCES trials(trial_id),
+                name TEXT NOT NULL,
+                value REAL NOT NULL,
+                distribution BLOB NOT NULL,
+                PRIMARY KEY (trial_id, name)
+            );
+            CREATE TABLE IF NOT EXISTS trial_intermediate_values (
+                trial_id INTEGER NOT NULL REFERENCES trials(trial_id),
+                step INTEGER NOT NULL,
+                value REAL,
+                PRIMARY KEY (trial_id, step)
+            );
+            CREATE TABLE IF NOT EXISTS trial_values (
+                trial_id INTEGER NOT NULL REFERENCES trials(trial_id),
+                objective INTEGER NOT NULL,
+                value REAL,
+                PRIMARY KEY (trial_id, objective)
+            );
+            CREATE TABLE IF NOT EXISTS trial_user_attrs (
+                trial_id INTEGER NOT NULL REFERENCES trials(trial_id),
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (trial_id, key)
+            );
+            CREATE TABLE IF NOT EXISTS trial_constraints (
+                trial_id INTEGER NOT NULL REFERENCES trials(trial_id),
+                constraint_idx INTEGER NOT NULL,
+                value REAL,
+                PRIMARY KEY (trial_id, constraint_idx)
+            );
+            CREATE TABLE IF NOT EXISTS study_user_attrs (
+                study_id INTEGER NOT NULL REFERENCES studies(study_id),
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (study_id, key)
+            );
+            CREATE INDEX IF NOT EXISTS trials_study_state_value
+                ON trials (study_id, state, value);",
+        )?;
+        Ok(SqliteStorage { connection })
+    }
+
+    /// Errors unless `trial_id` exists and is still running.
+    fn check_trial_is_updatable(&self, trial_id: usize) -> Result<()> {
+        let state: Option<i64> = self
+            .connection
+            .query_row(
+                "SELECT state FROM trials WHERE trial_id = ?1",
+                params![trial_id as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match state {
+            None => Err(RustunaError::TrialNotFound(trial_id).into()),
+            Some(state) if state_from_i64(state)? != FrozenTrialState::Running => {
+                Err(RustunaError::TrialAlreadyFinished(trial_id).into())
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn load_trial_summaries(&self, study_id: usize) -> Result<Vec<TrialSummary>> {
+        let mut statement = self.connection.prepare(
+            "SELECT trial_id, number, state, value, datetime_start, datetime_complete
+             FROM trials WHERE study_id = ?1 ORDER BY trial_id",
+        )?;
+        let mut rows = statement.query(params![study_id as i64])?;
+        let mut summaries = vec![];
+        while let Some(row) = rows.next()? {
+            summaries.push(TrialSummary {
+                trial_id: row.get::<_, i64>(0)? as usize,
+                number: row.get::<_, i64>(1)? as usize,
+                state: state_from_i64(row.get(2)?)?,
+                // SQLite stores NaN as NULL.
+                value: row.get::<_, Option<f64>>(3)?.unwrap_or(f64::NAN),
+                datetime_start: row.get::<_, Option<i64>>(4)?.map(time_from_i64),
+                datetime_complete: row.get::<_, Option<i64>>(5)?.map(time_from_i64),
+            });
+        }
+        Ok(summaries)
+    }
+
+    /// Trials matching `filter`, a condition on the `trials` table aliased `t`,
+    /// ordered by id. Each table is read once however many trials match.
+    fn load_trials(
+        &self,
+        filter: &str,
+        params: impl rusqlite::Params + Copy,
+    ) -> Result<Vec<FrozenTrial>> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT trial_id, state, value, seed, datetime_start, datetime_complete, number,
+             study_id, heartbeat FROM trials t WHERE {} ORDER BY trial_id",
+            filter
+        ))?;
+        let mut rows = statement.query(params)?;
+        let mut trials = vec![];
+        while let Some(row) = rows.next()? {
+            // SQLite stores NaN as NULL.
+            let value = row.get::<_, Option<f64>>(2)?.unwrap_or(f64::NAN);
+            let mut trial = FrozenTrial::new(
+                row.get::<_, i64>(0)? as usize,
+                state_from_i64(row.get(1)?)?,
+                value,
+            );
+            trial.seed = row.get::<_, Option<i64>>(3)?.map(|seed| seed as u64);
+            trial.datetime_start = row.get::<_, Option<i64>>(4)?.map(time_from_i64);
+            trial.datetime_complete = row.get::<_, Option<i64>>(5)?.map(time_from_i64);
+            trial.number = row.get::<_, i64>(6)? as usize;
+            trial.study_id = row.get::<_, i64>(7)? as usize;
+            trial.heartbeat = row.get::<_, Option<i64>>(8)?.map(time_from_i64);
+            trials.push(trial);
+        }
+        let idx: HashMap<usize, usize> = trials
+            .iter()
+            .enumerate()
+            .map(|(idx, trial)| (trial.trial_id, idx))
+            .collect();
+        // Rows of the per-trial table `table`, with the trial they belong to.
+        let query = |columns: &str, table: &str, order: &str| -> Result<rusqlite::Statement> {
+            Ok(self.connection.prepare(&format!(
+                "SELECT x.trial_id, {} FROM {} x JOIN trials t ON t.trial_id = x.trial_id
+                 WHERE {} ORDER BY x.trial_id, {}",
+                columns, table, filter, order
+            ))?)
+        };
+
+        let mut statement = query("x.name, x.value, x.distribution", "trial_params", "x.name")?;
+        let mut rows = statement.query(params)?;
+        while let Some(row) = rows.next()? {
+            let trial = &mut trials[idx[&(row.get::<_, i64>(0)? as usize)]];
+            let name: String = row.get(1)?;
+            let distribution: Vec<u8> = row.get(3)?;
+            trial.internal_params.insert(name.clone(), row.get(2)?);
+            trial
+                .distributions
+                .insert(name, bincode::deserialize::<Distributions>(&distribution)?);
+        }
+
+        let mut statement = query("x.step, x.value", "trial_intermediate_values", "x.step")?;
+        let mut rows = statement.query(params)?;
+        while let Some(row) = rows.next()? {
+            let trial = &mut trials[idx[&(row.get::<_, i64>(0)? as usize)]];
+            let step: i64 = row.get(1)?;
+            let value: Option<f64> = row.get(2)?;
+            trial
+                .intermediate_values
+                .insert(step as usize, value.unwrap_or(f64::NAN));
+        }
+
+        let mut statement = query("x.value", "trial_values", "x.objective")?;
+        let mut rows = statement.query(params)?;
+        while let Some(row) = rows.next()? {
+            let trial = &mut trials[idx[&(row.get::<_, i64>(0)? as usize)]];
+            let value: Option<f64> = row.get(1)?;
+            trial.values.push(value.unwrap_or(f64::NAN));
+        }
+
+        let mut statement = query("x.key, x.value", "trial_user_attrs", "x.key")?;
+        let mut rows = statement.query(params)?;
+        while let Some(row) = rows.next()? {
+            let trial = &mut trials[idx[&(row.get::<_, i64>(0)? as usize)]];
+            trial.user_attrs.insert(row.get(1)?, row.get(2)?);
+        }
+
+        let mut statement = query("x.value", "trial_constraints", "x.constraint_idx")?;
+        let mut rows = statement.query(params)?;
+        while let Some(row) = rows.next()? {
+            let trial = &mut trials[idx[&(row.get::<_, i64>(0)? as usize)]];
+            let value: Option<f64> = row.get(1)?;
+            trial.constraints.push(value.unwrap_or(f64::NAN));
+        }
+        Ok(trials)
+    }
+
+    fn check_study_exists(&self, study_id: usize) -> Result<()> {
+        self.connection
+            .query_row(
+                "SELECT study_id FROM studies WHERE study_id = ?1",
+                params![study_id as i64],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?
+            .ok_or(RustunaError::StudyNotFound(study_id))?;
+        Ok(())
+    }
+
+    fn load_trial_ids(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<usize>> {
+        let mut statement = self.connection.prepare(sql)?;
+        let ids = statement
+            .query_map(params, |row| row.get::<_, i64>(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+        Ok(ids.into_iter().map(|id| id as usize).collect())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn create_study(&mut self, study_name: &str, directions: &[StudyDirection]) -> Result<usize> {
+        if self.get_study_id(study_name).is_ok() {
+            return Err(RustunaError::DuplicateStudyName(study_name.to_string()).into());
+        }
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO studies (study_name) VALUES (?1)",
+            params![study_name],
+        )?;
+        let study_id = transaction.last_insert_rowid();
+        for (objective, &direction) in directions.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO study_directions (study_id, objective, direction)
+                 VALUES (?1, ?2, ?3)",
+                params![study_id, objective as i64, direction_to_i64(direction)],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(study_id as usize)
+    }
+
+    fn delete_study(&mut self, study_name: &str) -> Result<()> {
+        let study_id = self.get_study_id(study_name)? as i64;
+        let transaction = self.connection.transaction()?;
+        for table in [
+            "trial_params",
+            "trial_intermediate_values",
+            "trial_values",
+            "trial_user_attrs",
+            "trial_constraints",
+        ] {
+            transaction.execute(
+                &format!(
+                    "DELETE FROM {} WHERE trial_id IN
+                     (SELECT trial_id FROM trials WHERE study_id = ?1)",
+                    table
+                ),
+                params![study_id],
+            )?;
+        }
+        for table in ["trials", "study_user_attrs", "study_directions", "studies"] {
+            transaction.execute(
+                &format!("DELETE FROM {} WHERE study_id = ?1", table),
+                params![study_id],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn get_study_id(&self, study_name: &str) -> Result<usize> {
+        let study_id: Option<i64> = self
+            .connection
+            .query_row(
+                "SELECT study_id FROM studies WHERE study_name = ?1",
+                params![study_name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        study_id
+            .map(|study_id| study_id as usize)
+            .ok_or_else(|| RustunaError::StudyNameNotFound(study_name.to_string()).into())
+    }
+
+    fn get_study_directions(&self, study_id: usize) -> Result<Vec<StudyDirection>> {
+        self.check_study_exists(study_id)?;
+        let mut statement = self.connection.prepare(
+            "SELECT direction FROM study_directions WHERE study_id = ?1 ORDER BY objective",
+        )?;
+        let directions = statement
+            .query_map(params![study_id as i64], |row| row.get::<_, i64>(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+        directions.into_iter().map(direction_from_i64).collect()
+    }
+
+    fn get_all_study_names(&self) -> Result<Vec<String>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT study_name FROM studies ORDER BY study_id")?;
+        let names = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(names)
+    }
+
+    fn create_new_trial(&mut self, study_id: usize) -> Result<usize> {
+        self.check_study_exists(study_id)?;
+        self.connection.execute(
+            "INSERT INTO trials (study_id, number, state, value, datetime_start)
+             VALUES (?1, (SELECT COUNT(*) FROM trials WHERE study_id = ?1), ?2, ?3, ?4)",
+            params![
+                study_id as i64,
+                state_to_i64(FrozenTrialState::Running),
+                0_f64,
+                time_to_i64(SystemTime::now())
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid() as usize)
+    }
+
+    fn get_trial(&self, trial_id: usize) -> Result<FrozenTrial> {
+        self.load_trials("t.trial_id = ?1", [trial_id as i64])?
+            .pop()
+            .ok_or_else(|| RustunaError::TrialNotFound(trial_id).into())
+    }
+
+    fn get_all_trials(&self) -> Result<Vec<FrozenTrial>> {
+        self.load_trials("1", [])
+    }
+
+    fn get_study_trials(&self, study_id: usize) -> Result<Vec<FrozenTrial>> {
+        self.load_trials("t.study_id = ?1", [study_id as i64])
+    }
+
+    fn get_study_trial_summaries(&self, study_id: usize) -> Result<Vec<TrialSummary>> {
+        self.load_trial_summaries(study_id)
+    }
+
+    fn get_best_trial(
+        &self,
+        study_id: usize,
+        direction: StudyDirection,
+    ) -> Result<Option<FrozenTrial>> {
+        let order = match direction {
+            StudyDirection::Minimize => "ASC",
+            StudyDirection::Maximize => "DESC",
+        };
+        // Infinities compare beyond 9e999, and NaN constraints are stored as NULL.
+        let sql = format!(
+            "SELECT trial_id FROM trials
+             WHERE study_id = ?1 AND state = {} AND value IS NOT NULL AND abs(value) < 9e999
+             ORDER BY EXISTS (
+                 SELECT 1 FROM trial_constraints
+                 WHERE trial_constraints.trial_id = trials.trial_id
+                     AND (value IS NULL OR value > 0)
+             ), value {} LIMIT 1",
+            state_to_i64(FrozenTrialState::Completed),
+            order
+        );
+        let ids = self.load_trial_ids(&sql, params![study_id as i64])?;
+        ids.first().map(|&id| self.get_trial(id)).transpose()
+    }
+
+    fn set_trial_value(&mut self, trial_id: usize, value: f64) -> Result<()> {
+        self.set_trial_values(trial_id, &[value])
+    }
+
+    fn set_trial_values(&mut self, trial_id: usize, values: &[f64]) -> Result<()> {
+        self.check_trial_is_updatable(trial_id)?;
+        let value = *values
+            .first()
+            .ok_or_else(|| anyhow!("No values given for trial id: {}", trial_id))?;
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "UPDATE trials SET value = ?1 WHERE trial_id = ?2",
+            params![value, trial_id as i64],
+        )?;
+        transaction.execute(
+            "DELETE FROM trial_values WHERE trial_id = ?1",
+            params![trial_id as i64],
+        )?;
+        for (objective, value) in values.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO trial_values (trial_id, objective, value) VALUES (?1, ?2, ?3)",
+                params![trial_id as i64, objective as i64, value],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn set_trial_state(&mut self, trial_id: usize, state: FrozenTrialState) -> Result<()> {
+        self.check_trial_is_updatable(trial_id)?;
+        let datetime_complete =
+            (state != FrozenTrialState::Running).then(|| time_to_i64(SystemTime::now()));
+        self.connection.execute(
+            "UPDATE trials SET state = ?1, datetime_complete = ?2 WHERE trial_id = ?3",
+            params![state_to_i64(state), datetime_complete, trial_id as i64],
+        )?;
+        Ok(())
+    }
+
+    fn set_trial_param(
+        &mut self,
+        trial_id: usize,
+        name: &str,
+        distribution: Distributions,
+        value: f64,
+    ) -> Result<()> {
+        self.check_trial_is_updatable(trial_id)?;
+        self.connection.execute(
+            "INSERT OR REPLACE INTO trial_params (trial_id, name, value, distribution)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                trial_id as i64,
+                name,
+                value,
+                bincode::serialize(&distribution)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn set_trial_intermediate_value(
+        &mut self,
+        trial_id: usize,
+        step: usize,
+        value: f64,
+    ) -> Result<()> {
+        self.check_trial_is_updatable(trial_id)?;
+        self.connection.execute(
+            "INSERT OR REPLACE INTO trial_intermediate_values (trial_id, step, value)
+             VALUES (?1, ?2, ?3)",
+            params![trial_id as i64, step as i64, value],
+        )?;
+        Ok(())
+    }
+
+    fn set_trial_seed(&mut self, trial_id: usize, seed: u64) -> Result<()> {
+        self.check_trial_is_updatable(trial_id)?;
+        self.connection.execute(
+            "UPDATE trials SET seed = ?1 WHERE trial_id = ?2",
+            params![seed as i64, trial_id as i64],
+        )?;
+        Ok(())
+    }
+
+    fn set_trial_user_attr(&mut self, trial_id: usize, key: &str, value: &str) -> Result<()> {
+        self.check_trial_is_updatable(trial_id)?;
+        self.connection.execute(
+            "INSERT OR REPLACE INTO trial_user_attrs (trial_id, key, value) VALUES (?1, ?2, ?3)",
+            params![trial_id as i64, key, value],
+        )?;
+        Ok(())
+    }
+
+    fn set_trial_constraints(&mut self, trial_id: usize, constraints: &[f64]) -> Result<()> {
+        self.check_trial_is_updatable(trial_id)?;
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "DELETE FROM trial_constraints WHERE trial_id = ?1",
+            params![trial_id as i64],
+        )?;
+        for (constraint_idx, value) in constraints.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO trial_constraints (trial_id, constraint_idx, value)
+                 VALUES (?1, ?2, ?3)",
+                params![trial_id as i64, constraint_idx as i64, value],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn record_heartbeat(&mut self, trial_id: usize) -> Result<()> {
+        self.check_trial_is_updatable(trial_id)?;
+        self.connection.execute(
+            "UPDATE trials SET heartbeat = ?1 WHERE trial_id = ?2",
+            params![time_to_i64(SystemTime::now()), trial_id as i64],
+        )?;
+        Ok(())
+    }
+
+    fn fail_stale_trials(&mut self, study_id: usize, max_age: Duration) -> Result<Vec<usize>> {
+        self.check_study_exists(study_id)?;
+        let deadline = SystemTime::now()
+            .checked_sub(max_age)
+            .map_or(i64::MIN, time_to_i64);
+        let stale = self.load_trial_ids(
+            "SELECT trial_id FROM trials
+             WHERE study_id = ?1 AND state = ?2 AND heartbeat < ?3 ORDER BY trial_id",
+            params![
+                study_id as i64,
+                state_to_i64(FrozenTrialState::Running),
+                deadline
+            ],
+        )?;
+        for &trial_id in &stale {
+            self.set_trial_state(trial_id, FrozenTrialState::Failed)?;
+        }
+        Ok(stale)
+    }
+
+    fn set_study_user_attr(&mut self, study_id: usize, key: &str, value: &str) -> Result<()> {
+        self.check_study_exists(study_id)?;
+        self.connection.execute(
+            "INSERT OR REPLACE INTO study_user_attrs (study_id, key, value) VALUES (?1, ?2, ?3)",
+            params![study_id as i64, key, value],
+        )?;
+        Ok(())
+    }
+
+    fn get_study_user_attrs(&self, study_id: usize) -> Result<HashMap<String, String>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT key, value FROM study_user_attrs WHERE study_id = ?1")?;
+        let attrs = statement
+            .query_map(params![study_id as i64], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<rusqlite::Result<HashMap<String, String>>>()?;
+        Ok(attrs)
+    }
+}
+
+fn state_to_i64(state: FrozenTrialState) -> i64 {
+    match state {
+        FrozenTrialState::Running => 0,
+        FrozenTrialState::Completed => 1,
+        FrozenTrialState::Failed => 2,
+        FrozenTrialState::Pruned => 3,
+    }
+}
+
+fn state_from_i64(state: i64) -> Result<FrozenTrialState> {
+    match state {
+        0 => Ok(FrozenTrialState::Running),
+        1 => Ok(FrozenTrialState::Completed),
+        2 => Ok(FrozenTrialState::Failed),
+        3 => Ok(FrozenTrialState::Pruned),
+        _ => Err(anyhow!("Unknown trial state in sqlite: {}", state)),
+    }
+}
+
+fn direction_to_i64(direction: StudyDirection) -> i64 {
+    match direction {
+        StudyDirection::Minimize => 0,
+        StudyDirection::Maximize => 1,
+    }
+}
+
+fn direction_from_i64(direction: i64) -> Result<StudyDirection> {
+    match direction {
+        0 => Ok(StudyDirection::Minimize),
+        1 => Ok(StudyDirection::Maximize),
+        _ => Err(anyhow!("Unknown study direction in sqlite: {}", direction)),
+    }
+}
+
+/// Timestamps are stored as microseconds since the unix epoch.
+fn time_to_i64(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_micros() as i64)
+}
+
+fn time_from_i64(micros: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_micros(micros as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distributions::UniformDistribution;
+
+    #[test]
+    fn nan_intermediate_values_read_back_as_nan() {
+        let mut storage = SqliteStorage::open(":memory:").unwrap();
+        let study_id = storage
+            .create_study("study", &[StudyDirection::Minimize])
+            .unwrap();
+        let trial_id = storage.create_new_trial(study_id).unwrap();
+        storage
+            .set_trial_intermediate_value(trial_id, 0, f64::NAN)
+            .unwrap();
+        storage
+            .set_trial_intermediate_value(trial_id, 1, 0.5)
+            .unwrap();
+        let trial = storage.get_trial(trial_id).unwrap();
+        assert!(trial.intermediate_values[&0].is_nan());
+        assert_eq!(trial.intermediate_values[&1], 0.5);
+    }
+
+    #[test]
+    fn study_trials_match_trials_read_one_by_one() {
+        let mut storage = SqliteStorage::open(":memory:").unwrap();
+        let directions = [StudyDirection::Minimize, StudyDirection::Maximize];
+        let study_ids = [
+            storage.create_study("a", &directions).unwrap(),
+            storage.create_study("b", &directions).unwrap(),
+        ];
+        for i in 0..6 {
+            let study_id = study_ids[i % 2];
+            let trial_id = storage.create_new_trial(study_id).unwrap();
+            let distribution = Distributions::Uni(UniformDistribution {
+                low: 0.0,
+                high: 10.0,
+            });
+            storage
+                .set_trial_param(trial_id, "x", distribution, i as f64)
+                .unwrap();
+            for step in 0..i {
+                storage
+                    .set_trial_intermediate_value(trial_id, step, step as f64)
+                    .unwrap();
+            }
+            storage
+                .set_trial_user_attr(trial_id, "i", &i.to_string())
+                .unwrap();
+            storage
+                .set_trial_constraints(trial_id, &[i as f64, -1.0])
+                .unwrap();
+            if i != 4 {
+                storage
+                    .set_trial_values(trial_id, &[i as f64, f64::NAN])
+                    .unwrap();
+                storage
+                    .set_trial_state(trial_id, FrozenTrialState::Completed)
+                    .unwrap();
+            }
+        }
+        for &study_id in &study_ids {
+            let trials = storage.get_study_trials(study_id).unwrap();
+            assert_eq!(trials.len(), 3);
+            for trial in trials {
+                let expected = storage.get_trial(trial.trial_id).unwrap();
+                assert_eq!(trial.study_id, study_id);
+                assert_eq!(format!("{:?}", trial), format!("{:?}", expected));
+                assert_eq!(
+                    trial.values.is_empty(),
+                    trial.state == FrozenTrialState::Running
+                );
+            }
+        }
+        assert_eq!(storage.get_all_trials().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn read_errors_are_returned() {
+        let mut storage = SqliteStorage::open(":memory:").unwrap();
+        let study_id = storage
+            .create_study("study", &[StudyDirection::Minimize])
+            .unwrap();
+        let trial_id = storage.create_new_trial(study_id).unwrap();
+        storage.set_trial_value(trial_id, 1.0).unwrap();
+        storage
+            .set_trial_state(trial_id, FrozenTrialState::Completed)
+            .unwrap();
+        storage
+            .connection
+            .execute_batch("DROP TABLE trial_params; DROP TABLE study_user_attrs;")
+            .unwrap();
+        assert!(storage.get_all_trials().is_err());
+        assert!(storage.get_study_trials(study_id).is_err());
+        assert!(storage
+            .get_best_trial(study_id, StudyDirection::Minimize)
+            .is_err());
+        assert!(storage.get_study_user_attrs(study_id).is_err());
+        storage
+            .connection
+            .execute_batch("PRAGMA foreign_keys = OFF; DROP TABLE studies;")
+            .unwrap();
+        assert!(storage.get_all_study_names().is_err());
+    }
+}
@@ -0,0 +1,2414 @@
+use crate::distributions::{params_approx_eq, Distributions, ExternalRepr};
+use crate::importance::mean_decrease_impurity;
+use crate::pruners::{NopPruner, Pruner};
+use crate::samplers::{RandomSampler, Sampler};
+use crate::storages::{InMemoryStorage, Storage};
+use crate::trial::{
+    FrozenTrial, FrozenTrialState, ParetoPoint, ReportPolicy, Trial, TrialRecord, TrialSummary,
+};
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(PartialEq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum StudyDirection {
+    #[default]
+    Minimize,
+    Maximize,
+}
+
+pub type Objective = fn(&mut Trial) -> Result<f64>;
+/// Objective of a study created with `Study::new_multi_objective`, returning
+/// one value per study direction.
+pub type MultiObjective = fn(&mut Trial) -> Result<Vec<f64>>;
+/// Hook run by `Study::optimize_with_callbacks` after each trial finishes,
+/// whether it completed, failed or was pruned.
+pub type Callback = Box<dyn FnMut(&Study, &FrozenTrial)>;
+/// Internal params of a failed trial and the number of the attempt to rerun them.
+type Retry = (HashMap<String, f64>, u32);
+
+/// Handle to a study. Clones share its storage, sampler and pruner, so the
+/// clone each `Trial` holds writes through to the same trials.
+#[derive(Clone)]
+pub struct Study {
+    pub(crate) storage: Arc<Mutex<dyn Storage>>,
+    pub(crate) sampler: Arc<Mutex<dyn Sampler>>,
+    study_id: usize,
+    study_name: String,
+    pub(crate) pruner: Arc<dyn Pruner>,
+    pub(crate) direction: StudyDirection,
+    directions: Vec<StudyDirection>,
+    pub(crate) report_policy: ReportPolicy,
+    pub(crate) seed: Option<u64>,
+    search_space_signature: Arc<Mutex<HashMap<String, String>>>,
+    /// Distribution kind each param was first suggested with in this study.
+    param_kinds: Arc<Mutex<HashMap<String, &'static str>>>,
+    strict_search_space: bool,
+    /// Relative and absolute tolerance within which `enqueue_trial` takes a
+    /// continuous param to match the value a trial suggested.
+    param_tolerances: (f64, f64),
+    catch: bool,
+    silent: bool,
+    heartbeat_interval: Option<Duration>,
+    max_retries: u32,
+    /// Internal params of failed trials to run again, with the attempt number.
+    retry_queue: Arc<Mutex<VecDeque<Retry>>>,
+    enqueued_params: Arc<Mutex<VecDeque<HashMap<String, ExternalRepr>>>>,
+    /// Set by `stop` and checked before each trial. Clones share it, so an
+    /// objective can stop the study through its trial too.
+    stop_flag: Arc<AtomicBool>,
+}
+
+impl Study {
+    /// Creates a study registered in `storage` under a generated name.
+    pub fn new(
+        storage: impl Storage + 'static,
+        sampler: impl Sampler + 'static,
+        direction: StudyDirection,
+    ) -> Self {
+        Self::register(storage, sampler, None, vec![direction])
+            .expect("Failed to create the study in storage")
+    }
+
+    /// Registers a new study in `storage`, generating a name unless one is given.
+    fn register(
+        mut storage: impl Storage + 'static,
+        sampler: impl Sampler + 'static,
+        study_name: Option<&str>,
+        directions: Vec<StudyDirection>,
+    ) -> Result<Self> {
+        let study_name = match study_name {
+            Some(study_name) => study_name.to_string(),
+            None => {
+                let names = storage.get_all_study_names()?;
+                (0..)
+                    .map(|n| format!("no-name-{}", n))
+                    .find(|name| !names.contains(name))
+                    .expect("study names are exhausted")
+            }
+        };
+        let study_id = storage.create_study(&study_name, &directions)?;
+        Ok(Self::from_storage(
+            storage, sampler, study_id, study_name, directions,
+        ))
+    }
+
+    fn from_storage(
+        storage: impl Storage + 'static,
+        sampler: impl Sampler + 'static,
+        study_id: usize,
+        study_name: String,
+        directions: Vec<StudyDirection>,
+    ) -> Self {
+        Study {
+            storage: Arc::new(Mutex::new(storage)),
+            sampler: Arc::new(Mutex::new(sampler)),
+            study_id,
+            study_name,
+            pruner: Arc::new(NopPruner),
+            direction: directions[0],
+            directions,
+            report_policy: ReportPolicy::Reject,
+            seed: None,
+            search_space_signature: Arc::new(Mutex::new(HashMap::new())),
+            param_kinds: Arc::new(Mutex::new(HashMap::new())),
+            strict_search_space: false,
+            param_tolerances: (1e-9, 1e-12),
+            catch: false,
+            silent: false,
+            heartbeat_interval: None,
+            max_retries: 0,
+            retry_queue: Arc::new(Mutex::new(VecDeque::new())),
+            enqueued_params: Arc::new(Mutex::new(VecDeque::new())),
+            stop_flag: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Creates a study optimizing one objective per entry of `directions`.
+    /// Samplers and pruners only look at the first objective.
+    pub fn new_multi_objective(
+        storage: impl Storage + 'static,
+        sampler: impl Sampler + 'static,
+        directions: Vec<StudyDirection>,
+    ) -> Result<Self> {
+        if directions.is_empty() {
+            return Err(anyhow!("At least one direction must be given"));
+        }
+        Self::register(storage, sampler, None, directions)
+    }
+
+    pub fn set_pruner(&mut self, pruner: impl Pruner + 'static) {
+        self.pruner = Arc::new(pruner);
+    }
+
+    /// Defaults to `ReportPolicy::Reject`, so a training loop reporting a step
+    /// twice or out of order fails instead of corrupting the curve seen by pruners.
+    pub fn set_report_policy(&mut self, report_policy: ReportPolicy) {
+        self.report_policy = report_policy;
+    }
+
+    /// Seeds every trial with `seed ^ trial_id`, so each trial can later be
+    /// reproduced on its own with `reproduce_trial`. The sampler is reseeded
+    /// from the trial seed and the param name before each param is sampled.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    /// Makes `optimize` fail instead of warn when an objective declares a param
+    /// with another distribution than earlier `optimize` calls did. Params they
+    /// never declared are accepted, since spaces may depend on earlier choices.
+    pub fn set_strict_search_space(&mut self, strict: bool) {
+        self.strict_search_space = strict;
+    }
+
+    /// Tolerances within which `enqueue_trial` with `skip_if_exists` takes a
+    /// continuous param to match an earlier trial, `1e-9` and `1e-12` by default.
+    /// Int, bool and categorical params always have to match exactly. Fails
+    /// unless both tolerances are non-negative.
+    pub fn set_param_tolerances(&mut self, rel_tol: f64, abs_tol: f64) -> Result<()> {
+        if !(rel_tol >= 0.0 && abs_tol >= 0.0) {
+            return Err(anyhow!(
+                "Tolerances must be non-negative, got rel_tol={} and abs_tol={}",
+                rel_tol,
+                abs_tol
+            ));
+        }
+        self.param_tolerances = (rel_tol, abs_tol);
+        Ok(())
+    }
+
+    /// Makes `optimize` carry on with the next trial when the objective panics
+    /// or returns an error, instead of returning the error. The trial is marked
+    /// `Failed` either way.
+    pub fn set_catch(&mut self, catch: bool) {
+        self.catch = catch;
+    }
+
+    /// Makes `optimize` run the params of a failed trial again in the next
+    /// trial, up to `max_retries` times, before leaving them failed. Each retry
+    /// takes one of the `n_trials` and reuses the stored internal params, and a
+    /// failure that will be retried does not end `optimize`.
+    pub fn set_max_retries(&mut self, max_retries: u32) {
+        self.max_retries = max_retries;
+    }
+
+    /// Stops `optimize` from logging the outcome of every trial and the best
+    /// value so far at info level. Failed trials are still warned about.
+    pub fn set_silent(&mut self, silent: bool) {
+        self.silent = silent;
+    }
+
+    /// Makes running trials record a heartbeat every `interval`, and `optimize`
+    /// fail the running trials whose heartbeat is over twice that old before
+    /// each trial, so trials of a crashed worker sharing the storage end.
+    /// Fails if `interval` is zero.
+    pub fn set_heartbeat_interval(&mut self, interval: Duration) -> Result<()> {
+        check_heartbeat_interval(interval)?;
+        self.heartbeat_interval = Some(interval);
+        Ok(())
+    }
+
+    /// Marks the running trials whose heartbeat is older than `max_age` as
+    /// failed, returning their ids.
+    pub fn fail_stale_trials(&self, max_age: Duration) -> Result<Vec<usize>> {
+        let stale = self
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .fail_stale_trials(self.study_id, max_age)?;
+        for trial_id in &stale {
+            warn!(
+                "trial_id={} failed: no heartbeat for {:?}",
+                trial_id, max_age
+            );
+        }
+        Ok(stale)
+    }
+
+    pub fn optimize(&self, objective: Objective, n_trials: u64) -> Result<()> {
+        self.optimize_with_timeout(objective, Some(n_trials), None)
+    }
+
+    /// Runs trials until `n_trials` have been run or `timeout` has elapsed,
+    /// whichever comes first. A trial still running at the timeout is finished.
+    pub fn optimize_with_timeout(
+        &self,
+        objective: Objective,
+        n_trials: Option<u64>,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        self.optimize_sequential(objective, n_trials, timeout, &mut [])
+    }
+
+    /// Runs `n_trials` trials, passing each finished trial to every callback in
+    /// order. A callback can end the optimization early with `Study::stop`.
+    pub fn optimize_with_callbacks(
+        &self,
+        objective: Objective,
+        n_trials: u64,
+        mut callbacks: Vec<Callback>,
+    ) -> Result<()> {
+        self.optimize_sequential(objective, Some(n_trials), None, &mut callbacks)
+    }
+
+    /// Makes the running `optimize` call return once the current trial finishes.
+    pub fn stop(&self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+    }
+
+    fn optimize_sequential(
+        &self,
+        objective: Objective,
+        n_trials: Option<u64>,
+        timeout: Option<Duration>,
+        callbacks: &mut [Callback],
+    ) -> Result<()> {
+        if n_trials.is_none() && timeout.is_none() {
+            return Err(anyhow!("Either n_trials or timeout must be given"));
+        }
+        self.stop_flag.store(false, Ordering::SeqCst);
+        let started = Instant::now();
+        let mut seen_signature = HashMap::new();
+        let mut n_run = 0;
+        while n_trials.is_none_or(|n_trials| n_run < n_trials)
+            && timeout.is_none_or(|timeout| started.elapsed() < timeout)
+            && !self.stop_flag.load(Ordering::SeqCst)
+            && !self
+                .sampler
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_exhausted(self)
+        {
+            n_run += 1;
+            self.run_trial(
+                |trial| objective(trial).map(|value| vec![value]),
+                &mut seen_signature,
+                callbacks,
+            )?;
+        }
+        self.log_if_exhausted(n_run)?;
+        self.search_space_signature
+            .lock()
+            .unwrap()
+            .extend(seen_signature);
+        Ok(())
+    }
+
+    /// Runs `n_trials` trials across `n_jobs` threads. In a seeded study each
+    /// trial samples the same params whatever the thread scheduling, as long as
+    /// the sampler does not depend on the other trials, e.g. `RandomSampler`.
+    pub fn optimize_parallel(
+        &self,
+        objective: Objective,
+        n_trials: u64,
+        n_jobs: usize,
+    ) -> Result<()> {
+        self.stop_flag.store(false, Ordering::SeqCst);
+        let next_trial = AtomicU64::new(0);
+        let n_run = AtomicU64::new(0);
+        let failed = AtomicBool::new(false);
+        let results: Vec<Result<HashMap<String, String>>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..n_jobs)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut seen_signature = HashMap::new();
+                        while !failed.load(Ordering::SeqCst)
+                            && !self.stop_flag.load(Ordering::SeqCst)
+                            && !self
+                                .sampler
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .is_exhausted(self)
+                            && next_trial.fetch_add(1, Ordering::SeqCst) < n_trials
+                        {
+                            n_run.fetch_add(1, Ordering::SeqCst);
+                            let result = self.run_trial(
+                                |trial| objective(trial).map(|value| vec![value]),
+                                &mut seen_signature,
+                                &mut [],
+                            );
+                            if let Err(err) = result {
+                                failed.store(true, Ordering::SeqCst);
+                                return Err(err);
+                            }
+                        }
+                        Ok(seen_signature)
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("optimize worker panicked"))
+                .collect()
+        });
+        self.log_if_exhausted(n_run.into_inner())?;
+        for seen_signature in results {
+            self.search_space_signature
+                .lock()
+                .unwrap()
+                .extend(seen_signature?);
+        }
+        Ok(())
+    }
+
+    /// Runs `n_trials` trials of a study created with `new_multi_objective`.
+    pub fn optimize_multi_objective(&self, objective: MultiObjective, n_trials: u64) -> Result<()> {
+        self.stop_flag.store(false, Ordering::SeqCst);
+        let mut seen_signature = HashMap::new();
+        let mut n_run = 0;
+        for _ in 0..n_trials {
+            if self.stop_flag.load(Ordering::SeqCst)
+                || self
+                    .sampler
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .is_exhausted(self)
+            {
+                break;
+            }
+            n_run += 1;
+            self.run_trial(objective, &mut seen_signature, &mut [])?;
+        }
+        self.log_if_exhausted(n_run)?;
+        self.search_space_signature
+            .lock()
+            .unwrap()
+            .extend(seen_signature);
+        Ok(())
+    }
+
+    /// Asks for a trial, evaluates `objective` on it, records the outcome and
+    /// then hands the finished trial to `callbacks`.
+    fn run_trial(
+        &self,
+        objective: impl FnOnce(&mut Trial) -> Result<Vec<f64>>,
+        seen_signature: &mut HashMap<String, String>,
+        callbacks: &mut [Callback],
+    ) -> Result<()> {
+        if let Some(interval) = self.heartbeat_interval {
+            self.fail_stale_trials(interval * 2)?;
+        }
+        let mut trial = self.ask()?;
+        let trial_id = trial.trial_id;
+        let heartbeat = self
+            .heartbeat_interval
+            .map(|interval| self.start_heartbeat(trial_id, interval))
+            .transpose()?;
+        // A panic in the sampler or storage poisons its mutex; every lock of
+        // them recovers the guard, so the following trials still run.
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| objective(&mut trial))) {
+            Ok(result) => result,
+            Err(payload) => Err(anyhow!(payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string()))),
+        };
+        if let Some((stop, beater)) = heartbeat {
+            drop(stop);
+            beater.join().expect("heartbeat thread panicked");
+        }
+        let result = result.and_then(|values| {
+            if values.len() != self.directions.len() {
+                return Err(anyhow!(
+                    "Objective returned {} values for {} directions",
+                    values.len(),
+                    self.directions.len()
+                ));
+            }
+            Ok(values)
+        });
+        let values = match result {
+            Ok(values) => values,
+            Err(err) => {
+                warn!("trial_id={} failed: {}", trial_id, err);
+                self.storage
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .set_trial_state(trial_id, FrozenTrialState::Failed)?;
+                let retried = self.schedule_retry(&trial)?;
+                self.invoke_callbacks(trial_id, callbacks)?;
+                if self.catch || retried {
+                    return Ok(());
+                }
+                return Err(err.context(format!("trial_id={} failed", trial_id)));
+            }
+        };
+        if values.iter().any(|value| !value.is_finite()) {
+            warn!(
+                "trial_id={} failed: objective returned non-finite values {:?}",
+                trial_id, values
+            );
+            self.storage
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_trial_state(trial_id, FrozenTrialState::Failed)?;
+            self.schedule_retry(&trial)?;
+            return self.invoke_callbacks(trial_id, callbacks);
+        }
+        if let Err(err) = self.check_search_space(trial_id, seen_signature) {
+            self.storage
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_trial_state(trial_id, FrozenTrialState::Failed)?;
+            self.invoke_callbacks(trial_id, callbacks)?;
+            return Err(err);
+        }
+        let state = if trial.pruned {
+            FrozenTrialState::Pruned
+        } else {
+            FrozenTrialState::Completed
+        };
+        let mut storage = self.storage.lock().unwrap_or_else(PoisonError::into_inner);
+        storage.set_trial_values(trial_id, &values)?;
+        storage.set_trial_state(trial_id, state)?;
+        drop(storage);
+        if !self.silent {
+            self.log_progress(trial_id, state, &values)?;
+        }
+        self.invoke_callbacks(trial_id, callbacks)
+    }
+
+    /// Queues the params of the failed `trial` for the next trial unless it
+    /// used up the retries, returning whether it did queue them.
+    fn schedule_retry(&self, trial: &Trial) -> Result<bool> {
+        if trial.retry_attempt >= self.max_retries {
+            return Ok(false);
+        }
+        let attempt = trial.retry_attempt + 1;
+        let failed = self
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_trial(trial.trial_id)?;
+        warn!(
+            "trial_id={} will be retried, attempt {} of {}",
+            trial.trial_id, attempt, self.max_retries
+        );
+        self.retry_queue
+            .lock()
+            .unwrap()
+            .push_back((failed.internal_params, attempt));
+        Ok(true)
+    }
+
+    /// Records a heartbeat for `trial_id` now and then every `interval` on
+    /// another thread, until the returned sender is dropped.
+    fn start_heartbeat(
+        &self,
+        trial_id: usize,
+        interval: Duration,
+    ) -> Result<(mpsc::Sender<()>, thread::JoinHandle<()>)> {
+        self.storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record_heartbeat(trial_id)?;
+        let storage = Arc::clone(&self.storage);
+        let (stop, stopped) = mpsc::channel::<()>();
+        let beater = thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                if let Err(err) = storage
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .record_heartbeat(trial_id)
+                {
+                    warn!("trial_id={} stopped its heartbeat: {}", trial_id, err);
+                    return;
+                }
+            }
+        });
+        Ok((stop, beater))
+    }
+
+    /// Logs that optimizing stopped early because the sampler, e.g. a
+    /// `GridSampler` that ran every combination, has nothing left to propose.
+    fn log_if_exhausted(&self, n_run: u64) -> Result<()> {
+        if !self.silent
+            && self
+                .sampler
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_exhausted(self)
+        {
+            info!(
+                "Sampler is exhausted after {} trials of this run, {} unique points evaluated",
+                n_run,
+                self.n_unique_points()?
+            );
+        }
+        Ok(())
+    }
+
+    /// Number of distinct param sets among the completed trials, e.g. the grid
+    /// points a `GridSampler` got evaluated.
+    fn n_unique_points(&self) -> Result<usize> {
+        let mut points: Vec<Vec<(String, u64)>> = self
+            .get_trials(Some(FrozenTrialState::Completed))?
+            .into_iter()
+            .map(|trial| {
+                let mut point: Vec<(String, u64)> = trial
+                    .internal_params
+                    .into_iter()
+                    .map(|(name, value)| (name, value.to_bits()))
+                    .collect();
+                point.sort_unstable();
+                point
+            })
+            .collect();
+        points.sort_unstable();
+        points.dedup();
+        Ok(points.len())
+    }
+
+    fn log_progress(&self, trial_id: usize, state: FrozenTrialState, values: &[f64]) -> Result<()> {
+        let value = match values {
+            [value] => value.to_string(),
+            values => format!("{:?}", values),
+        };
+        let state = match state {
+            FrozenTrialState::Pruned => "pruned",
+            _ => "completed",
+        };
+        match self.best_trial()? {
+            Some(best) => info!(
+                "trial_id={} is {} with value={}, best is trial_id={} with value={}",
+                trial_id, state, value, best.trial_id, best.value
+            ),
+            None => info!("trial_id={} is {} with value={}", trial_id, state, value),
+        }
+        Ok(())
+    }
+
+    fn invoke_callbacks(&self, trial_id: usize, callbacks: &mut [Callback]) -> Result<()> {
+        if callbacks.is_empty() {
+            return Ok(());
+        }
+        let trial = self
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_trial(trial_id)?;
+        for callback in callbacks.iter_mut() {
+            callback(self, &trial);
+        }
+        Ok(())
+    }
+
+    /// Compares the params declared by a trial against the search space recorded
+    /// by previous `optimize` calls, and collects them into `seen_signature`.
+    fn check_search_space(
+        &self,
+        trial_id: usize,
+        seen_signature: &mut HashMap<String, String>,
+    ) -> Result<()> {
+        let trial = self
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_trial(trial_id)?;
+        let recorded = self.search_space_signature.lock().unwrap();
+        for (name, distribution) in trial.distributions {
+            let signature = format!("{:?}", distribution);
+            // Params missing from the record may just be conditional on others.
+            let drifted = recorded
+                .get(&name)
+                .is_some_and(|recorded| recorded != &signature)
+                && seen_signature.get(&name) != Some(&signature);
+            if drifted {
+                let message = format!(
+                    "Param {} is declared as {} but previous optimize calls used {}",
+                    name, signature, recorded[&name]
+                );
+                if self.strict_search_space {
+                    return Err(anyhow!(message));
+                }
+                warn!("{}", message);
+            }
+            seen_signature.insert(name, signature);
+        }
+        Ok(())
+    }
+
+    /// Fails if `name` was suggested in an earlier trial with another kind of
+    /// distribution, since history-based samplers cannot compare their values.
+    pub(crate) fn check_param_kind(&self, name: &str, distribution: &Distributions) -> Result<()> {
+        let mut kinds = self.param_kinds.lock().unwrap();
+        match kinds.get(name) {
+            Some(&kind) if kind != distribution.kind() => Err(anyhow!(
+                "Param {} is suggested as {} but earlier trials suggested it as {}",
+                name,
+                distribution.kind(),
+                kind
+            )),
+            Some(_) => Ok(()),
+            None => {
+                kinds.insert(name.to_string(), distribution.kind());
+                Ok(())
+            }
+        }
+    }
+
+    /// Starts a new running trial whose objective is evaluated by the caller.
+    /// Trials can be asked for several times before any of them is told.
+    pub fn ask(&self) -> Result<Trial> {
+        let trial_id = self
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .create_new_trial(self.study_id)?;
+        let number = self
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_trial(trial_id)?
+            .number;
+        let seed = self.seed.map(|base_seed| base_seed ^ trial_id as u64);
+        if let Some(seed) = seed {
+            self.storage
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_trial_seed(trial_id, seed)?;
+        }
+        let relative_params = match self.sample_relative_params(trial_id, seed) {
+            Ok(relative_params) => relative_params,
+            Err(err) => {
+                // The trial is already stored, so it must not be left running.
+                self.storage
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .set_trial_state(trial_id, FrozenTrialState::Failed)?;
+                return Err(err);
+            }
+        };
+        let mut trial = Trial::new(self.clone(), trial_id, number);
+        trial.relative_params = relative_params;
+        trial.seed = seed;
+        if let Some((params, attempt)) = self.retry_queue.lock().unwrap().pop_front() {
+            trial.fixed_params = params;
+            trial.retry_attempt = attempt;
+        } else if let Some(params) = self.enqueued_params.lock().unwrap().pop_front() {
+            trial.enqueued_params = params;
+        }
+        Ok(trial)
+    }
+
+    /// Prepares the sampler for a new trial and samples its relative params.
+    fn sample_relative_params(
+        &self,
+        trial_id: usize,
+        seed: Option<u64>,
+    ) -> Result<HashMap<String, (Distributions, f64)>> {
+        let mut sampler = self.sampler.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(seed) = seed {
+            sampler.reseed(seed);
+        }
+        sampler.before_trial(self, trial_id)?;
+        let search_space = sampler.infer_relative_search_space(self, trial_id)?;
+        Ok(sampler
+            .sample_relative(self, trial_id, &search_space)?
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let distribution = search_space.get(&name)?.clone();
+                Some((name, (distribution, value)))
+            })
+            .collect())
+    }
+
+    /// Makes the next asked trial suggest `params` instead of sampling them.
+    /// Params missing from `params` are sampled as usual, and a value that does
+    /// not fit its distribution makes the suggest call fail. With `skip_if_exists`
+    /// nothing is queued if the same params are already queued, or if a completed
+    /// or running trial suggested exactly these params with the same internal
+    /// values, so `3` and `3.0` match for an int param. Continuous values match
+    /// within the tolerances of `set_param_tolerances`.
+    pub fn enqueue_trial(
+        &self,
+        params: HashMap<String, ExternalRepr>,
+        skip_if_exists: bool,
+    ) -> Result<()> {
+        if skip_if_exists && self.has_trial_with(&params)? {
+            info!(
+                "Skipping enqueued params {:?} already run by a trial",
+                params
+            );
+            return Ok(());
+        }
+        let mut enqueued_params = self.enqueued_params.lock().unwrap();
+        if skip_if_exists
+            && enqueued_params
+                .iter()
+                .any(|queued| same_params(queued, &params))
+        {
+            info!("Skipping enqueued params {:?} already queued", params);
+            return Ok(());
+        }
+        enqueued_params.push_back(params);
+        Ok(())
+    }
+
+    fn has_trial_with(&self, params: &HashMap<String, ExternalRepr>) -> Result<bool> {
+        let (rel_tol, abs_tol) = self.param_tolerances;
+        Ok(self
+            .trials()?
+            .into_iter()
+            .filter(|trial| {
+                matches!(
+                    trial.state,
+                    FrozenTrialState::Completed | FrozenTrialState::Running
+                )
+            })
+            .any(|trial| {
+                // Enqueued values in the internal form of the trial's distributions.
+                let enqueued: Option<HashMap<String, f64>> = params
+                    .iter()
+                    .map(|(name, value)| {
+                        let distribution = trial.distributions.get(name)?;
+                        let internal_repr =
+                            distribution.coerced_internal_repr_of(name, value).ok()?;
+                        Some((name.clone(), internal_repr))
+                    })
+                    .collect();
+                enqueued.is_some_and(|enqueued| {
+                    params_approx_eq(
+                        &enqueued,
+                        &trial.internal_params,
+                        &trial.distributions,
+                        rel_tol,
+                        abs_tol,
+                    )
+                })
+            }))
+    }
+
+    /// Re-runs a single trial in isolation with its recorded seed and params,
+    /// without adding anything to this study.
+    pub fn reproduce_trial(&self, trial_id: usize, objective: Objective) -> Result<f64> {
+        let original = self
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_trial(trial_id)?;
+        let mut sampler = RandomSampler::new();
+        if let Some(seed) = original.seed {
+            sampler.reseed(seed);
+        }
+        let scratch = Study::new(InMemoryStorage::new(), sampler, self.direction);
+        let mut trial = scratch.ask()?;
+        trial.fixed_params = original.internal_params;
+        trial.seed = original.seed;
+        objective(&mut trial)
+    }
+
+    /// Completes a trial returned by `ask`, in any order relative to other asked trials.
+    /// A NaN or infinite `value` marks the trial `Failed` instead.
+    pub fn tell(&self, trial: Trial, value: f64) -> Result<()> {
+        trial.complete(value)
+    }
+
+    pub fn study_name(&self) -> &str {
+        &self.study_name
+    }
+
+    /// Stores a finished trial evaluated outside of this study, e.g. to warm
+    /// start it from an earlier experiment, so samplers learn from it like any
+    /// other trial. Every param must lie within its distribution. A completed
+    /// trial with a NaN or infinite `value` is stored as `Failed` without it.
+    pub fn add_trial(
+        &self,
+        params: HashMap<String, (Distributions, ExternalRepr)>,
+        value: f64,
+        state: FrozenTrialState,
+    ) -> Result<usize> {
+        if state == FrozenTrialState::Running {
+            return Err(anyhow!("Added trials must be finished"));
+        }
+        let mut internal_params = vec![];
+        for (name, (distribution, value)) in params {
+            self.check_param_kind(&name, &distribution)?;
+            let internal_repr = distribution
+                .checked_internal_repr_of(&name, &value)
+                .with_context(|| format!("Invalid value of param {}", name))?;
+            internal_params.push((name, distribution, internal_repr));
+        }
+        let mut storage = self.storage.lock().unwrap_or_else(PoisonError::into_inner);
+        let trial_id = storage.create_new_trial(self.study_id)?;
+        for (name, distribution, internal_repr) in internal_params {
+            storage.set_trial_param(trial_id, &name, distribution, internal_repr)?;
+        }
+        if state == FrozenTrialState::Completed && !value.is_finite() {
+            warn!(
+                "trial_id={} failed: added with non-finite value {}",
+                trial_id, value
+            );
+            storage.set_trial_state(trial_id, FrozenTrialState::Failed)?;
+            return Ok(trial_id);
+        }
+        storage.set_trial_value(trial_id, value)?;
+        storage.set_trial_state(trial_id, state)?;
+        Ok(trial_id)
+    }
+
+    pub fn direction(&self) -> StudyDirection {
+        self.direction
+    }
+
+    pub fn directions(&self) -> &[StudyDirection] {
+        &self.directions
+    }
+
+    /// All trials of the study so far, which is the history a custom `Sampler` learns from.
+    pub fn trials(&self) -> Result<Vec<FrozenTrial>> {
+        self.storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_study_trials(self.study_id)
+    }
+
+    /// Params every completed trial suggested, each with the distribution they
+    /// all used. Params suggested by only some trials, or with distributions
+    /// that disagree between trials, are left out.
+    pub fn search_space(&self) -> Result<HashMap<String, Distributions>> {
+        let completed = self.get_trials(Some(FrozenTrialState::Completed))?;
+        let (first, rest) = match completed.split_first() {
+            Some(split) => split,
+            None => return Ok(HashMap::new()),
+        };
+        let mut search_space = first.distributions.clone();
+        for trial in rest {
+            search_space
+                .retain(|name, distribution| trial.distributions.get(name) == Some(distribution));
+        }
+        Ok(search_space)
+    }
+
+    /// Trials in `state`, or all trials when `state` is `None`.
+    pub fn get_trials(&self, state: Option<FrozenTrialState>) -> Result<Vec<FrozenTrial>> {
+        let mut trials = self.trials()?;
+        if let Some(state) = state {
+            trials.retain(|trial| trial.state == state);
+        }
+        Ok(trials)
+    }
+
+    /// Like `get_trials`, but without copying the params, intermediate values
+    /// and attributes of each trial, for reading large studies cheaply.
+    pub fn get_trial_summaries(
+        &self,
+        state: Option<FrozenTrialState>,
+    ) -> Result<Vec<TrialSummary>> {
+        let mut summaries = self
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_study_trial_summaries(self.study_id)?;
+        if let Some(state) = state {
+            summaries.retain(|summary| summary.state == state);
+        }
+        Ok(summaries)
+    }
+
+    /// Number of trials in the study, whatever their state.
+    pub fn n_trials(&self) -> Result<usize> {
+        Ok(self.get_trial_summaries(None)?.len())
+    }
+
+    /// Trial counts per state with the best trial so far, e.g. to print a
+    /// one-line status at the end of a run.
+    pub fn summary(&self) -> Result<StudySummary> {
+        let mut summary = StudySummary::default();
+        for trial in self.get_trial_summaries(None)? {
+            summary.n_trials += 1;
+            match trial.state {
+                FrozenTrialState::Running => summary.n_running += 1,
+                FrozenTrialState::Completed => summary.n_completed += 1,
+                FrozenTrialState::Pruned => summary.n_pruned += 1,
+                FrozenTrialState::Failed => summary.n_failed += 1,
+            }
+        }
+        if let Some(best_trial) = self.best_trial()? {
+            summary.best_value = Some(best_trial.value);
+            summary.best_trial_number = Some(best_trial.number);
+        }
+        Ok(summary)
+    }
+
+    /// All trials as flat records, each with a column for every param any
+    /// trial suggested.
+    pub fn trials_records(&self) -> Result<Vec<TrialRecord>> {
+        let trials = self.trials()?;
+        let names: BTreeSet<&String> = trials
+            .iter()
+            .flat_map(|trial| trial.distributions.keys())
+            .collect();
+        trials
+            .iter()
+            .map(|trial| {
+                let mut params = trial.params()?;
+                Ok(TrialRecord {
+                    number: trial.number,
+                    state: trial.state,
+                    value: trial.value,
+                    duration: trial.duration(),
+                    params: names
+                        .iter()
+                        .map(|&name| (name.clone(), params.remove(name)))
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+
+    /// Writes one row per trial with its number, state, value and a column per
+    /// param. Params a trial did not suggest, and the value of a trial that is
+    /// running or failed, are left empty.
+    pub fn to_csv(&self, path: &str) -> Result<()> {
+        let records = self.trials_records()?;
+        let names: Vec<String> = records
+            .first()
+            .map_or(vec![], |record| record.params.keys().cloned().collect());
+        let mut writer = BufWriter::new(File::create(path)?);
+        let header: Vec<String> = ["number", "state", "value"]
+            .iter()
+            .map(|column| column.to_string())
+            .chain(names.iter().cloned())
+            .collect();
+        write_csv_row(&mut writer, &header)?;
+        for record in records {
+            let value = match record.state {
+                FrozenTrialState::Completed | FrozenTrialState::Pruned => record.value.to_string(),
+                FrozenTrialState::Running | FrozenTrialState::Failed => String::new(),
+            };
+            let row: Vec<String> = vec![
+                record.number.to_string(),
+                format!("{:?}", record.state),
+                value,
+            ]
+            .into_iter()
+            .chain(
+                record
+                    .params
+                    .into_values()
+                    .map(|param| param.map_or(String::new(), |param| param.to_string())),
+            )
+            .collect();
+            write_csv_row(&mut writer, &row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the study in the shape of Optuna's trial records, with Optuna's
+    /// state names and `FloatDistribution`/`IntDistribution`/`CategoricalDistribution`
+    /// distributions, so Optuna tooling can read it. Datetimes are UTC.
+    pub fn to_optuna_json(&self, path: &str) -> Result<()> {
+        let trials: Vec<Value> = self
+            .trials()?
+            .iter()
+            .map(|trial| -> Result<Value> {
+                let (state, values) = match trial.state {
+                    FrozenTrialState::Running => ("RUNNING", Value::Null),
+                    FrozenTrialState::Failed => ("FAIL", Value::Null),
+                    FrozenTrialState::Completed => ("COMPLETE", json!(trial.values)),
+                    FrozenTrialState::Pruned => ("PRUNED", json!(trial.values)),
+                };
+                let params: Map<String, Value> = trial
+                    .params()?
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.to_json()))
+                    .collect();
+                let distributions: Map<String, Value> = trial
+                    .distributions
+                    .iter()
+                    .map(|(name, distribution)| (name.clone(), distribution.to_optuna_json()))
+                    .collect();
+                let intermediate_values: Map<String, Value> = trial
+                    .intermediate_values
+                    .iter()
+                    .map(|(step, value)| (step.to_string(), json!(value)))
+                    .collect();
+                Ok(json!({
+                    "number": trial.number,
+                    "state": state,
+                    "values": values,
+                    "params": params,
+                    "distributions": distributions,
+                    "user_attrs": trial.user_attrs,
+                    "intermediate_values": intermediate_values,
+                    "datetime_start": trial.datetime_start.map(format_datetime),
+                    "datetime_complete": trial.datetime_complete.map(format_datetime),
+                }))
+            })
+            .collect::<Result<_>>()?;
+        let directions: Vec<&str> = self
+            .directions
+            .iter()
+            .map(|direction| match direction {
+                StudyDirection::Minimize => "MINIMIZE",
+                StudyDirection::Maximize => "MAXIMIZE",
+            })
+            .collect();
+        let study = json!({
+            "study_name": self.study_name,
+            "directions": directions,
+            "user_attrs": self.user_attrs()?,
+            "trials": trials,
+        });
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, &study)?;
+        Ok(())
+    }
+
+    pub fn best_trial(&self) -> Result<Option<FrozenTrial>> {
+        self.storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_best_trial(self.study_id, self.direction)
+    }
+
+    /// Completed trials no other trial beats on every objective. For a single
+    /// objective this is just the best trial.
+    pub fn best_trials(&self) -> Result<Vec<FrozenTrial>> {
+        if self.directions.len() == 1 {
+            return Ok(self.best_trial()?.into_iter().collect());
+        }
+        self.pareto_front()
+    }
+
+    /// The trials of `best_trials` as plot-ready points, sorted by their first
+    /// objective value in ascending order.
+    pub fn get_trials_as_pareto(&self) -> Result<Vec<ParetoPoint>> {
+        let mut points = self
+            .pareto_front()?
+            .into_iter()
+            .map(|trial| {
+                Ok(ParetoPoint {
+                    number: trial.number,
+                    params: trial.params()?,
+                    values: trial.values,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        points.sort_by(|a, b| a.values[0].total_cmp(&b.values[0]));
+        Ok(points)
+    }
+
+    /// Completed trials with finite values that no other trial dominates.
+    fn pareto_front(&self) -> Result<Vec<FrozenTrial>> {
+        let mut completed: Vec<FrozenTrial> = self
+            .get_trials(Some(FrozenTrialState::Completed))?
+            .into_iter()
+            .filter(|trial| trial.values.len() == self.directions.len())
+            .filter(|trial| trial.values.iter().all(|value| value.is_finite()))
+            .collect();
+        // After a lexicographic sort from best to worst, a trial can only be
+        // dominated by an earlier one, and then by one already on the front.
+        completed.sort_by(|a, b| {
+            a.values
+                .iter()
+                .zip(&b.values)
+                .zip(&self.directions)
+                .map(|((x, y), direction)| match direction {
+                    StudyDirection::Minimize => x.total_cmp(y),
+                    StudyDirection::Maximize => y.total_cmp(x),
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let mut front: Vec<FrozenTrial> = vec![];
+        for trial in completed {
+            if !front
+                .iter()
+                .any(|other| dominates(&other.values, &trial.values, &self.directions))
+            {
+                front.push(trial);
+            }
+        }
+        Ok(front)
+    }
+
+    pub fn best_value(&self) -> Result<Option<f64>> {
+        Ok(self.best_trial()?.map(|trial| trial.value))
+    }
+
+    pub fn best_params(&self) -> Result<Option<HashMap<String, ExternalRepr>>> {
+        self.best_trial()?.map(|trial| trial.params()).transpose()
+    }
+
+    /// How much each param explains the objective value across the completed
+    /// trials, by mean decrease impurity of a random forest on the internal
+    /// params. The importances sum to 1.
+    pub fn get_param_importances(&self) -> Result<HashMap<String, f64>> {
+        let completed: Vec<FrozenTrial> = self
+            .get_trials(Some(FrozenTrialState::Completed))?
+            .into_iter()
+            .filter(|trial| trial.value.is_finite())
+            .collect();
+        let mut rng = StdRng::seed_from_u64(self.seed.unwrap_or(0));
+        Ok(mean_decrease_impurity(&completed, &mut rng))
+    }
+
+    pub fn set_user_attr(&self, key: &str, value: &str) -> Result<()> {
+        self.storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_study_user_attr(self.study_id, key, value)
+    }
+
+    pub fn user_attrs(&self) -> Result<HashMap<String, String>> {
+        self.storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_study_user_attrs(self.study_id)
+    }
+
+    /// Checkpoints this study alone, under its name, into a compact binary file.
+    pub fn save_bincode(&self, path: &str) -> Result<()> {
+        let mut checkpoint = InMemoryStorage::new();
+        let study_id = checkpoint.create_study(&self.study_name, &self.directions)?;
+        for (key, value) in self.user_attrs()? {
+            checkpoint.set_study_user_attr(study_id, &key, &value)?;
+        }
+        checkpoint.import_trials(study_id, self.trials()?);
+        checkpoint.save_bincode(path)
+    }
+
+    /// Copies the study with its user attrs and trials into `storage` under the
+    /// same name, e.g. to carry on the history with a different `sampler`. The copy
+    /// shares nothing with this study, and its trials get new ids and timestamps;
+    /// running trials are copied as still running.
+    pub fn copy_study(
+        &self,
+        mut storage: impl Storage + 'static,
+        sampler: impl Sampler + 'static,
+    ) -> Result<Study> {
+        let study_id = storage.create_study(&self.study_name, &self.directions)?;
+        for (key, value) in self.user_attrs()? {
+            storage.set_study_user_attr(study_id, &key, &value)?;
+        }
+        for trial in self.trials()? {
+            let trial_id = storage.create_new_trial(study_id)?;
+            if let Some(seed) = trial.seed {
+                storage.set_trial_seed(trial_id, seed)?;
+            }
+            for (name, distribution) in trial.distributions {
+                let internal_repr = trial.internal_params[&name];
+                storage.set_trial_param(trial_id, &name, distribution, internal_repr)?;
+            }
+            for (step, value) in trial.intermediate_values {
+                storage.set_trial_intermediate_value(trial_id, step, value)?;
+            }
+            for (key, value) in trial.user_attrs {
+                storage.set_trial_user_attr(trial_id, &key, &value)?;
+            }
+            if !trial.constraints.is_empty() {
+                storage.set_trial_constraints(trial_id, &trial.constraints)?;
+            }
+            match trial.state {
+                FrozenTrialState::Running => {}
+                // Failed trials have no value to copy.
+                FrozenTrialState::Failed => storage.set_trial_state(trial_id, trial.state)?,
+                FrozenTrialState::Completed | FrozenTrialState::Pruned => {
+                    if trial.values.is_empty() {
+                        storage.set_trial_value(trial_id, trial.value)?;
+                    } else {
+                        storage.set_trial_values(trial_id, &trial.values)?;
+                    }
+                    storage.set_trial_state(trial_id, trial.state)?;
+                }
+            }
+        }
+        load_study(storage, sampler, &self.study_name)
+    }
+
+    /// Restores a study checkpointed by `save_bincode`, sampling new trials with `sampler`.
+    pub fn load_bincode(path: &str, sampler: impl Sampler + 'static) -> Result<Self> {
+        let storage = InMemoryStorage::load_bincode(path)?;
+        let study_name = match storage.get_all_study_names()?.as_slice() {
+            [study_name] => study_name.clone(),
+            names => {
+                return Err(anyhow!(
+                    "Expected one study in {}, found {}",
+                    path,
+                    names.len()
+                ))
+            }
+        };
+        load_study(storage, sampler, &study_name)
+    }
+}
+
+/// Whether two sets of enqueued params name the same values, reading an int
+/// and an integral float alike.
+fn same_params(a: &HashMap<String, ExternalRepr>, b: &HashMap<String, ExternalRepr>) -> bool {
+    a.len() == b.len()
+        && a.iter().all(|(name, x)| match (x, b.get(name)) {
+            (ExternalRepr::Int(x), Some(ExternalRepr::Float(y)))
+            | (ExternalRepr::Float(y), Some(ExternalRepr::Int(x))) => *x as f64 == *y,
+            (x, y) => Some(x) == y,
+        })
+}
+
+/// Formats `time` as the ISO 8601 UTC datetime `YYYY-MM-DDTHH:MM:SS.ffffff`.
+fn format_datetime(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+    // Civil date from days since the epoch, after Howard Hinnant's `civil_from_days`.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_micros()
+    )
+}
+
+/// Writes `cells` as one CSV line, quoting cells that need it.
+fn check_heartbeat_interval(interval: Duration) -> Result<()> {
+    if interval.is_zero() {
+        return Err(anyhow!("Heartbeat interval must be positive"));
+    }
+    Ok(())
+}
+
+fn write_csv_row(writer: &mut impl Write, cells: &[String]) -> Result<()> {
+    let line: Vec<String> = cells
+        .iter()
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.clone()
+            }
+        })
+        .collect();
+    writeln!(writer, "{}", line.join(","))?;
+    Ok(())
+}
+
+/// Whether the objective values `a` are at least as good as `b` on every
+/// objective and better on one, in the direction given for each objective.
+/// Equal points do not dominate each other, and NaN is worse than any value.
+pub fn dominates(a: &[f64], b: &[f64], directions: &[StudyDirection]) -> bool {
+    let mut better = false;
+    for ((&x, &y), direction) in a.iter().zip(b).zip(directions) {
+        let (x, y) = match direction {
+            StudyDirection::Minimize => (x, y),
+            StudyDirection::Maximize => (-x, -y),
+        };
+        let ordering = match (x.is_nan(), y.is_nan()) {
+            (true, true) => std::cmp::Ordering::Equal,
+            (true, false) => std::cmp::Ordering::Greater,
+            (false, true) => std::cmp::Ordering::Less,
+            (false, false) => x.partial_cmp(&y).unwrap(),
+        };
+        match ordering {
+            std::cmp::Ordering::Greater => return false,
+            std::cmp::Ordering::Less => better = true,
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+    better
+}
+
+/// Trial counts of a study per state with its best trial, as returned by
+/// `Study::summary`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct StudySummary {
+    pub n_trials: usize,
+    pub n_running: usize,
+    pub n_completed: usize,
+    pub n_pruned: usize,
+    pub n_failed: usize,
+    pub best_value: Option<f64>,
+    pub best_trial_number: Option<usize>,
+}
+
+impl fmt::Display for StudySummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "trials={} running={} completed={} pruned={} failed={}",
+            self.n_trials, self.n_running, self.n_completed, self.n_pruned, self.n_failed
+        )?;
+        if let (Some(value), Some(number)) = (self.best_value, self.best_trial_number) {
+            write!(f, " best value={} at trial number={}", value, number)?;
+        }
+        Ok(())
+    }
+}
+
+/// Callback for `Study::optimize_with_callbacks` stopping the study as soon
+/// as a completed trial's value reaches `target_value` in the study direction.
+pub fn stop_at_target_value(target_value: f64) -> Callback {
+    Box::new(move |study, trial| {
+        if trial.state != FrozenTrialState::Completed {
+            return;
+        }
+        let reached = match study.direction() {
+            StudyDirection::Minimize => trial.value <= target_value,
+            StudyDirection::Maximize => trial.value >= target_value,
+        };
+        if reached {
+            info!(
+                "trial_id={} reached target value={} with value={}, stopping",
+                trial.trial_id, target_value, trial.value
+            );
+            study.stop();
+        }
+    })
+}
+
+/// Builder for a `Study`, defaulting to an in-memory storage, a random
+/// sampler, no pruner and a generated name.
+pub struct StudyBuilder<St: Storage, Sa: Sampler> {
+    storage: St,
+    sampler: Sa,
+    pruner: Arc<dyn Pruner>,
+    direction: StudyDirection,
+    study_name: Option<String>,
+    seed: Option<u64>,
+    heartbeat_interval: Option<Duration>,
+}
+
+impl Default for StudyBuilder<InMemoryStorage, RandomSampler> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StudyBuilder<InMemoryStorage, RandomSampler> {
+    pub fn new() -> Self {
+        StudyBuilder {
+            storage: InMemoryStorage::new(),
+            sampler: RandomSampler::new(),
+            pruner: Arc::new(NopPruner),
+            direction: StudyDirection::Minimize,
+            study_name: None,
+            seed: None,
+            heartbeat_interval: None,
+        }
+    }
+}
+
+impl<St: Storage + 'static, Sa: Sampler + 'static> StudyBuilder<St, Sa> {
+    pub fn with_storage<T: Storage + 'static>(self, storage: T) -> StudyBuilder<T, Sa> {
+        StudyBuilder {
+            storage,
+            sampler: self.sampler,
+            pruner: self.pruner,
+            direction: self.direction,
+            study_name: self.study_name,
+            seed: self.seed,
+            heartbeat_interval: self.heartbeat_interval,
+        }
+    }
+
+    pub fn with_sampler<T: Sampler + 'static>(self, sampler: T) -> StudyBuilder<St, T> {
+        StudyBuilder {
+            storage: self.storage,
+            sampler,
+            pruner: self.pruner,
+            direction: self.direction,
+            study_name: self.study_name,
+            seed: self.seed,
+            heartbeat_interval: self.heartbeat_interval,
+        }
+    }
+
+    pub fn with_pruner(mut self, pruner: impl Pruner + 'static) -> Self {
+        self.pruner = Arc::new(pruner);
+        self
+    }
+
+    pub fn with_direction(mut self, direction: StudyDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_study_name(mut self, study_name: &str) -> Self {
+        self.study_name = Some(study_name.to_string());
+        self
+    }
+
+    /// See `Study::set_seed`.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// See `Study::set_heartbeat_interval`.
+    pub fn with_heartbeat_interval(mut self, interval: Duration) -> Result<Self> {
+        check_heartbeat_interval(interval)?;
+        self.heartbeat_interval = Some(interval);
+        Ok(self)
+    }
+
+    /// Registers the study in the storage, failing if it already has a study
+    /// of the given name.
+    pub fn build(self) -> Result<Study> {
+        let mut study = Study::register(
+            self.storage,
+            self.sampler,
+            self.study_name.as_deref(),
+            vec![self.direction],
+        )?;
+        study.pruner = self.pruner;
+        study.seed = self.seed;
+        study.heartbeat_interval = self.heartbeat_interval;
+        Ok(study)
+    }
+}
+
+pub fn create_study(
+    storage: impl Storage + 'static,
+    sampler: impl Sampler + 'static,
+    direction: StudyDirection,
+) -> Study {
+    StudyBuilder::new()
+        .with_storage(storage)
+        .with_sampler(sampler)
+        .with_direction(direction)
+        .build()
+        .expect("Failed to create the study in storage")
+}
+
+/// Creates a study under `study_name`, failing if the storage already has a
+/// study of that name.
+pub fn create_named_study(
+    storage: impl Storage + 'static,
+    sampler: impl Sampler + 'static,
+    study_name: &str,
+    direction: StudyDirection,
+) -> Result<Study> {
+    Study::register(storage, sampler, Some(study_name), vec![direction])
+}
+
+/// Resumes the study named `study_name` from a storage that already holds it,
+/// e.g. a SQLite file written by an earlier run. New trials are appended after
+/// the existing ones and the search space of the completed trials counts as
+/// already seen.
+pub fn load_study(
+    storage: impl Storage + 'static,
+    sampler: impl Sampler + 'static,
+    study_name: &str,
+) -> Result<Study> {
+    let study_id = storage.get_study_id(study_name)?;
+    let directions = storage.get_study_directions(study_id)?;
+    if directions.is_empty() {
+        return Err(anyhow!("Study {} has no directions", study_name));
+    }
+    let study = Study::from_storage(
+        storage,
+        sampler,
+        study_id,
+        study_name.to_string(),
+        directions,
+    );
+    let completed = study.get_trials(Some(FrozenTrialState::Completed))?;
+    let mut signature = study.search_space_signature.lock().unwrap();
+    let mut kinds = study.param_kinds.lock().unwrap();
+    for trial in completed {
+        for (name, distribution) in trial.distributions {
+            kinds.entry(name.clone()).or_insert(distribution.kind());
+            signature.insert(name, format!("{:?}", distribution));
+        }
+    }
+    drop(kinds);
+    drop(signature);
+    Ok(study)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distributions::{
+        CategoricalDistribution, IntUniformDistribution, UniformDistribution,
+    };
+    use crate::samplers::TpeSampler;
+
+    fn study() -> Study {
+        create_study(
+            InMemoryStorage::new(),
+            RandomSampler::new(),
+            StudyDirection::Minimize,
+        )
+    }
+
+    fn objective(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_int("x", 0, 10)?;
+        let y = trial.suggest_int("y", 0, 10)?;
+        Ok((x + y) as f64)
+    }
+
+    fn params(values: &[(&str, ExternalRepr)]) -> HashMap<String, ExternalRepr> {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    fn n_enqueued(study: &Study) -> usize {
+        study.enqueued_params.lock().unwrap().len()
+    }
+
+    #[test]
+    fn best_trial_keeps_the_suggested_params() {
+        let study = study();
+        study.optimize(objective, 5).unwrap();
+        let params = study.best_trial().unwrap().unwrap().params().unwrap();
+        assert!(params.contains_key("x"));
+        assert!(params.contains_key("y"));
+    }
+
+    #[test]
+    fn nan_objective_fails_the_trial() {
+        let study = study();
+        study
+            .optimize(
+                |trial| {
+                    trial.suggest_uniform("x", 0.0, 1.0)?;
+                    Ok(f64::NAN)
+                },
+                3,
+            )
+            .unwrap();
+        let summary = study.summary().unwrap();
+        assert_eq!(summary.n_failed, 3);
+        assert_eq!(summary.n_completed, 0);
+        assert_eq!(study.best_trial().unwrap(), None);
+    }
+
+    #[test]
+    fn added_trials_with_non_finite_values_fail() {
+        let study = study();
+        for value in [f64::NAN, f64::INFINITY, 1.0].iter() {
+            study
+                .add_trial(HashMap::new(), *value, FrozenTrialState::Completed)
+                .unwrap();
+        }
+        let completed = study.get_trials(Some(FrozenTrialState::Completed)).unwrap();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].value, 1.0);
+        assert_eq!(
+            study
+                .get_trials(Some(FrozenTrialState::Failed))
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(study.best_value().unwrap(), Some(1.0));
+    }
+
+    #[test]
+    fn enqueue_skips_params_a_trial_already_ran() {
+        let study = study();
+        let xy = params(&[("x", ExternalRepr::Int(3)), ("y", ExternalRepr::Int(4))]);
+        study.enqueue_trial(xy.clone(), false).unwrap();
+        study.optimize(objective, 1).unwrap();
+
+        study.enqueue_trial(xy, true).unwrap();
+        assert_eq!(n_enqueued(&study), 0);
+        let float_xy = params(&[("x", ExternalRepr::Float(3.0)), ("y", ExternalRepr::Int(4))]);
+        study.enqueue_trial(float_xy, true).unwrap();
+        assert_eq!(n_enqueued(&study), 0);
+    }
+
+    #[test]
+    fn enqueue_only_skips_identical_param_sets() {
+        let study = study();
+        let xy = params(&[("x", ExternalRepr::Int(3)), ("y", ExternalRepr::Int(4))]);
+        study.enqueue_trial(xy, false).unwrap();
+        study.optimize(objective, 1).unwrap();
+
+        study.enqueue_trial(HashMap::new(), true).unwrap();
+        assert_eq!(n_enqueued(&study), 1);
+        study
+            .enqueue_trial(params(&[("x", ExternalRepr::Int(3))]), true)
+            .unwrap();
+        assert_eq!(n_enqueued(&study), 2);
+    }
+
+    #[test]
+    fn enqueue_matches_continuous_params_within_tolerance() {
+        let mut study = study();
+        study
+            .add_trial(
+                vec![(
+                    "x".to_string(),
+                    (
+                        Distributions::Uni(UniformDistribution::new(0.0, 1.0)),
+                        ExternalRepr::Float(0.3),
+                    ),
+                )]
+                .into_iter()
+                .collect(),
+                0.3,
+                FrozenTrialState::Completed,
+            )
+            .unwrap();
+        study
+            .enqueue_trial(params(&[("x", ExternalRepr::Float(0.3 + 1e-12))]), true)
+            .unwrap();
+        assert_eq!(n_enqueued(&study), 0);
+        study
+            .enqueue_trial(params(&[("x", ExternalRepr::Float(0.3 + 1e-6))]), true)
+            .unwrap();
+        assert_eq!(n_enqueued(&study), 1);
+
+        assert!(study.set_param_tolerances(-1.0, 1e-3).is_err());
+        assert!(study.set_param_tolerances(0.0, f64::NAN).is_err());
+        study.set_param_tolerances(0.0, 1e-3).unwrap();
+        study
+            .enqueue_trial(params(&[("x", ExternalRepr::Float(0.3005))]), true)
+            .unwrap();
+        assert_eq!(n_enqueued(&study), 1);
+    }
+
+    #[test]
+    fn enqueue_skips_params_already_queued() {
+        let study = study();
+        let xy = params(&[("x", ExternalRepr::Int(3)), ("y", ExternalRepr::Int(4))]);
+        study.enqueue_trial(xy.clone(), true).unwrap();
+        study.enqueue_trial(xy, true).unwrap();
+        let float_xy = params(&[("x", ExternalRepr::Float(3.0)), ("y", ExternalRepr::Int(4))]);
+        study.enqueue_trial(float_xy, true).unwrap();
+        assert_eq!(n_enqueued(&study), 1);
+        let other = params(&[("x", ExternalRepr::Int(3)), ("y", ExternalRepr::Int(5))]);
+        study.enqueue_trial(other, true).unwrap();
+        assert_eq!(n_enqueued(&study), 2);
+    }
+
+    #[test]
+    fn tell_in_any_order_completes_each_trial_by_id() {
+        let study = create_study(
+            InMemoryStorage::new(),
+            TpeSampler::new()
+                .with_n_startup_trials(2)
+                .with_constant_liar(true),
+            StudyDirection::Minimize,
+        );
+        let mut trials = vec![];
+        for _ in 0..4 {
+            let mut trial = study.ask().unwrap();
+            trial.suggest_uniform("x", 0.0, 1.0).unwrap();
+            trials.push(Some(trial));
+        }
+        let trial_ids: Vec<usize> = trials
+            .iter()
+            .map(|trial| trial.as_ref().unwrap().trial_id())
+            .collect();
+        let mut told = vec![];
+        for &idx in [2, 0, 3, 1].iter() {
+            let trial = trials[idx].take().unwrap();
+            study.tell(trial, idx as f64).unwrap();
+            told.push(idx);
+            // A trial asked while others are still running samples against them.
+            let mut extra = study.ask().unwrap();
+            extra.suggest_uniform("x", 0.0, 1.0).unwrap();
+            extra.fail().unwrap();
+            for (i, &trial_id) in trial_ids.iter().enumerate() {
+                let trial = study.storage.lock().unwrap().get_trial(trial_id).unwrap();
+                if told.contains(&i) {
+                    assert_eq!(trial.state, FrozenTrialState::Completed);
+                    assert_eq!(trial.value, i as f64);
+                } else {
+                    assert_eq!(trial.state, FrozenTrialState::Running);
+                }
+            }
+        }
+        let best_trial = study.best_trial().unwrap().unwrap();
+        assert_eq!(best_trial.trial_id, trial_ids[0]);
+        assert_eq!(study.summary().unwrap().n_completed, 4);
+    }
+
+    thread_local! {
+        static SUGGESTED: std::cell::RefCell<HashMap<String, ExternalRepr>> =
+            std::cell::RefCell::new(HashMap::new());
+    }
+
+    fn recording_objective(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", -1.0, 1.0)?;
+        let choices = vec![ExternalRepr::Int(1), ExternalRepr::Int(2)];
+        let c = match trial.suggest_categorical("c", choices)? {
+            ExternalRepr::Int(c) => c as f64,
+            _ => unreachable!(),
+        };
+        let n = trial.suggest_int("n", 0, 100)?;
+        let params = trial.params()?;
+        SUGGESTED.with(|suggested| *suggested.borrow_mut() = params);
+        Ok(x * c + n as f64)
+    }
+
+    #[test]
+    fn reproduce_trial_suggests_the_original_params() {
+        let mut study = study();
+        study.set_seed(7);
+        study.optimize(recording_objective, 3).unwrap();
+        for original in study.trials().unwrap() {
+            let value = study
+                .reproduce_trial(original.trial_id, recording_objective)
+                .unwrap();
+            let params = SUGGESTED.with(|suggested| suggested.borrow().clone());
+            assert_eq!(params, original.params().unwrap());
+            assert_eq!(value, original.value);
+        }
+        assert_eq!(study.n_trials().unwrap(), 3);
+    }
+
+    fn wider_objective(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_int("x", 0, 20)?;
+        let y = trial.suggest_int("y", 0, 10)?;
+        Ok((x + y) as f64)
+    }
+
+    fn extended_objective(trial: &mut Trial) -> Result<f64> {
+        let z = trial.suggest_uniform("z", 0.0, 1.0)?;
+        Ok(objective(trial)? + z)
+    }
+
+    #[test]
+    fn strict_search_space_accepts_matching_signatures() {
+        let mut study = study();
+        study.set_strict_search_space(true);
+        study.optimize(objective, 2).unwrap();
+        study.optimize(objective, 2).unwrap();
+        study.optimize(extended_objective, 2).unwrap();
+        assert_eq!(study.summary().unwrap().n_completed, 6);
+    }
+
+    #[test]
+    fn strict_search_space_rejects_a_drifted_signature() {
+        let mut study = study();
+        study.set_strict_search_space(true);
+        study.optimize(objective, 2).unwrap();
+        assert!(study.optimize(wider_objective, 2).is_err());
+        assert_eq!(study.summary().unwrap().n_completed, 2);
+    }
+
+    #[test]
+    fn drifted_signature_only_warns_by_default() {
+        let study = study();
+        study.optimize(objective, 2).unwrap();
+        study.optimize(wider_objective, 2).unwrap();
+        assert_eq!(study.summary().unwrap().n_completed, 4);
+    }
+
+    fn quadratic(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", -1.0, 1.0)?;
+        let n = trial.suggest_int("n", 0, 10)?;
+        Ok((x - 0.3).powi(2) + n as f64)
+    }
+
+    #[test]
+    fn seeded_parallel_runs_find_the_same_best_trial() {
+        let run = || {
+            let study = StudyBuilder::new()
+                .with_sampler(RandomSampler::new())
+                .with_seed(5)
+                .build()
+                .unwrap();
+            study.optimize_parallel(quadratic, 20, 4).unwrap();
+            let best_trial = study.best_trial().unwrap().unwrap();
+            (
+                best_trial.number,
+                best_trial.value,
+                best_trial.params().unwrap(),
+            )
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn dominates_ties_and_incomparable_points() {
+        let minimize = [StudyDirection::Minimize, StudyDirection::Minimize];
+        assert!(dominates(&[1.0, 2.0], &[2.0, 3.0], &minimize));
+        assert!(dominates(&[1.0, 2.0], &[1.0, 3.0], &minimize));
+        assert!(!dominates(&[2.0, 3.0], &[1.0, 2.0], &minimize));
+        assert!(!dominates(&[1.0, 2.0], &[1.0, 2.0], &minimize));
+        assert!(!dominates(&[1.0, 3.0], &[2.0, 1.0], &minimize));
+        assert!(!dominates(&[2.0, 1.0], &[1.0, 3.0], &minimize));
+    }
+
+    #[test]
+    fn dominates_with_mixed_directions() {
+        let mixed = [StudyDirection::Minimize, StudyDirection::Maximize];
+        assert!(dominates(&[1.0, 3.0], &[2.0, 1.0], &mixed));
+        assert!(dominates(&[1.0, 3.0], &[1.0, 2.0], &mixed));
+        assert!(!dominates(&[2.0, 1.0], &[1.0, 3.0], &mixed));
+        assert!(!dominates(&[1.0, 2.0], &[2.0, 3.0], &mixed));
+        assert!(!dominates(&[1.0, 3.0], &[1.0, 3.0], &mixed));
+    }
+
+    #[test]
+    fn dominates_treats_nan_as_the_worst_value() {
+        let minimize = [StudyDirection::Minimize, StudyDirection::Minimize];
+        let maximize = [StudyDirection::Maximize, StudyDirection::Maximize];
+        assert!(!dominates(&[1.0, f64::NAN], &[2.0, 5.0], &minimize));
+        assert!(dominates(&[2.0, 5.0], &[2.0, f64::NAN], &minimize));
+        assert!(dominates(&[2.0, 5.0], &[2.0, f64::NAN], &maximize));
+        assert!(!dominates(&[2.0, f64::NAN], &[2.0, 5.0], &maximize));
+        assert!(!dominates(&[f64::NAN, 1.0], &[f64::NAN, 1.0], &minimize));
+        assert!(dominates(&[f64::NAN, 1.0], &[f64::NAN, 2.0], &minimize));
+        assert!(dominates(
+            &[1.0, f64::INFINITY],
+            &[1.0, f64::NAN],
+            &minimize
+        ));
+    }
+
+    #[test]
+    fn bincode_round_trip_keeps_the_study() {
+        let study = create_named_study(
+            InMemoryStorage::new(),
+            RandomSampler::from_seed(9),
+            "checkpointed",
+            StudyDirection::Maximize,
+        )
+        .unwrap();
+        study.set_user_attr("dataset", "v2").unwrap();
+        study
+            .optimize(
+                |trial| {
+                    let x = trial.suggest_uniform("x", -1.0, 1.0)?;
+                    trial.report(x / 2.0, 0)?;
+                    let n = trial.suggest_int("n", 0, 10)?;
+                    Ok(x + n as f64)
+                },
+                5,
+            )
+            .unwrap();
+        let mut trial = study.ask().unwrap();
+        trial.suggest_uniform("x", -1.0, 1.0).unwrap();
+        trial.fail().unwrap();
+
+        let path = std::env::temp_dir().join(format!("rustuna-study-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        study.save_bincode(path).unwrap();
+        let loaded = Study::load_bincode(path, RandomSampler::new());
+        std::fs::remove_file(path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.study_name(), "checkpointed");
+        assert_eq!(loaded.direction(), StudyDirection::Maximize);
+        assert_eq!(loaded.user_attrs().unwrap(), study.user_attrs().unwrap());
+        assert_eq!(loaded.trials().unwrap(), study.trials().unwrap());
+        assert_eq!(loaded.best_trial().unwrap(), study.best_trial().unwrap());
+        loaded.optimize(quadratic, 1).unwrap();
+        assert_eq!(loaded.n_trials().unwrap(), 7);
+    }
+
+    fn temp_path(file_name: &str) -> String {
+        let file_name = format!("rustuna-{}-{}", std::process::id(), file_name);
+        std::env::temp_dir()
+            .join(file_name)
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn csv_has_a_row_per_trial_and_a_column_per_param() {
+        let study = study();
+        let choices = vec![
+            ExternalRepr::Str("a,b".to_string()),
+            ExternalRepr::Str("say \"hi\"".to_string()),
+        ];
+        let c = Distributions::Categorical(CategoricalDistribution::new(choices.clone()));
+        let x = Distributions::IntUni(IntUniformDistribution::new(0, 10));
+        let completed: HashMap<_, _> = vec![
+            ("c".to_string(), (c.clone(), choices[0].clone())),
+            ("x".to_string(), (x.clone(), ExternalRepr::Int(2))),
+        ]
+        .into_iter()
+        .collect();
+        study
+            .add_trial(completed, 1.5, FrozenTrialState::Completed)
+            .unwrap();
+        let failed: HashMap<_, _> = vec![("c".to_string(), (c, choices[1].clone()))]
+            .into_iter()
+            .collect();
+        study
+            .add_trial(failed, 0.0, FrozenTrialState::Failed)
+            .unwrap();
+        let mut running = study.ask().unwrap();
+        running.suggest_int("x", 4, 4).unwrap();
+
+        let path = temp_path("trials.csv");
+        study.to_csv(&path).unwrap();
+        let csv = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            csv.unwrap(),
+            "number,state,value,c,x\n\
+             0,Completed,1.5,\"a,b\",2\n\
+             1,Failed,,\"say \"\"hi\"\"\",\n\
+             2,Running,,,4\n"
+        );
+    }
+
+    #[test]
+    fn optuna_json_uses_optuna_names() {
+        let study = create_named_study(
+            InMemoryStorage::new(),
+            RandomSampler::new(),
+            "exported",
+            StudyDirection::Maximize,
+        )
+        .unwrap();
+        study.set_user_attr("dataset", "v2").unwrap();
+        let mut trial = study.ask().unwrap();
+        trial.suggest_int("x", 2, 2).unwrap();
+        trial
+            .suggest_categorical("c", vec![ExternalRepr::Bool(true)])
+            .unwrap();
+        trial.report(0.5, 3).unwrap();
+        study.tell(trial, 1.5).unwrap();
+        let mut trial = study.ask().unwrap();
+        trial.suggest_uniform("y", 0.0, 1.0).unwrap();
+        trial.fail().unwrap();
+
+        let path = temp_path("optuna.json");
+        study.to_optuna_json(&path).unwrap();
+        let exported = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        let exported: Value = serde_json::from_str(&exported.unwrap()).unwrap();
+
+        assert_eq!(exported["study_name"], "exported");
+        assert_eq!(exported["directions"], json!(["MAXIMIZE"]));
+        assert_eq!(exported["user_attrs"], json!({"dataset": "v2"}));
+        let completed = &exported["trials"][0];
+        assert_eq!(completed["number"], 0);
+        assert_eq!(completed["state"], "COMPLETE");
+        assert_eq!(completed["values"], json!([1.5]));
+        assert_eq!(completed["params"], json!({"x": 2, "c": true}));
+        assert_eq!(
+            completed["distributions"]["x"],
+            json!({
+                "name": "IntDistribution",
+                "attributes": {"low": 2, "high": 2, "log": false, "step": 1},
+            })
+        );
+        assert_eq!(
+            completed["distributions"]["c"],
+            json!({"name": "CategoricalDistribution", "attributes": {"choices": [true]}})
+        );
+        assert_eq!(completed["intermediate_values"], json!({"3": 0.5}));
+        assert!(completed["datetime_complete"].is_string());
+        let failed = &exported["trials"][1];
+        assert_eq!(failed["state"], "FAIL");
+        assert_eq!(failed["values"], Value::Null);
+        assert_eq!(
+            failed["distributions"]["y"]["attributes"],
+            json!({"low": 0.0, "high": 1.0, "log": false, "step": null})
+        );
+    }
+
+    #[test]
+    fn datetimes_are_formatted_in_utc() {
+        let leap_day = UNIX_EPOCH + Duration::from_micros(951_782_401_500_000);
+        assert_eq!(format_datetime(leap_day), "2000-02-29T00:00:01.500000");
+        assert_eq!(format_datetime(UNIX_EPOCH), "1970-01-01T00:00:00.000000");
+    }
+
+    #[test]
+    fn only_trials_with_an_old_heartbeat_are_stale() {
+        let study = study();
+        let beating = study.ask().unwrap().trial_id();
+        study.ask().unwrap();
+        study
+            .storage
+            .lock()
+            .unwrap()
+            .record_heartbeat(beating)
+            .unwrap();
+        assert!(study
+            .fail_stale_trials(Duration::from_secs(60))
+            .unwrap()
+            .is_empty());
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            study.fail_stale_trials(Duration::from_millis(10)).unwrap(),
+            vec![beating]
+        );
+        let states: Vec<FrozenTrialState> = study
+            .trials()
+            .unwrap()
+            .iter()
+            .map(|trial| trial.state)
+            .collect();
+        assert_eq!(
+            states,
+            vec![FrozenTrialState::Failed, FrozenTrialState::Running]
+        );
+        assert!(study.trials().unwrap()[1].heartbeat().is_none());
+    }
+
+    fn slow_objective(trial: &mut Trial) -> Result<f64> {
+        thread::sleep(Duration::from_millis(60));
+        trial.suggest_uniform("x", 0.0, 1.0)
+    }
+
+    #[test]
+    fn optimize_beats_while_running_and_fails_crashed_trials() {
+        let mut study = study();
+        assert!(study.set_heartbeat_interval(Duration::ZERO).is_err());
+        study
+            .set_heartbeat_interval(Duration::from_millis(10))
+            .unwrap();
+        let crashed = study.ask().unwrap().trial_id();
+        study
+            .storage
+            .lock()
+            .unwrap()
+            .record_heartbeat(crashed)
+            .unwrap();
+        thread::sleep(Duration::from_millis(30));
+
+        let start = SystemTime::now();
+        study.optimize(slow_objective, 1).unwrap();
+        let trials = study.trials().unwrap();
+        assert_eq!(trials[0].state, FrozenTrialState::Failed);
+        assert_eq!(trials[1].state, FrozenTrialState::Completed);
+        let last_beat = trials[1].heartbeat().unwrap();
+        assert!(last_beat.duration_since(start).unwrap() >= Duration::from_millis(20));
+    }
+
+    thread_local! {
+        static FAILURES_LEFT: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+    }
+
+    /// Fails while `FAILURES_LEFT` is positive, counting it down.
+    fn flaky_objective(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", 0.0, 1.0)?;
+        let failures_left = FAILURES_LEFT.with(|left| left.replace(left.get().saturating_sub(1)));
+        if failures_left > 0 {
+            return Err(anyhow!("flaky failure"));
+        }
+        Ok(x)
+    }
+
+    fn internal_xs(study: &Study) -> Vec<f64> {
+        study
+            .trials()
+            .unwrap()
+            .iter()
+            .map(|trial| trial.internal_params["x"])
+            .collect()
+    }
+
+    #[test]
+    fn failed_trials_are_retried_with_the_same_params() {
+        let mut study = study();
+        study.set_max_retries(2);
+        FAILURES_LEFT.with(|left| left.set(2));
+        study.optimize(flaky_objective, 4).unwrap();
+
+        let states: Vec<FrozenTrialState> = study
+            .trials()
+            .unwrap()
+            .iter()
+            .map(|trial| trial.state)
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                FrozenTrialState::Failed,
+                FrozenTrialState::Failed,
+                FrozenTrialState::Completed,
+                FrozenTrialState::Completed,
+            ]
+        );
+        let xs = internal_xs(&study);
+        assert_eq!(xs[0], xs[1]);
+        assert_eq!(xs[0], xs[2]);
+        assert_ne!(xs[2], xs[3]);
+    }
+
+    #[test]
+    fn optimize_fails_once_the_retries_are_used_up() {
+        let mut study = study();
+        study.set_max_retries(1);
+        FAILURES_LEFT.with(|left| left.set(u32::MAX));
+        assert!(study.optimize(flaky_objective, 5).is_err());
+        assert_eq!(
+            study
+                .get_trials(Some(FrozenTrialState::Failed))
+                .unwrap()
+                .len(),
+            2
+        );
+        let xs = internal_xs(&study);
+        assert_eq!(xs[0], xs[1]);
+    }
+
+    #[test]
+    fn copied_studies_keep_the_history() {
+        let mut study = create_named_study(
+            InMemoryStorage::new(),
+            RandomSampler::new(),
+            "source",
+            StudyDirection::Maximize,
+        )
+        .unwrap();
+        study.set_seed(4);
+        study.set_user_attr("dataset", "v2").unwrap();
+        study
+            .optimize(
+                |trial| {
+                    let x = trial.suggest_uniform("x", -1.0, 1.0)?;
+                    trial.report(x / 2.0, 0)?;
+                    trial.report_constraints(vec![x])?;
+                    trial.set_user_attr("note", "copied")?;
+                    let c = trial.suggest_bool("c")?;
+                    Ok(x + f64::from(u8::from(c)))
+                },
+                3,
+            )
+            .unwrap();
+        let mut failed = study.ask().unwrap();
+        failed.suggest_int("n", 0, 10).unwrap();
+        failed.fail().unwrap();
+        study.ask().unwrap();
+
+        let copy = study
+            .copy_study(
+                crate::storages::SqliteStorage::open(":memory:").unwrap(),
+                RandomSampler::new(),
+            )
+            .unwrap();
+        assert_eq!(copy.study_name(), "source");
+        assert_eq!(copy.directions(), study.directions());
+        assert_eq!(copy.user_attrs().unwrap(), study.user_attrs().unwrap());
+        let originals = study.trials().unwrap();
+        let copies = copy.trials().unwrap();
+        assert_eq!(copies.len(), originals.len());
+        for (copy, original) in copies.iter().zip(&originals) {
+            assert_eq!(copy.number, original.number);
+            assert_eq!(copy.state, original.state);
+            assert_eq!(copy.values, original.values);
+            assert_eq!(copy.internal_params, original.internal_params);
+            assert_eq!(copy.distributions, original.distributions);
+            assert_eq!(copy.intermediate_values, original.intermediate_values);
+            assert_eq!(copy.seed, original.seed);
+            assert_eq!(copy.user_attrs, original.user_attrs);
+            assert_eq!(copy.constraints, original.constraints);
+        }
+        assert_eq!(copies[4].state, FrozenTrialState::Running);
+        assert_eq!(
+            copy.best_trial().unwrap().unwrap().number,
+            study.best_trial().unwrap().unwrap().number
+        );
+
+        copy.optimize(quadratic, 1).unwrap();
+        assert_eq!(copy.n_trials().unwrap(), 6);
+        assert_eq!(study.n_trials().unwrap(), 5);
+    }
+
+    #[test]
+    fn timeout_stops_starting_trials_and_finishes_the_last() {
+        let study = study();
+        let started = Instant::now();
+        study
+            .optimize_with_timeout(slow_objective, None, Some(Duration::from_millis(150)))
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        let trials = study.trials().unwrap();
+        assert!((2..=3).contains(&trials.len()), "{} trials", trials.len());
+        assert!(trials
+            .iter()
+            .all(|trial| trial.state == FrozenTrialState::Completed));
+    }
+
+    #[test]
+    fn n_trials_can_end_a_timed_optimization_first() {
+        let study = study();
+        study
+            .optimize_with_timeout(objective, Some(3), Some(Duration::from_secs(60)))
+            .unwrap();
+        assert_eq!(study.n_trials().unwrap(), 3);
+        assert!(study.optimize_with_timeout(objective, None, None).is_err());
+        assert_eq!(study.n_trials().unwrap(), 3);
+    }
+
+    fn panicking_objective(trial: &mut Trial) -> Result<f64> {
+        let x = trial.suggest_uniform("x", 0.0, 1.0)?;
+        if x >= 0.0 {
+            panic!("objective bug");
+        }
+        Ok(x)
+    }
+
+    #[test]
+    fn objective_panics_fail_the_trial() {
+        let mut study = study();
+        let err = study.optimize(panicking_objective, 3).unwrap_err();
+        assert!(format!("{:#}", err).contains("objective bug"), "{:#}", err);
+        study.set_catch(true);
+        study.optimize(panicking_objective, 2).unwrap();
+        let states: Vec<FrozenTrialState> = study
+            .trials()
+            .unwrap()
+            .iter()
+            .map(|trial| trial.state)
+            .collect();
+        assert_eq!(states, vec![FrozenTrialState::Failed; 3]);
+    }
+
+    /// Suggests the midpoint of uniform params, panicking on its first call.
+    struct PanicOnceSampler {
+        panicked: bool,
+    }
+
+    impl Sampler for PanicOnceSampler {
+        fn reseed(&mut self, _seed: u64) {}
+
+        fn sample_independent(
+            &mut self,
+            _study: &Study,
+            _trial_id: usize,
+            _name: &str,
+            distribution: Distributions,
+        ) -> Result<f64> {
+            if !self.panicked {
+                self.panicked = true;
+                panic!("sampler bug");
+            }
+            match distribution {
+                Distributions::Uni(distribution) => {
+                    Ok((distribution.low + distribution.high) / 2.0)
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        fn sample_independent_int(
+            &mut self,
+            _study: &Study,
+            _trial_id: usize,
+            _name: &str,
+            _distribution: Distributions,
+        ) -> Result<i64> {
+            unimplemented!()
+        }
+
+        fn sample_independent_category(
+            &mut self,
+            _study: &Study,
+            _trial_id: usize,
+            _name: &str,
+            _distribution: Distributions,
+        ) -> Result<ExternalRepr> {
+            unimplemented!()
+        }
+    }
+
+    fn uniform_objective(trial: &mut Trial) -> Result<f64> {
+        trial.suggest_uniform("x", 0.0, 1.0)
+    }
+
+    #[test]
+    fn a_sampler_panic_does_not_poison_later_trials() {
+        let mut study = create_study(
+            InMemoryStorage::new(),
+            PanicOnceSampler { panicked: false },
+            StudyDirection::Minimize,
+        );
+        study.set_catch(true);
+        study.optimize(uniform_objective, 2).unwrap();
+        let trials = study.trials().unwrap();
+        assert_eq!(trials[0].state, FrozenTrialState::Failed);
+        assert_eq!(trials[1].state, FrozenTrialState::Completed);
+        assert_eq!(trials[1].value, 0.5);
+        assert!(study.sampler.is_poisoned());
+    }
+
+    #[test]
+    fn zero_heartbeat_intervals_are_errors() {
+        assert!(StudyBuilder::new()
+            .with_heartbeat_interval(Duration::ZERO)
+            .is_err());
+        let study = StudyBuilder::new()
+            .with_heartbeat_interval(Duration::from_secs(1))
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(study.heartbeat_interval, Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn exhausted_grids_count_their_unique_points() {
+        let search_space: HashMap<String, Vec<ExternalRepr>> = vec![
+            ("x", vec![ExternalRepr::Int(0), ExternalRepr::Int(1)]),
+            (
+                "y",
+                vec![
+                    ExternalRepr::Int(0),
+                    ExternalRepr::Int(5),
+                    ExternalRepr::Int(10),
+                ],
+            ),
+        ]
+        .into_iter()
+        .map(|(name, values)| (name.to_string(), values))
+        .collect();
+        let study = create_study(
+            InMemoryStorage::new(),
+            crate::samplers::GridSampler::new(search_space),
+            StudyDirection::Minimize,
+        );
+        study.optimize(objective, 100).unwrap();
+        assert_eq!(study.n_trials().unwrap(), 6);
+        assert_eq!(study.n_unique_points().unwrap(), 6);
+
+        let x = Distributions::IntUni(IntUniformDistribution::new(0, 10));
+        let repeated: HashMap<_, _> = vec![
+            ("x".to_string(), (x.clone(), ExternalRepr::Int(1))),
+            ("y".to_string(), (x, ExternalRepr::Int(5))),
+        ]
+        .into_iter()
+        .collect();
+        study
+            .add_trial(repeated, 6.0, FrozenTrialState::Completed)
+            .unwrap();
+        assert_eq!(study.n_unique_points().unwrap(), 6);
+    }
+
+    #[test]
+    fn trial_seeds_mix_the_study_seed_with_the_trial_id() {
+        let mut study = study();
+        study.set_seed(10);
+        study.optimize(objective, 3).unwrap();
+        let seeds: Vec<(usize, Option<u64>)> = study
+            .trials()
+            .unwrap()
+            .iter()
+            .map(|trial| (trial.trial_id, trial.seed))
+            .collect();
+        assert_eq!(seeds, vec![(0, Some(10)), (1, Some(11)), (2, Some(8))]);
+    }
+}
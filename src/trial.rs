@@ -0,0 +1,1042 @@
+use crate::distributions::{
+    BoolDistribution, CategoricalDistribution, DiscreteUniformDistribution, Distribution,
+    Distributions, ExternalRepr, IntUniformDistribution, LogUniformDistribution,
+    UniformDistribution,
+};
+use crate::error::RustunaError;
+use crate::samplers::Sampler;
+use crate::study::Study;
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum FrozenTrialState {
+    Running,
+    Completed,
+    Failed,
+    Pruned,
+}
+
+/// How `Trial::report` treats a step that is not after every step reported so far.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ReportPolicy {
+    /// Stores the value, replacing any earlier report at the same step.
+    Overwrite,
+    /// Returns an error, leaving the intermediate values untouched.
+    Reject,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrozenTrial {
+    pub(crate) trial_id: usize,
+    /// Id of the study owning the trial within its storage.
+    #[serde(default)]
+    pub(crate) study_id: usize,
+    /// Position of the trial within its study, counting from zero.
+    #[serde(default)]
+    pub(crate) number: usize,
+    pub(crate) state: FrozenTrialState,
+    #[serde(deserialize_with = "deserialize_value")]
+    pub(crate) value: f64,
+    /// Every objective value; `value` is the first of them.
+    #[serde(default, deserialize_with = "deserialize_values")]
+    pub(crate) values: Vec<f64>,
+    pub(crate) internal_params: HashMap<String, f64>,
+    pub(crate) distributions: HashMap<String, Distributions>,
+    #[serde(deserialize_with = "deserialize_intermediate_values")]
+    pub(crate) intermediate_values: BTreeMap<usize, f64>,
+    pub(crate) seed: Option<u64>,
+    #[serde(default)]
+    pub(crate) user_attrs: HashMap<String, String>,
+    /// Constraint values reported with `Trial::report_constraints`, each feasible when `<= 0`.
+    #[serde(default, deserialize_with = "deserialize_values")]
+    pub(crate) constraints: Vec<f64>,
+    #[serde(default)]
+    pub(crate) datetime_start: Option<SystemTime>,
+    #[serde(default)]
+    pub(crate) datetime_complete: Option<SystemTime>,
+    /// Last time the worker running the trial reported it alive.
+    #[serde(default)]
+    pub(crate) heartbeat: Option<SystemTime>,
+}
+
+/// Flat view of a trial with one column per param of its study, as returned
+/// by `Study::trials_records`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TrialRecord {
+    pub number: usize,
+    pub state: FrozenTrialState,
+    pub value: f64,
+    pub duration: Option<Duration>,
+    /// External value of every param of the study, `None` if this trial did not suggest it.
+    pub params: BTreeMap<String, Option<ExternalRepr>>,
+}
+
+/// A trial on the Pareto front, as returned by `Study::get_trials_as_pareto`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ParetoPoint {
+    pub number: usize,
+    /// One value per study direction.
+    pub values: Vec<f64>,
+    pub params: HashMap<String, ExternalRepr>,
+}
+
+/// A trial without its params, intermediate values and attributes, as returned
+/// by `Study::get_trial_summaries`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct TrialSummary {
+    pub trial_id: usize,
+    pub number: usize,
+    pub state: FrozenTrialState,
+    pub value: f64,
+    pub datetime_start: Option<SystemTime>,
+    pub datetime_complete: Option<SystemTime>,
+}
+
+/// serde_json writes non-finite floats as `null`, so read those back as NaN.
+fn deserialize_value<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    if deserializer.is_human_readable() {
+        Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::NAN))
+    } else {
+        f64::deserialize(deserializer)
+    }
+}
+
+/// Like `deserialize_value`, for each value of a list.
+fn deserialize_values<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f64>, D::Error> {
+    if deserializer.is_human_readable() {
+        let values = Vec::<Option<f64>>::deserialize(deserializer)?;
+        Ok(values
+            .into_iter()
+            .map(|value| value.unwrap_or(f64::NAN))
+            .collect())
+    } else {
+        Vec::deserialize(deserializer)
+    }
+}
+
+/// Like `deserialize_value`, for each value reported at a step.
+fn deserialize_intermediate_values<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<usize, f64>, D::Error> {
+    if deserializer.is_human_readable() {
+        let values = BTreeMap::<usize, Option<f64>>::deserialize(deserializer)?;
+        Ok(values
+            .into_iter()
+            .map(|(step, value)| (step, value.unwrap_or(f64::NAN)))
+            .collect())
+    } else {
+        BTreeMap::deserialize(deserializer)
+    }
+}
+
+impl FrozenTrial {
+    pub(crate) fn new(trial_id: usize, state: FrozenTrialState, value: f64) -> Self {
+        Self {
+            trial_id,
+            study_id: 0,
+            number: 0,
+            state,
+            value,
+            values: vec![],
+            internal_params: HashMap::new(),
+            distributions: HashMap::new(),
+            intermediate_values: BTreeMap::new(),
+            seed: None,
+            user_attrs: HashMap::new(),
+            constraints: vec![],
+            datetime_start: None,
+            datetime_complete: None,
+            heartbeat: None,
+        }
+    }
+
+    pub fn trial_id(&self) -> usize {
+        self.trial_id
+    }
+
+    pub(crate) fn summary(&self) -> TrialSummary {
+        TrialSummary {
+            trial_id: self.trial_id,
+            number: self.number,
+            state: self.state,
+            value: self.value,
+            datetime_start: self.datetime_start,
+            datetime_complete: self.datetime_complete,
+        }
+    }
+
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    pub fn state(&self) -> FrozenTrialState {
+        self.state
+    }
+
+    /// Objective value. Values and internal params are always stored as `f64`,
+    /// whatever the precision of the objective.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    pub fn internal_params(&self) -> &HashMap<String, f64> {
+        &self.internal_params
+    }
+
+    /// Distribution each param was suggested from, e.g. to rebuild the search
+    /// space when warm-starting another study.
+    pub fn distributions(&self) -> &HashMap<String, Distributions> {
+        &self.distributions
+    }
+
+    /// Values reported with `Trial::report`, keyed by step.
+    pub fn intermediate_values(&self) -> &BTreeMap<usize, f64> {
+        &self.intermediate_values
+    }
+
+    pub fn user_attrs(&self) -> &HashMap<String, String> {
+        &self.user_attrs
+    }
+
+    pub fn constraints(&self) -> &[f64] {
+        &self.constraints
+    }
+
+    /// Total amount by which the constraints are violated, infinite if one is NaN.
+    pub fn constraint_violation(&self) -> f64 {
+        self.constraints
+            .iter()
+            .map(|&c| {
+                if c.is_nan() {
+                    f64::INFINITY
+                } else {
+                    c.max(0.0)
+                }
+            })
+            .sum()
+    }
+
+    /// Whether every constraint is satisfied, which holds for a trial reporting none.
+    pub fn is_feasible(&self) -> bool {
+        self.constraint_violation() == 0.0
+    }
+
+    pub fn datetime_start(&self) -> Option<SystemTime> {
+        self.datetime_start
+    }
+
+    pub fn datetime_complete(&self) -> Option<SystemTime> {
+        self.datetime_complete
+    }
+
+    /// Last heartbeat of a study with `Study::set_heartbeat_interval`.
+    pub fn heartbeat(&self) -> Option<SystemTime> {
+        self.heartbeat
+    }
+
+    /// Wall-clock time the trial took, if it has finished.
+    pub fn duration(&self) -> Option<Duration> {
+        self.datetime_complete?
+            .duration_since(self.datetime_start?)
+            .ok()
+    }
+
+    pub fn is_finised(&self) -> bool {
+        !matches!(self.state, FrozenTrialState::Running)
+    }
+
+    /// External values of the params, or an error naming a param stored
+    /// without its distribution.
+    pub fn params(&self) -> Result<HashMap<String, ExternalRepr>> {
+        let mut external_repr: HashMap<String, ExternalRepr> = HashMap::new();
+        for (param_name, &internal_repr) in &self.internal_params {
+            let distribution = self
+                .distributions
+                .get(param_name)
+                .ok_or_else(|| anyhow!("Missing distribution of param {}", param_name))?;
+            match distribution {
+                Distributions::Uni(dist) => {
+                    external_repr.insert(
+                        param_name.to_string(),
+                        ExternalRepr::Float(dist.to_external_repr(internal_repr)),
+                    );
+                }
+                Distributions::IntUni(dist) => {
+                    external_repr.insert(
+                        param_name.to_string(),
+                        ExternalRepr::Int(dist.to_external_repr(internal_repr)),
+                    );
+                }
+                Distributions::Categorical(dist) => {
+                    external_repr
+                        .insert(param_name.to_string(), dist.to_external_repr(internal_repr));
+                }
+                Distributions::LogUni(dist) => {
+                    external_repr.insert(
+                        param_name.to_string(),
+                        ExternalRepr::Float(dist.to_external_repr(internal_repr)),
+                    );
+                }
+                Distributions::DiscreteUni(dist) => {
+                    external_repr.insert(
+                        param_name.to_string(),
+                        ExternalRepr::Float(dist.to_external_repr(internal_repr)),
+                    );
+                }
+                Distributions::Bool(dist) => {
+                    external_repr.insert(
+                        param_name.to_string(),
+                        ExternalRepr::Bool(dist.to_external_repr(internal_repr)),
+                    );
+                }
+            };
+        }
+        Ok(external_repr)
+    }
+}
+
+/// One line with the number, state, value and params of the trial, the
+/// params sorted by name.
+impl fmt::Display for FrozenTrial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "trial number={} state={:?} value={} params={{",
+            self.number, self.state, self.value
+        )?;
+        // Params stored without their distribution are shown in internal form.
+        let params = self.params().unwrap_or_default();
+        let mut names: Vec<&String> = self.internal_params.keys().collect();
+        names.sort();
+        for (i, name) in names.into_iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match params.get(name) {
+                Some(value) => write!(f, "{}={}", name, value)?,
+                None => write!(f, "{}={}", name, self.internal_params[name])?,
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+pub struct Trial {
+    pub(crate) study: Study,
+    pub(crate) trial_id: usize,
+    pub(crate) number: usize,
+    pub(crate) fixed_params: HashMap<String, f64>,
+    pub(crate) enqueued_params: HashMap<String, ExternalRepr>,
+    /// Internal values drawn by `Sampler::sample_relative`, with their distribution.
+    pub(crate) relative_params: HashMap<String, (Distributions, f64)>,
+    /// Seed recorded for the trial by a seeded study.
+    pub(crate) seed: Option<u64>,
+    /// How many failed trials before this one ran the same params.
+    pub(crate) retry_attempt: u32,
+    pub(crate) pruned: bool,
+}
+
+impl Trial {
+    pub(crate) fn new(study: Study, trial_id: usize, number: usize) -> Self {
+        Trial {
+            study,
+            trial_id,
+            number,
+            fixed_params: HashMap::new(),
+            enqueued_params: HashMap::new(),
+            relative_params: HashMap::new(),
+            seed: None,
+            retry_attempt: 0,
+            pruned: false,
+        }
+    }
+
+    pub fn trial_id(&self) -> usize {
+        self.trial_id
+    }
+
+    /// Sequential position of this trial within its study, which unlike the
+    /// id does not depend on other studies sharing the storage.
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// External values of the params suggested so far in this trial, read back
+    /// from storage.
+    pub fn params(&self) -> Result<HashMap<String, ExternalRepr>> {
+        self.study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_trial(self.trial_id)?
+            .params()
+    }
+
+    pub fn suggest_uniform(&mut self, name: &str, low: f64, high: f64) -> Result<f64> {
+        check_bounds(name, low, high)?;
+        let distribution = UniformDistribution::new(low, high);
+        let distribution_enum = Distributions::Uni(UniformDistribution::new(low, high));
+        if let Some(internal_repr) = self.cached_param(name, &distribution_enum)? {
+            return Ok(distribution.to_external_repr(internal_repr));
+        }
+        let param_value = if let Some(internal_repr) = self.fixed_param(name, &distribution_enum)? {
+            distribution.to_external_repr(internal_repr)
+        } else if distribution.is_single() {
+            low
+        } else {
+            let value = self.sampler(name).sample_independent(
+                &self.study,
+                self.trial_id,
+                name,
+                distribution_enum,
+            )?;
+            value.max(low).min(high)
+        };
+        let param_value_in_internal_repr = distribution.to_internal_repr(param_value);
+        self.study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_trial_param(
+                self.trial_id,
+                name,
+                Distributions::Uni(distribution),
+                param_value_in_internal_repr,
+            )?;
+        Ok(param_value)
+    }
+
+    /// Suggests a float in `[low, high]`, restricted to the grid
+    /// `low, low + step, ...` when `step` is given.
+    pub fn suggest_float(
+        &mut self,
+        name: &str,
+        low: f64,
+        high: f64,
+        step: Option<f64>,
+    ) -> Result<f64> {
+        let step = match step {
+            Some(step) => step,
+            None => return self.suggest_uniform(name, low, high),
+        };
+        check_bounds(name, low, high)?;
+        if step.is_nan() || step <= 0.0 {
+            return Err(anyhow!(
+                "Step must be positive for param {}: step={}",
+                name,
+                step
+            ));
+        }
+        let distribution = DiscreteUniformDistribution::new(low, high, step);
+        let distribution_enum = Distributions::DiscreteUni(distribution.clone());
+        if let Some(internal_repr) = self.cached_param(name, &distribution_enum)? {
+            return Ok(distribution.to_external_repr(internal_repr));
+        }
+        let param_value = if let Some(internal_repr) = self.fixed_param(name, &distribution_enum)? {
+            distribution.to_external_repr(internal_repr)
+        } else if distribution.is_single() {
+            low
+        } else {
+            self.sampler(name).sample_independent(
+                &self.study,
+                self.trial_id,
+                name,
+                distribution_enum,
+            )?
+        };
+        let param_value = distribution.to_external_repr(param_value);
+        let param_value_in_internal_repr = distribution.to_internal_repr(param_value);
+        self.study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_trial_param(
+                self.trial_id,
+                name,
+                Distributions::DiscreteUni(distribution),
+                param_value_in_internal_repr,
+            )?;
+        Ok(param_value)
+    }
+
+    /// Suggests a float on the grid `low, low + q, ...` within `[low, high]`;
+    /// shorthand for `suggest_float` with `Some(q)` as step.
+    pub fn suggest_discrete_uniform(
+        &mut self,
+        name: &str,
+        low: f64,
+        high: f64,
+        q: f64,
+    ) -> Result<f64> {
+        self.suggest_float(name, low, high, Some(q))
+    }
+
+    pub fn suggest_log(&mut self, name: &str, low: f64, high: f64) -> Result<f64> {
+        check_bounds(name, low, high)?;
+        if low <= 0.0 {
+            return Err(anyhow!(
+                "Low must be positive for log param {}: low={}",
+                name,
+                low
+            ));
+        }
+        let distribution = LogUniformDistribution::new(low, high);
+        let distribution_enum = Distributions::LogUni(LogUniformDistribution::new(low, high));
+        if let Some(internal_repr) = self.cached_param(name, &distribution_enum)? {
+            return Ok(distribution.to_external_repr(internal_repr));
+        }
+        let param_value = if let Some(internal_repr) = self.fixed_param(name, &distribution_enum)? {
+            distribution.to_external_repr(internal_repr)
+        } else if distribution.is_single() {
+            low
+        } else {
+            let value = self.sampler(name).sample_independent(
+                &self.study,
+                self.trial_id,
+                name,
+                distribution_enum,
+            )?;
+            value.max(low).min(high)
+        };
+        let param_value_in_internal_repr = distribution.to_internal_repr(param_value);
+        self.study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_trial_param(
+                self.trial_id,
+                name,
+                Distributions::LogUni(distribution),
+                param_value_in_internal_repr,
+            )?;
+        Ok(param_value)
+    }
+
+    /// Suggests one of `choices`, returned as the same variant it was given as.
+    pub fn suggest_categorical(
+        &mut self,
+        name: &str,
+        choices: Vec<ExternalRepr>,
+    ) -> Result<ExternalRepr> {
+        if choices.is_empty() {
+            return Err(anyhow!("No choices given for param {}", name));
+        }
+        self.suggest_from_categorical(name, CategoricalDistribution::new(choices))
+    }
+
+    /// Like `suggest_categorical`, but samplers drawing at random pick each
+    /// choice with probability proportional to its weight.
+    pub fn suggest_weighted_categorical(
+        &mut self,
+        name: &str,
+        choices: Vec<ExternalRepr>,
+        weights: Vec<f64>,
+    ) -> Result<ExternalRepr> {
+        if choices.is_empty() {
+            return Err(anyhow!("No choices given for param {}", name));
+        }
+        let distribution = CategoricalDistribution::new(choices)
+            .with_weights(weights)
+            .with_context(|| format!("Invalid weights for param {}", name))?;
+        self.suggest_from_categorical(name, distribution)
+    }
+
+    fn suggest_from_categorical(
+        &mut self,
+        name: &str,
+        distribution: CategoricalDistribution,
+    ) -> Result<ExternalRepr> {
+        let distribution_enum = Distributions::Categorical(distribution.clone());
+        if let Some(internal_repr) = self.cached_param(name, &distribution_enum)? {
+            return Ok(distribution.to_external_repr(internal_repr));
+        }
+        let param_value = if let Some(internal_repr) = self.fixed_param(name, &distribution_enum)? {
+            distribution.to_external_repr(internal_repr)
+        } else {
+            self.sampler(name).sample_independent_category(
+                &self.study,
+                self.trial_id,
+                name,
+                distribution_enum,
+            )?
+        };
+        let param_value_in_internal_repr = distribution.index_of(name, &param_value)? as f64;
+        self.study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_trial_param(
+                self.trial_id,
+                name,
+                Distributions::Categorical(distribution),
+                param_value_in_internal_repr,
+            )?;
+        Ok(param_value)
+    }
+
+    /// Suggests `true` or `false` with equal probability.
+    pub fn suggest_bool(&mut self, name: &str) -> Result<bool> {
+        let distribution = BoolDistribution;
+        let distribution_enum = Distributions::Bool(BoolDistribution);
+        if let Some(internal_repr) = self.cached_param(name, &distribution_enum)? {
+            return Ok(distribution.to_external_repr(internal_repr));
+        }
+        let param_value_in_internal_repr =
+            if let Some(internal_repr) = self.fixed_param(name, &distribution_enum)? {
+                internal_repr
+            } else {
+                self.sampler(name).sample_independent(
+                    &self.study,
+                    self.trial_id,
+                    name,
+                    distribution_enum,
+                )?
+            };
+        let param_value = distribution.to_external_repr(param_value_in_internal_repr);
+        let param_value_in_internal_repr = distribution.to_internal_repr(param_value);
+        self.study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_trial_param(
+                self.trial_id,
+                name,
+                Distributions::Bool(distribution),
+                param_value_in_internal_repr,
+            )?;
+        Ok(param_value)
+    }
+
+    pub fn suggest_int(&mut self, name: &str, low: i64, high: i64) -> Result<i64> {
+        self.suggest_int_distribution(name, IntUniformDistribution::new(low, high))
+    }
+
+    /// Suggests an integer from the grid `low, low + step, ...` within `[low, high]`.
+    pub fn suggest_int_step(&mut self, name: &str, low: i64, high: i64, step: i64) -> Result<i64> {
+        self.suggest_int_distribution(name, IntUniformDistribution::new(low, high).with_step(step))
+    }
+
+    /// Suggests an integer in `[low, high]` sampled uniformly in log space.
+    pub fn suggest_int_log(&mut self, name: &str, low: i64, high: i64) -> Result<i64> {
+        self.suggest_int_distribution(name, IntUniformDistribution::new(low, high).with_log(true))
+    }
+
+    fn suggest_int_distribution(
+        &mut self,
+        name: &str,
+        distribution: IntUniformDistribution,
+    ) -> Result<i64> {
+        check_bounds(name, distribution.low as f64, distribution.high as f64)?;
+        if distribution.step <= 0 {
+            return Err(anyhow!(
+                "Step must be positive for param {}: step={}",
+                name,
+                distribution.step
+            ));
+        }
+        if distribution.log && distribution.low <= 0 {
+            return Err(anyhow!(
+                "Low must be positive for log param {}: low={}",
+                name,
+                distribution.low
+            ));
+        }
+        let distribution_enum = Distributions::IntUni(distribution.clone());
+        if let Some(internal_repr) = self.cached_param(name, &distribution_enum)? {
+            return Ok(distribution.to_external_repr(internal_repr));
+        }
+        let param_value = if let Some(internal_repr) = self.fixed_param(name, &distribution_enum)? {
+            distribution.to_external_repr(internal_repr)
+        } else if distribution.is_single() {
+            distribution.low
+        } else {
+            self.sampler(name).sample_independent_int(
+                &self.study,
+                self.trial_id,
+                name,
+                distribution_enum,
+            )?
+        };
+        let param_value_in_internal_repr = distribution.to_internal_repr(param_value);
+        self.study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_trial_param(
+                self.trial_id,
+                name,
+                Distributions::IntUni(distribution),
+                param_value_in_internal_repr,
+            )?;
+        Ok(param_value)
+    }
+
+    /// Records the intermediate `value` at `step`, for pruners to judge the trial by.
+    pub fn report(&mut self, value: f64, step: usize) -> Result<()> {
+        let trial = self
+            .study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_trial(self.trial_id)?;
+        if self.study.report_policy == ReportPolicy::Reject {
+            if let Some(&last) = trial.intermediate_values.keys().next_back() {
+                if step <= last {
+                    return Err(anyhow!(
+                        "Step {} is not after the last reported step {} for trial id: {}",
+                        step,
+                        last,
+                        self.trial_id
+                    ));
+                }
+            }
+        }
+        self.study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_trial_intermediate_value(self.trial_id, step, value)
+    }
+
+    /// Attaches metadata such as a git commit or dataset version to this trial.
+    pub fn set_user_attr(&mut self, key: &str, value: &str) -> Result<()> {
+        self.study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_trial_user_attr(self.trial_id, key, value)
+    }
+
+    /// Records constraint values for this trial, each satisfied when it is `<= 0`.
+    /// Feasible trials are preferred by `Study::best_trial` and `NsgaIISampler`.
+    pub fn report_constraints(&mut self, constraints: Vec<f64>) -> Result<()> {
+        self.study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_trial_constraints(self.trial_id, &constraints)
+    }
+
+    /// Finishes the trial as `Completed` with `value`, for trials driven outside
+    /// of `optimize`. A NaN or infinite `value` marks the trial `Failed` instead.
+    /// Fails if the trial is already finished.
+    pub fn complete(self, value: f64) -> Result<()> {
+        if !value.is_finite() {
+            warn!(
+                "trial_id={} failed: told non-finite value {}",
+                self.trial_id, value
+            );
+            return self.fail();
+        }
+        let mut storage = self
+            .study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        storage.set_trial_value(self.trial_id, value)?;
+        storage.set_trial_state(self.trial_id, FrozenTrialState::Completed)
+    }
+
+    /// Finishes the trial as `Failed`. Fails if the trial is already finished.
+    pub fn fail(self) -> Result<()> {
+        self.study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_trial_state(self.trial_id, FrozenTrialState::Failed)
+    }
+
+    /// Finishes the trial as `Pruned`, keeping its last reported value as its
+    /// value. Fails if the trial is already finished.
+    pub fn prune(self) -> Result<()> {
+        let mut storage = self
+            .study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let trial = storage.get_trial(self.trial_id)?;
+        if let Some(&value) = trial.intermediate_values.values().next_back() {
+            storage.set_trial_value(self.trial_id, value)?;
+        }
+        storage.set_trial_state(self.trial_id, FrozenTrialState::Pruned)
+    }
+
+    /// Locks the study sampler, reseeding it for `name` when the trial is seeded so
+    /// the value does not depend on what other trials sampled in the meantime.
+    fn sampler(&self, name: &str) -> MutexGuard<'_, dyn Sampler + 'static> {
+        let mut sampler = self
+            .study
+            .sampler
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(seed) = self.seed {
+            sampler.reseed(param_seed(seed, name));
+        }
+        sampler
+    }
+
+    /// Internal value forced for `name` by `reproduce_trial` or `Study::enqueue_trial`,
+    /// or else sampled for it by `Sampler::sample_relative`.
+    fn fixed_param(&self, name: &str, distribution: &Distributions) -> Result<Option<f64>> {
+        if let Some(&internal_repr) = self.fixed_params.get(name) {
+            return Ok(Some(internal_repr));
+        }
+        if let Some(value) = self.enqueued_params.get(name) {
+            return distribution
+                .coerced_internal_repr_of(name, value)
+                .map(Some)
+                .map_err(|err| err.context(format!("Invalid enqueued value of param {}", name)));
+        }
+        match self.relative_params.get(name) {
+            Some((relative_distribution, internal_repr))
+                if relative_distribution == distribution =>
+            {
+                Ok(Some(*internal_repr))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the internal value of `name` if it was already suggested in
+    /// this trial, so repeated calls read the same value instead of resampling.
+    /// A param new to the trial is checked against the kind of distribution
+    /// earlier trials suggested it with. Errors if the trial is already finished.
+    fn cached_param(&self, name: &str, distribution: &Distributions) -> Result<Option<f64>> {
+        let trial = self
+            .study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_trial(self.trial_id)?;
+        // Storage would refuse a new param anyway, but not a cached one.
+        if trial.is_finised() {
+            return Err(RustunaError::TrialAlreadyFinished(self.trial_id).into());
+        }
+        match trial.distributions.get(name) {
+            None => self
+                .study
+                .check_param_kind(name, distribution)
+                .map(|()| None),
+            Some(existing) if existing == distribution => Ok(Some(trial.internal_params[name])),
+            Some(existing) => Err(anyhow!(
+                "Param {} is already suggested with a different distribution: {:?} != {:?}",
+                name,
+                existing,
+                distribution
+            )),
+        }
+    }
+
+    /// Asks the study's pruner whether this trial should stop early. Once this
+    /// returns true the objective is expected to return, and `optimize` records
+    /// the trial as `Pruned`.
+    pub fn should_prune(&mut self) -> Result<bool> {
+        let trial = self
+            .study
+            .storage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_trial(self.trial_id)?;
+        self.pruned = self.study.pruner.prune(&self.study, &trial)?;
+        Ok(self.pruned)
+    }
+}
+
+/// Seed for sampling `name` in a trial seeded with `seed`, mixing in the
+/// FNV-1a hash of the name so the value is stable across Rust versions.
+fn param_seed(seed: u64, name: &str) -> u64 {
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    seed ^ hash
+}
+
+fn check_bounds(name: &str, low: f64, high: f64) -> Result<()> {
+    match low.partial_cmp(&high) {
+        // Infinite bounds would make the samplers draw from an unbounded range.
+        Some(Ordering::Less) | Some(Ordering::Equal) if low.is_finite() && high.is_finite() => {
+            Ok(())
+        }
+        // `None` means one of the bounds is NaN.
+        _ => Err(RustunaError::InvalidBounds {
+            name: name.to_string(),
+            low,
+            high,
+        }
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samplers::RandomSampler;
+    use crate::storages::InMemoryStorage;
+    use crate::study::{create_study, StudyDirection};
+
+    fn study() -> Study {
+        create_study(
+            InMemoryStorage::new(),
+            RandomSampler::new(),
+            StudyDirection::Minimize,
+        )
+    }
+
+    fn intermediate_values(trial: &Trial) -> Vec<(usize, f64)> {
+        let storage = trial.study.storage.lock().unwrap();
+        let stored = storage.get_trial(trial.trial_id).unwrap();
+        stored.intermediate_values.into_iter().collect()
+    }
+
+    #[test]
+    fn report_overwrites_a_repeated_step() {
+        let mut study = study();
+        study.set_report_policy(ReportPolicy::Overwrite);
+        let mut trial = study.ask().unwrap();
+        trial.report(1.0, 0).unwrap();
+        trial.report(2.0, 1).unwrap();
+        trial.report(3.0, 1).unwrap();
+        trial.report(4.0, 0).unwrap();
+        assert_eq!(intermediate_values(&trial), vec![(0, 4.0), (1, 3.0)]);
+    }
+
+    #[test]
+    fn report_rejects_a_step_not_after_the_last() {
+        let mut study = study();
+        study.set_report_policy(ReportPolicy::Reject);
+        let mut trial = study.ask().unwrap();
+        trial.report(1.0, 0).unwrap();
+        trial.report(2.0, 2).unwrap();
+        assert!(trial.report(3.0, 2).is_err());
+        assert!(trial.report(3.0, 1).is_err());
+        trial.report(3.0, 3).unwrap();
+        assert_eq!(
+            intermediate_values(&trial),
+            vec![(0, 1.0), (2, 2.0), (3, 3.0)]
+        );
+    }
+
+    #[test]
+    fn zero_width_ranges_suggest_the_bound() {
+        let study = study();
+        let mut trial = study.ask().unwrap();
+        assert_eq!(trial.suggest_uniform("x", 5.0, 5.0).unwrap(), 5.0);
+        assert_eq!(trial.suggest_log("y", 2.0, 2.0).unwrap(), 2.0);
+        let params = trial.params().unwrap();
+        assert_eq!(params["x"], ExternalRepr::Float(5.0));
+        assert_eq!(params["y"], ExternalRepr::Float(2.0));
+    }
+
+    #[test]
+    fn json_round_trip_keeps_params_and_distributions() {
+        let study = create_study(
+            InMemoryStorage::new(),
+            RandomSampler::from_seed(3),
+            StudyDirection::Minimize,
+        );
+        // A value whose shortest JSON form only reads back exactly with
+        // serde_json's `float_roundtrip`.
+        let x = 0.935_719_537_196_776_1;
+        let mut enqueued = HashMap::new();
+        enqueued.insert("x".to_string(), ExternalRepr::Float(x));
+        study.enqueue_trial(enqueued, false).unwrap();
+        let mut trial = study.ask().unwrap();
+        trial.suggest_uniform("x", -1.0, 1.0).unwrap();
+        trial.suggest_log("lr", 1e-5, 1e-1).unwrap();
+        trial.suggest_discrete_uniform("d", 0.0, 1.0, 0.25).unwrap();
+        trial.suggest_int_step("n", 0, 10, 2).unwrap();
+        trial.suggest_bool("b").unwrap();
+        let choices = vec![
+            ExternalRepr::Str("a".to_string()),
+            ExternalRepr::Int(1),
+            ExternalRepr::Float(0.5),
+        ];
+        trial.suggest_categorical("c", choices).unwrap();
+        trial.report(0.5, 1).unwrap();
+        let trial_id = trial.trial_id();
+        trial.complete(0.25).unwrap();
+
+        let original = study.storage.lock().unwrap().get_trial(trial_id).unwrap();
+        let json = serde_json::to_string(&original).unwrap();
+        let loaded: FrozenTrial = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.internal_params, original.internal_params);
+        assert_eq!(loaded.distributions, original.distributions);
+        assert_eq!(loaded.params().unwrap(), original.params().unwrap());
+        assert_eq!(loaded.internal_params["x"], x);
+        assert_eq!(loaded, original);
+    }
+
+    #[test]
+    fn suggest_on_a_finished_trial_errors() {
+        let study = study();
+        let mut trial = study.ask().unwrap();
+        trial.suggest_int("n", 0, 10).unwrap();
+        study
+            .storage
+            .lock()
+            .unwrap()
+            .set_trial_state(trial.trial_id, FrozenTrialState::Completed)
+            .unwrap();
+        let err = trial.suggest_uniform("x", 0.0, 1.0).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot update finished trials");
+        assert!(trial.suggest_int("m", 0, 10).is_err());
+        assert!(trial.suggest_bool("b").is_err());
+        assert!(!trial.params().unwrap().contains_key("x"));
+    }
+
+    #[test]
+    fn unknown_categorical_values_are_errors() {
+        let study = study();
+        let mut enqueued = HashMap::new();
+        enqueued.insert("c".to_string(), ExternalRepr::Int(3));
+        study.enqueue_trial(enqueued, false).unwrap();
+        let mut trial = study.ask().unwrap();
+        let choices = || vec![ExternalRepr::Int(1), ExternalRepr::Int(2)];
+        let err = trial.suggest_categorical("c", choices()).unwrap_err();
+        match err.downcast_ref::<RustunaError>() {
+            Some(RustunaError::UnknownCategory { name, value, .. }) => {
+                assert_eq!(name, "c");
+                assert_eq!(value, &ExternalRepr::Int(3));
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+        let err = trial
+            .suggest_weighted_categorical("w", choices(), vec![1.0])
+            .unwrap_err();
+        assert!(err.to_string().contains("param w"), "{}", err);
+        assert!(trial.params().unwrap().is_empty());
+    }
+
+    #[test]
+    fn non_finite_bounds_are_invalid() {
+        let study = study();
+        let mut trial = study.ask().unwrap();
+        let bounds = [
+            (f64::NEG_INFINITY, f64::INFINITY),
+            (0.0, f64::INFINITY),
+            (f64::NAN, 1.0),
+            (2.0, 1.0),
+        ];
+        for &(low, high) in bounds.iter() {
+            let err = trial.suggest_uniform("x", low, high).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<RustunaError>(),
+                Some(RustunaError::InvalidBounds { .. })
+            ));
+            assert!(trial.suggest_float("y", low, high, None).is_err());
+        }
+        assert!(trial.suggest_log("z", 1.0, f64::INFINITY).is_err());
+        assert!(trial.params().unwrap().is_empty());
+    }
+}